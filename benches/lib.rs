@@ -40,6 +40,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         .cloned()
         .collect(),
         functions: functions1,
+        ..Default::default()
     };
 
     let mut functions2: FunctionMap = FxHashMap::default();
@@ -71,9 +72,16 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         .cloned()
         .collect(),
         functions: functions2,
+        ..Default::default()
     };
 
-    b.iter(|| black_box(grcov::merge_results(&mut result, result2.clone())));
+    b.iter(|| {
+        black_box(grcov::merge_results(
+            &mut result,
+            result2.clone(),
+            grcov::MergeStrategy::Sum,
+        ))
+    });
 }
 
 #[bench]
@@ -82,6 +90,7 @@ fn bench_lib_consumer(b: &mut Bencher) {
     let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
         FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
     ));
+    let parse_errors: Arc<SyncParseErrors> = Arc::new(Mutex::new(Vec::new()));
     let (sender, receiver) = unbounded();
     let working_dir = PathBuf::from("");
     let gcno_buf: Vec<u8> = vec![
@@ -100,6 +109,7 @@ fn bench_lib_consumer(b: &mut Bencher) {
         for i in 0..num_threads {
             let receiver = receiver.clone();
             let result_map = Arc::clone(&result_map);
+            let parse_errors = Arc::clone(&parse_errors);
             let working_dir = working_dir.clone();
 
             let t = thread::Builder::new()
@@ -107,12 +117,10 @@ fn bench_lib_consumer(b: &mut Bencher) {
                 .spawn(move || {
                     consumer(
                         &working_dir,
-                        None,
                         &result_map,
                         receiver,
-                        false,
-                        false,
-                        None,
+                        &parse_errors,
+                        &GrcovConfig::default(),
                     );
                 })
                 .unwrap();
@@ -130,6 +138,7 @@ fn bench_lib_consumer(b: &mut Bencher) {
                         gcda_buf: Vec::new(),
                     }),
                     name: "".to_string(),
+                    archive: None,
                 }))
                 .unwrap();
         }
@@ -3,8 +3,8 @@
 extern crate test;
 
 use grcov::{
-    output_activedata_etl, output_covdir, output_lcov, CovResult, Function, FunctionMap,
-    ResultTuple,
+    output_activedata_etl, output_covdir, output_lcov, CovResult, Demangler, Function, FunctionMap,
+    ReportMetadata, ResultTuple,
 };
 use rustc_hash::FxHashMap;
 use std::path::PathBuf;
@@ -39,6 +39,7 @@ fn generate_cov_result_iter() -> Vec<ResultTuple> {
                         functions
                     },
                     lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
+                    ..Default::default()
                 },
             )
         })
@@ -51,6 +52,7 @@ fn bench_output_activedata_etl(b: &mut Bencher) {
         black_box(output_activedata_etl(
             &generate_cov_result_iter(),
             Some(&dir.path().join("temp")),
+            Demangler::None,
             false,
         ))
     });
@@ -64,7 +66,8 @@ fn bench_output_covdir(b: &mut Bencher) {
             &generate_cov_result_iter(),
             Some(&dir.path().join("temp")),
             2,
-        ));
+            &ReportMetadata::default(),
+        ))
     });
 }
 
@@ -75,7 +78,8 @@ fn bench_output_lcov(b: &mut Bencher) {
         black_box(output_lcov(
             &generate_cov_result_iter(),
             Some(&dir.path().join("temp")),
+            Demangler::None,
             false,
-        ));
+        ))
     });
 }
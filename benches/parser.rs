@@ -11,14 +11,21 @@ use test::{black_box, Bencher};
 fn bench_parser_lcov(b: &mut Bencher) {
     b.iter(|| {
         let file = std::fs::read("./test/prova.info").expect("Failed to open lcov file");
-        black_box(grcov::parse_lcov(file, true).unwrap());
+        black_box(grcov::parse_lcov(file, true, false, false).unwrap());
     });
 }
 
 #[bench]
 fn bench_parser_gcov(b: &mut Bencher) {
     let path = Path::new("./test/negative_counts.gcov");
-    b.iter(|| black_box(grcov::parse_gcov(path)));
+    b.iter(|| black_box(grcov::parse_gcov(path, false)));
+}
+
+#[bench]
+fn bench_parser_gcov_gz(b: &mut Bencher) {
+    let buffer = std::fs::read("./test/mozillavpn_serverconnection.gcno.gcov.json.gz")
+        .expect("Failed to open gcov JSON file");
+    b.iter(|| black_box(grcov::parse_gcov_gz_buf(&buffer, false, false).unwrap()));
 }
 
 #[bench]
@@ -0,0 +1,139 @@
+use rustc_hash::FxHashMap;
+
+use crate::defs::*;
+
+/// A drop of the line coverage of a file (or of the total, when `path` is `None`) versus the
+/// baseline, as percentages.
+#[derive(Debug, PartialEq)]
+pub struct CoverageDecrease {
+    pub path: Option<String>,
+    pub baseline: f64,
+    pub current: f64,
+}
+
+/// Compares the line coverage of the total and of each file of `results` with the one of the
+/// `baseline` (e.g. parsed from the lcov output of a previous run), returning the ones which
+/// dropped by more than `tolerance` percentage points. The files are matched by their relative
+/// path; the ones missing on either side, without lines or informational (as marked by
+/// `mark_informational` on both sides) are ignored.
+pub fn get_coverage_decreases(
+    results: &[ResultTuple],
+    baseline: &[(String, CovResult)],
+    tolerance: f64,
+) -> Vec<CoverageDecrease> {
+    let is_decrease = |baseline: f64, current: f64| baseline - current > tolerance;
+    let mut decreases = Vec::new();
+
+    let baseline_total =
+        CoverageTotals::counted(baseline.iter().map(|(_, result)| result)).line_percentage();
+    let current_total =
+        CoverageTotals::counted(results.iter().map(|(_, _, result)| result)).line_percentage();
+    if let (Some(baseline), Some(current)) = (baseline_total, current_total) {
+        if is_decrease(baseline, current) {
+            decreases.push(CoverageDecrease {
+                path: None,
+                baseline,
+                current,
+            });
+        }
+    }
+
+    let baseline: FxHashMap<String, &CovResult> = baseline
+        .iter()
+        .map(|(path, result)| (path.replace('\\', "/"), result))
+        .collect();
+    let mut results: Vec<&ResultTuple> = results.iter().collect();
+    results.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+    for (_, rel_path, result) in results {
+        if result.informational {
+            continue;
+        }
+        let path = rel_path.to_string_lossy().replace('\\', "/");
+        let Some(baseline_result) = baseline.get(&path) else {
+            continue;
+        };
+        let baseline = CoverageTotals::of(baseline_result).line_percentage();
+        let current = CoverageTotals::of(result).line_percentage();
+        if let (Some(baseline), Some(current)) = (baseline, current) {
+            if is_decrease(baseline, current) {
+                decreases.push(CoverageDecrease {
+                    path: Some(path),
+                    baseline,
+                    current,
+                });
+            }
+        }
+    }
+
+    decreases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn result(lines: &[(u32, u64)]) -> CovResult {
+        CovResult {
+            lines: lines.iter().cloned().collect(),
+            branches: BTreeMap::new(),
+            functions: FxHashMap::default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_get_coverage_decreases() {
+        let baseline = vec![
+            ("a.rs".to_string(), result(&[(1, 1), (2, 1)])),
+            ("b.rs".to_string(), result(&[(1, 1), (2, 0)])),
+            ("removed.rs".to_string(), result(&[(1, 1)])),
+        ];
+        let results = vec![
+            (
+                PathBuf::from("/src/a.rs"),
+                PathBuf::from("a.rs"),
+                result(&[(1, 1), (2, 0)]),
+            ),
+            (
+                PathBuf::from("/src/b.rs"),
+                PathBuf::from("b.rs"),
+                result(&[(1, 1), (2, 1)]),
+            ),
+            (
+                PathBuf::from("/src/new.rs"),
+                PathBuf::from("new.rs"),
+                result(&[(1, 0)]),
+            ),
+        ];
+
+        assert_eq!(
+            get_coverage_decreases(&results, &baseline, 0.0),
+            vec![
+                CoverageDecrease {
+                    path: None,
+                    baseline: 80.0,
+                    current: 60.0,
+                },
+                CoverageDecrease {
+                    path: Some("a.rs".to_string()),
+                    baseline: 100.0,
+                    current: 50.0,
+                },
+            ]
+        );
+
+        let decreases = get_coverage_decreases(&results, &baseline, 20.0);
+        assert_eq!(decreases.len(), 1);
+        assert_eq!(decreases[0].path.as_deref(), Some("a.rs"));
+
+        assert!(get_coverage_decreases(&results, &baseline, 50.0).is_empty());
+
+        // The informational files are left out of the totals, and aren't compared.
+        let (mut results, mut baseline) = (results, baseline);
+        results[0].2.informational = true;
+        baseline[0].1.informational = true;
+        assert!(get_coverage_decreases(&results, &baseline, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use log::warn;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde_json::{json, Value};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::defs::*;
+use crate::llvm_tools::run;
+use crate::output::{get_missed_ranges, get_target_output_writable};
+
+/// The author and commit which last touched a line.
+#[derive(Debug, PartialEq, Eq)]
+struct BlameLine {
+    line: u32,
+    author: String,
+    commit: String,
+}
+
+/// Parses the output of `git blame --porcelain`, in which the details of a commit are only given
+/// the first time it appears.
+fn parse_blame_porcelain(output: &str) -> Vec<BlameLine> {
+    let mut authors: FxHashMap<&str, &str> = FxHashMap::default();
+    let mut current: Option<(&str, u32)> = None;
+    let mut lines = Vec::new();
+
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            // The content of the line ends the entry.
+            if let Some((commit, final_line)) = current.take() {
+                lines.push(BlameLine {
+                    line: final_line,
+                    author: authors.get(commit).copied().unwrap_or_default().to_string(),
+                    commit: commit.to_string(),
+                });
+            }
+        } else if let Some(author) = line.strip_prefix("author ") {
+            if let Some((commit, _)) = current {
+                authors.insert(commit, author);
+            }
+        } else if current.is_none() {
+            // A header: "<commit> <original line> <final line> [<number of lines>]".
+            let mut parts = line.split(' ');
+            let commit = parts.next().unwrap_or_default();
+            let final_line = parts.nth(1).and_then(|l| l.parse().ok());
+            if commit.len() == 40 && commit.bytes().all(|b| b.is_ascii_hexdigit()) {
+                current = final_line.map(|final_line| (commit, final_line));
+            }
+        }
+    }
+
+    lines
+}
+
+fn blame_ranges(
+    repo_dir: &Path,
+    file: &Path,
+    ranges: &[(u32, u32)],
+) -> Result<Vec<BlameLine>, String> {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|(start, end)| format!("{},{}", start, end))
+        .collect();
+    let mut args = vec![
+        "-C".as_ref(),
+        repo_dir.as_os_str(),
+        "blame".as_ref(),
+        "--porcelain".as_ref(),
+    ];
+    for range in &ranges {
+        args.push("-L".as_ref());
+        args.push(range.as_ref());
+    }
+    args.push("--".as_ref());
+    args.push(file.as_os_str());
+
+    let output = run("git", &args)?;
+    Ok(parse_blame_porcelain(&String::from_utf8_lossy(&output)))
+}
+
+/// Outputs the author and commit which last touched each uncovered line, as found by running
+/// `git blame` once per file in `repo_dir`.
+pub fn output_blame(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    repo_dir: &Path,
+) -> io::Result<()> {
+    let blamed: Vec<Vec<Value>> = results
+        .par_iter()
+        .map(|(abs_path, rel_path, result)| {
+            let ranges = get_missed_ranges(&result.lines);
+            if ranges.is_empty() {
+                return Vec::new();
+            }
+
+            let lines = match blame_ranges(repo_dir, abs_path, &ranges) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    warn!("Failed to blame {}: {}", rel_path.display(), e);
+                    return Vec::new();
+                }
+            };
+            lines
+                .into_iter()
+                // The ranges also contain the lines without coverage data between the uncovered
+                // ones.
+                .filter(|blame_line| result.lines.get(&blame_line.line) == Some(&0))
+                .map(|blame_line| {
+                    json!({
+                        "path": rel_path,
+                        "line": blame_line.line,
+                        "author": blame_line.author,
+                        "commit": blame_line.commit,
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let blamed: Vec<Value> = blamed.into_iter().flatten().collect();
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &blamed)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blame_porcelain() {
+        let output = "\
+1111111111111111111111111111111111111111 2 2 2
+author Alice
+author-mail <alice@example.com>
+summary First
+filename src/main.rs
+\tfn main() {
+1111111111111111111111111111111111111111 3 3
+\t    foo();
+2222222222222222222222222222222222222222 4 5 1
+author Bob
+summary Second
+filename src/main.rs
+\tauthor not_a_header();
+";
+        assert_eq!(
+            parse_blame_porcelain(output),
+            vec![
+                BlameLine {
+                    line: 2,
+                    author: "Alice".to_string(),
+                    commit: "1111111111111111111111111111111111111111".to_string(),
+                },
+                BlameLine {
+                    line: 3,
+                    author: "Alice".to_string(),
+                    commit: "1111111111111111111111111111111111111111".to_string(),
+                },
+                BlameLine {
+                    line: 5,
+                    author: "Bob".to_string(),
+                    commit: "2222222222222222222222222222222222222222".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use log::warn;
+use md5::{Digest, Md5};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::defs::*;
+use crate::gcov::get_gcov_version;
+
+/// An on-disk cache of the results of the gcno/gcda pairs, keyed by a hash of their content, so
+/// that the unchanged ones don't need to be parsed again by the following runs.
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn get_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<(String, CovResult)>> {
+        let file = File::open(self.get_path(key)).ok()?;
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(results) => Some(results),
+            Err(e) => {
+                warn!("Ignoring invalid cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, results: &[(String, CovResult)]) {
+        // Write to a temporary file first, so that concurrent readers never see a partial entry.
+        let res = tempfile::NamedTempFile::new_in(&self.dir)
+            .and_then(|mut file| {
+                let mut writer = BufWriter::new(&mut file);
+                serde_json::to_writer(&mut writer, results)?;
+                writer.flush()?;
+                drop(writer);
+                Ok(file)
+            })
+            .and_then(|file| file.persist(self.get_path(key)).map_err(|e| e.error));
+        if let Err(e) = res {
+            warn!("Failed to write cache entry {}: {}", key, e);
+        }
+    }
+}
+
+fn hash_parts(parts: &[&[u8]]) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Returns the key identifying the results of a work item in the cache, or `None` if the item
+/// can't be cached (i.e. it isn't a gcno/gcda pair).
+pub fn get_cache_key(
+    work_item: &WorkItem,
+    branch_enabled: bool,
+    guess_directory: bool,
+    exclude_branch_throw: bool,
+    merge_branches_by_id: bool,
+    attribute_headers_to_includers: bool,
+) -> Option<String> {
+    if work_item.format != ItemFormat::Gcno {
+        return None;
+    }
+
+    let flags = [
+        u8::from(branch_enabled),
+        u8::from(guess_directory),
+        u8::from(exclude_branch_throw),
+        u8::from(merge_branches_by_id),
+        u8::from(attribute_headers_to_includers),
+    ];
+    match &work_item.item {
+        ItemType::Path((stem, gcno_path)) => {
+            let gcno = fs::read(gcno_path).ok()?;
+            let gcda = fs::read(gcno_path.with_extension("gcda")).unwrap_or_default();
+            let gcov_version = get_gcov_version().to_string();
+            Some(hash_parts(&[
+                b"gcc",
+                &flags,
+                stem.as_bytes(),
+                gcov_version.as_bytes(),
+                &gcno,
+                &gcda,
+            ]))
+        }
+        ItemType::Buffers(buffers) => {
+            let mut parts: Vec<&[u8]> =
+                vec![b"llvm", &flags, buffers.stem.as_bytes(), &buffers.gcno_buf];
+            parts.extend(buffers.gcda_buf.iter().map(Vec::as_slice));
+            Some(hash_parts(&parts))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffers_item(gcda_buf: Vec<Vec<u8>>) -> WorkItem {
+        WorkItem {
+            format: ItemFormat::Gcno,
+            item: ItemType::Buffers(GcnoBuffers {
+                stem: "foo".to_string(),
+                gcno_buf: vec![1, 2, 3],
+                gcda_buf,
+            }),
+            name: "foo".to_string(),
+            archive: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let key = get_cache_key(
+            &buffers_item(vec![vec![4, 5]]),
+            false,
+            false,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            get_cache_key(
+                &buffers_item(vec![vec![4, 5]]),
+                false,
+                false,
+                false,
+                false,
+                false
+            )
+            .unwrap(),
+            key
+        );
+        assert_ne!(
+            get_cache_key(
+                &buffers_item(vec![vec![4, 6]]),
+                false,
+                false,
+                false,
+                false,
+                false
+            )
+            .unwrap(),
+            key
+        );
+        assert_ne!(
+            get_cache_key(
+                &buffers_item(vec![vec![4], vec![5]]),
+                false,
+                false,
+                false,
+                false,
+                false
+            )
+            .unwrap(),
+            key
+        );
+        assert_ne!(
+            get_cache_key(
+                &buffers_item(vec![vec![4, 5]]),
+                true,
+                false,
+                false,
+                false,
+                false
+            )
+            .unwrap(),
+            key
+        );
+
+        let item = WorkItem {
+            format: ItemFormat::Info,
+            item: ItemType::Content(vec![1, 2, 3]),
+            name: "foo.info".to_string(),
+            archive: None,
+        };
+        assert_eq!(
+            get_cache_key(&item, false, false, false, false, false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let cache = ResultCache::new(&tmp_dir.path().join("cache")).unwrap();
+        assert_eq!(cache.get("key"), None);
+
+        let mut functions = FunctionMap::default();
+        functions.insert(
+            "f".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+            },
+        );
+        let results = vec![(
+            "foo.c".to_string(),
+            CovResult {
+                lines: [(1, 2), (2, 0)].iter().cloned().collect(),
+                branches: [(2, vec![true, false])].iter().cloned().collect(),
+                functions,
+                line_functions: [(1, "f".to_string())].iter().cloned().collect(),
+                branch_ids: [(2, vec![(0, 0), (0, 1)])].iter().cloned().collect(),
+                line_tests: [(1, ["test_f".to_string()].iter().cloned().collect())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                line_archives: [(1, ["shard.zip".to_string()].iter().cloned().collect())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                informational: false,
+            },
+        )];
+        cache.put("key", &results);
+        assert_eq!(cache.get("key"), Some(results));
+
+        fs::write(tmp_dir.path().join("cache").join("bad.json"), "{").unwrap();
+        assert_eq!(cache.get("bad"), None);
+    }
+}
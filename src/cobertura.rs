@@ -7,25 +7,11 @@ use rustc_hash::FxHashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
     fmt::Display,
-    io::{BufWriter, Cursor, Write},
+    io::{self, BufWriter, Cursor, Write},
 };
 use std::{fmt::Formatter, path::Path};
-use symbolic_common::Name;
-use symbolic_demangle::{Demangle, DemangleOptions};
 
-use crate::output::get_target_output_writable;
-
-macro_rules! demangle {
-    ($name: expr, $demangle: expr, $options: expr) => {{
-        if $demangle {
-            Name::from($name)
-                .demangle($options)
-                .unwrap_or_else(|| $name.clone())
-        } else {
-            $name.clone()
-        }
-    }};
-}
+use crate::output::{get_target_output_writable, sorted_functions, Demangler};
 
 // http://cobertura.sourceforge.net/xml/coverage-04.dtd
 
@@ -65,8 +51,8 @@ impl CoverageStats {
         let lines_valid = lines.len() as f64;
 
         let branches: Vec<Vec<Condition>> = lines
-            .into_iter()
-            .filter_map(|(_, l)| match l {
+            .into_values()
+            .filter_map(|l| match l {
                 Line::Branch { conditions, .. } => Some(conditions),
                 Line::Plain { .. } => None,
             })
@@ -135,7 +121,16 @@ struct Package {
 
 impl Stats for Package {
     fn get_lines(&self) -> FxHashMap<u32, Line> {
-        self.classes.get_lines()
+        unimplemented!("does not make sense to ask Package for lines")
+    }
+
+    // The counts of the classes are added up, as lines of different classes can have the same
+    // number.
+    fn get_stats(&self) -> CoverageStats {
+        self.classes
+            .iter()
+            .map(|c| c.get_stats())
+            .fold(CoverageStats::default(), |acc, stats| acc + stats)
     }
 }
 
@@ -231,12 +226,7 @@ impl Display for ConditionType {
     }
 }
 
-fn get_coverage(
-    results: &[ResultTuple],
-    sources: Vec<String>,
-    demangle: bool,
-    demangle_options: DemangleOptions,
-) -> Coverage {
+fn get_coverage(results: &[ResultTuple], sources: Vec<String>, demangler: Demangler) -> Coverage {
     let packages: Vec<Package> = results
         .iter()
         .map(|(_, rel_path, result)| {
@@ -272,13 +262,13 @@ fn get_coverage(
                 }
             };
 
-            let methods: Vec<Method> = result
-                .functions
-                .iter()
+            let methods: Vec<Method> = sorted_functions(&result.functions)
+                .into_iter()
                 .map(|(name, function)| {
-                    let mut func_end = end;
+                    // The functions with an unknown start (0) have no known lines.
+                    let mut func_end = if function.start == 0 { 0 } else { end };
 
-                    for start in &start_indexes {
+                    for start in start_indexes.iter().filter(|_| function.start != 0) {
                         if *start > function.start {
                             func_end = *start;
                             break;
@@ -299,7 +289,7 @@ fn get_coverage(
                         .collect();
 
                     Method {
-                        name: demangle!(name, demangle, demangle_options),
+                        name: demangler.demangle(name).unwrap_or_else(|| name.clone()),
                         signature: String::new(),
                         lines,
                     }
@@ -332,15 +322,14 @@ pub fn output_cobertura(
     source_dir: Option<&Path>,
     results: &[ResultTuple],
     output_file: Option<&Path>,
-    demangle: bool,
+    demangler: Demangler,
     pretty: bool,
-) {
-    let demangle_options = DemangleOptions::name_only();
+) -> io::Result<()> {
     let sources = vec![source_dir
         .unwrap_or_else(|| Path::new("."))
         .display()
         .to_string()];
-    let coverage = get_coverage(results, sources, demangle, demangle_options);
+    let coverage = get_coverage(results, sources, demangler);
 
     let mut writer = if pretty {
         Writer::new_with_indent(Cursor::new(vec![]), b' ', 4)
@@ -497,8 +486,9 @@ pub fn output_cobertura(
         .unwrap();
 
     let result = writer.into_inner().into_inner();
-    let mut file = BufWriter::new(get_target_output_writable(output_file));
-    file.write_all(&result).unwrap();
+    let mut file = BufWriter::new(get_target_output_writable(output_file)?);
+    file.write_all(&result)?;
+    file.flush()
 }
 
 fn write_lines(writer: &mut Writer<Cursor<Vec<u8>>>, lines: &[Line]) {
@@ -619,6 +609,7 @@ mod tests {
                     );
                     map
                 },
+                ..Default::default()
             },
             Result::Test => CovResult {
                 /* main.rs
@@ -696,6 +687,7 @@ mod tests {
                     );
                     map
                 },
+                ..Default::default()
             },
         }
     }
@@ -721,7 +713,7 @@ mod tests {
         )];
 
         for pretty in [false, true] {
-            output_cobertura(None, &results, Some(&file_path), true, pretty);
+            output_cobertura(None, &results, Some(&file_path), Demangler::Auto, pretty).unwrap();
 
             let results = read_file(&file_path);
 
@@ -744,6 +736,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cobertura_hit_counts() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_hit_counts.xml");
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            CovResult {
+                lines: [(1, 42), (2, 7), (3, 0)].iter().cloned().collect(),
+                branches: [(2, vec![true, false])].iter().cloned().collect(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        output_cobertura(None, &results, Some(&file_path), Demangler::Auto, false).unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains(r#"line number="1" hits="42"/>"#));
+        assert!(results.contains(r#"line number="2" hits="7" branch="true""#));
+        assert!(results.contains(r#"line number="3" hits="0"/>"#));
+    }
+
     #[test]
     fn test_cobertura_double_lines() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -756,7 +772,14 @@ mod tests {
             coverage_result(Result::Test),
         )];
 
-        output_cobertura(None, &results, Some(file_path.as_ref()), true, true);
+        output_cobertura(
+            None,
+            &results,
+            Some(file_path.as_ref()),
+            Demangler::Auto,
+            true,
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -795,7 +818,14 @@ mod tests {
             ),
         ];
 
-        output_cobertura(None, &results, Some(file_path.as_ref()), true, true);
+        output_cobertura(
+            None,
+            &results,
+            Some(file_path.as_ref()),
+            Demangler::Auto,
+            true,
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -815,6 +845,111 @@ mod tests {
         assert!(results.contains(r#"branch-rate="0.3333333333333333""#));
     }
 
+    #[test]
+    fn test_cobertura_rates() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_rates.xml");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/test.rs"),
+                PathBuf::from("src/test.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+        output_cobertura(
+            None,
+            &results,
+            Some(file_path.as_ref()),
+            Demangler::Auto,
+            true,
+        )
+        .unwrap();
+
+        // The rates of each class, package and of the whole report have to match the lines of
+        // their classes, without the ones of the methods which are repeated.
+        let xml = read_file(&file_path);
+        let mut reader = quick_xml::Reader::from_str(&xml);
+        let attribute = |e: &BytesStart, name: &str| -> f64 {
+            e.try_get_attribute(name)
+                .unwrap()
+                .unwrap()
+                .unescape_value()
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        let check = |rates: (f64, f64), counts: [f64; 4]| {
+            let [lines_covered, lines_valid, branches_covered, branches_valid] = counts;
+            assert!((rates.0 - lines_covered / lines_valid).abs() < f64::EPSILON);
+            assert!((rates.1 - branches_covered / branches_valid).abs() < f64::EPSILON);
+        };
+        // The rates and the counts of the current class, package and of the report.
+        let mut rates = [(0.0, 0.0); 3];
+        let mut counts = [[0.0; 4]; 3];
+        let mut in_method = false;
+        let mut checked = 0;
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                    tag @ (b"class" | b"package" | b"coverage") => {
+                        let level = match tag {
+                            b"class" => 0,
+                            b"package" => 1,
+                            _ => 2,
+                        };
+                        rates[level] = (attribute(&e, "line-rate"), attribute(&e, "branch-rate"));
+                        counts[level] = [0.0; 4];
+                    }
+                    b"method" => in_method = true,
+                    b"line" if !in_method => {
+                        let covered = if attribute(&e, "hits") > 0.0 {
+                            1.0
+                        } else {
+                            0.0
+                        };
+                        for count in counts.iter_mut() {
+                            count[0] += covered;
+                            count[1] += 1.0;
+                        }
+                    }
+                    b"condition" if !in_method => {
+                        let coverage = attribute(&e, "coverage");
+                        for count in counts.iter_mut() {
+                            count[2] += coverage;
+                            count[3] += 1.0;
+                        }
+                    }
+                    _ => {}
+                },
+                Event::End(e) => match e.name().as_ref() {
+                    b"method" => in_method = false,
+                    b"class" => {
+                        check(rates[0], counts[0]);
+                        checked += 1;
+                    }
+                    b"package" => {
+                        check(rates[1], counts[1]);
+                        checked += 1;
+                    }
+                    b"coverage" => {
+                        check(rates[2], counts[2]);
+                        checked += 1;
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        assert_eq!(checked, 5);
+    }
+
     #[test]
     fn test_cobertura_source_root_none() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -827,7 +962,7 @@ mod tests {
             CovResult::default(),
         )];
 
-        output_cobertura(None, &results, Some(&file_path), true, true);
+        output_cobertura(None, &results, Some(&file_path), Demangler::Auto, true).unwrap();
 
         let results = read_file(&file_path);
 
@@ -851,13 +986,44 @@ mod tests {
             Some(Path::new("src")),
             &results,
             Some(&file_path),
+            Demangler::Auto,
             true,
-            true,
-        );
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
         assert!(results.contains(r#"<source>src</source>"#));
         assert!(results.contains(r#"package name="main.rs""#));
     }
+
+    #[test]
+    fn test_cobertura_sources_absolute_source_root() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura.xml");
+        let source_root = tmp_dir.path().join("project");
+
+        let results = vec![(
+            source_root.join("src").join("main.rs"),
+            PathBuf::from("src/main.rs"),
+            CovResult::default(),
+        )];
+
+        output_cobertura(
+            Some(&source_root),
+            &results,
+            Some(&file_path),
+            Demangler::Auto,
+            false,
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+
+        assert!(results.contains(&format!(
+            "<sources><source>{}</source></sources>",
+            source_root.display()
+        )));
+        assert!(results.contains(r#"filename="src/main.rs""#));
+    }
 }
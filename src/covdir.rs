@@ -1,8 +1,27 @@
 use serde_json::{json, map::Map};
 use std::collections::BTreeMap;
+use std::path::Path;
 
 pub use crate::defs::*;
 
+/// Splits `path` into the names of its directories and its file name, on both `/` and `\` so
+/// that the keys of the report are the same on all the platforms. The root of an absolute path is
+/// a `/` directory.
+pub fn split_path(path: &Path) -> (Vec<String>, String) {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut dirs: Vec<String> = Vec::new();
+    if path.starts_with('/') {
+        dirs.push("/".to_string());
+    }
+    dirs.extend(
+        path.split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .map(|name| name.to_string()),
+    );
+    let file_name = dirs.pop().unwrap_or_default();
+    (dirs, file_name)
+}
+
 impl CDStats {
     pub fn new(total: usize, covered: usize, precision: usize) -> Self {
         let missed = total - covered;
@@ -44,12 +63,18 @@ impl CDStats {
 }
 
 impl CDFileStats {
-    pub fn new(name: String, coverage: BTreeMap<u32, u64>, precision: usize) -> Self {
+    pub fn new(
+        name: String,
+        coverage: BTreeMap<u32, u64>,
+        precision: usize,
+        informational: bool,
+    ) -> Self {
         let (total, covered, lines) = Self::get_coverage(coverage);
         Self {
             name,
             stats: CDStats::new(total, covered, precision),
             coverage: lines,
+            informational,
         }
     }
 
@@ -90,7 +115,7 @@ impl CDDirStats {
     }
 
     pub fn set_stats(&mut self, precision: usize) {
-        for file in self.files.iter() {
+        for file in self.files.iter().filter(|file| !file.informational) {
             self.stats.add(&file.stats);
         }
         for dir in self.dirs.iter() {
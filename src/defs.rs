@@ -2,23 +2,51 @@ use crossbeam_channel::{Receiver, Sender};
 use rustc_hash::FxHashMap;
 use serde::ser::{Serialize, Serializer};
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Mutex;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Function {
     pub start: u32,
     pub executed: bool,
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// The title and the metadata (e.g. a build id) of a report, shown in the html output and added to
+/// the covdir and summary outputs.
+#[derive(Debug, Default, Clone)]
+pub struct ReportMetadata {
+    pub title: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct CovResult {
     pub lines: BTreeMap<u32, u64>,
     pub branches: BTreeMap<u32, Vec<bool>>,
     pub functions: FunctionMap,
+    /// The function (as named in `functions`) each line belongs to, when known.
+    pub line_functions: BTreeMap<u32, String>,
+    /// The (block, branch) numbers of the branches of each line, in the same order as in
+    /// `branches`, when they are kept (see `GrcovConfig::merge_branches_by_id`) and known (from
+    /// the BRDA records of lcov files or the blocks of gcov JSON reports). The branches of
+    /// different inputs are matched by them when merging, rather than by their position.
+    #[serde(default)]
+    pub branch_ids: BTreeMap<u32, Vec<(u32, u32)>>,
+    /// The names of the tests which executed each line, when known (e.g. from the profraws of
+    /// each test with `--per-test-profraw`).
+    #[serde(default)]
+    pub line_tests: LineTags,
+    /// The names of the input archives whose coverage data executed each line, with
+    /// `--per-archive`.
+    #[serde(default)]
+    pub line_archives: LineTags,
+    /// Whether the file is informational (see `mark_informational`): it is listed in the outputs,
+    /// but not counted in their totals.
+    #[serde(default)]
+    pub informational: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -27,6 +55,21 @@ pub enum ItemFormat {
     Profraw,
     Info,
     JacocoXml,
+    Gcov,
+    PythonCoverage,
+}
+
+impl Display for ItemFormat {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Gcno => write!(f, "gcno"),
+            Self::Profraw => write!(f, "profraw"),
+            Self::Info => write!(f, "info"),
+            Self::JacocoXml => write!(f, "jacoco-xml"),
+            Self::Gcov => write!(f, "gcov"),
+            Self::PythonCoverage => write!(f, "python-coverage"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -49,10 +92,15 @@ pub struct WorkItem {
     pub format: ItemFormat,
     pub item: ItemType,
     pub name: String,
+    /// The name of the archive the item comes from, with `--per-archive`.
+    pub archive: Option<String>,
 }
 
 pub type FunctionMap = FxHashMap<String, Function>;
 
+/// The names (e.g. of tests or input archives) each line is tagged with.
+pub type LineTags = BTreeMap<u32, BTreeSet<String>>;
+
 pub type JobReceiver = Receiver<Option<WorkItem>>;
 pub type JobSender = Sender<Option<WorkItem>>;
 
@@ -60,6 +108,26 @@ pub type CovResultMap = FxHashMap<String, CovResult>;
 pub type SyncCovResultMap = Mutex<CovResultMap>;
 pub type ResultTuple = (PathBuf, PathBuf, CovResult);
 
+/// How the execution counts of a line are combined when merging the results of several runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The counts are added up.
+    #[default]
+    Sum,
+    /// The highest count is kept, i.e. a line is covered if it was covered by any run.
+    Max,
+}
+
+/// An input which couldn't be parsed, and so is missing from the results.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ParseError {
+    pub file: String,
+    pub format: String,
+    pub error: String,
+}
+
+pub type SyncParseErrors = Mutex<Vec<ParseError>>;
+
 #[derive(Debug, Default)]
 pub struct CDStats {
     pub total: usize,
@@ -73,6 +141,8 @@ pub struct CDFileStats {
     pub name: String,
     pub stats: CDStats,
     pub coverage: Vec<i64>,
+    /// Whether the file is informational, i.e. not counted in the stats of its directories.
+    pub informational: bool,
 }
 
 #[derive(Debug, Default)]
@@ -90,6 +160,75 @@ pub struct HtmlItem {
     pub result: CovResult,
 }
 
+/// The numbers of covered and total lines, branches and functions of one or more files, from which
+/// the outputs compute their coverage percentages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoverageTotals {
+    pub covered_lines: usize,
+    pub total_lines: usize,
+    pub covered_branches: usize,
+    pub total_branches: usize,
+    pub covered_functions: usize,
+    pub total_functions: usize,
+}
+
+impl CoverageTotals {
+    pub fn of(result: &CovResult) -> Self {
+        let branches = result.branches.values().flatten();
+        Self {
+            covered_lines: result.lines.values().filter(|&&hits| hits > 0).count(),
+            total_lines: result.lines.len(),
+            covered_branches: branches.clone().filter(|&&taken| taken).count(),
+            total_branches: branches.count(),
+            covered_functions: result.functions.values().filter(|f| f.executed).count(),
+            total_functions: result.functions.len(),
+        }
+    }
+
+    /// Sums the totals of the files counted in the totals, i.e. the ones which aren't
+    /// informational.
+    pub fn counted<'a>(results: impl IntoIterator<Item = &'a CovResult>) -> Self {
+        Self::sum(results.into_iter().filter(|result| !result.informational))
+    }
+
+    pub fn sum<'a>(results: impl IntoIterator<Item = &'a CovResult>) -> Self {
+        results
+            .into_iter()
+            .fold(Self::default(), |mut totals, result| {
+                totals.add(&Self::of(result));
+                totals
+            })
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.covered_lines += other.covered_lines;
+        self.total_lines += other.total_lines;
+        self.covered_branches += other.covered_branches;
+        self.total_branches += other.total_branches;
+        self.covered_functions += other.covered_functions;
+        self.total_functions += other.total_functions;
+    }
+
+    pub fn line_percentage(&self) -> Option<f64> {
+        coverage_percentage(self.covered_lines, self.total_lines)
+    }
+
+    pub fn branch_percentage(&self) -> Option<f64> {
+        coverage_percentage(self.covered_branches, self.total_branches)
+    }
+}
+
+/// Returns the percentage of `covered` over `total`, or `None` when there's nothing to cover: the
+/// coverage of files without lines (or branches) isn't applicable, and the outputs which need a
+/// number count them as fully covered.
+pub fn coverage_percentage(covered: usize, total: usize) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(covered as f64 * 100.0 / total as f64)
+    }
+}
+
 #[derive(Clone, Debug, Default, serde::Serialize)]
 pub struct HtmlStats {
     pub total_lines: usize,
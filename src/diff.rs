@@ -0,0 +1,232 @@
+use rustc_hash::FxHashMap;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::defs::*;
+use crate::llvm_tools::run;
+
+/// The lines added or modified in each file, keyed by path.
+pub type ChangedLines = FxHashMap<PathBuf, BTreeSet<u32>>;
+
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+// Parses the old and new ranges of a hunk header, e.g. `@@ -10,2 +12,3 @@ fn foo()`.
+fn parse_hunk_header(line: &str) -> Option<((u32, u32), (u32, u32))> {
+    let mut ranges = line.strip_prefix("@@ ")?.split(' ');
+    let old = parse_range(ranges.next()?.strip_prefix('-')?)?;
+    let new = parse_range(ranges.next()?.strip_prefix('+')?)?;
+    Some((old, new))
+}
+
+/// Parses a unified diff without context lines (as produced by `git diff --unified=0`), returning
+/// the lines of the new version of each file which were added or modified. Deleted files are not
+/// part of the result.
+///
+/// The context lines of a diff with `-U1` or more would be counted as changed, and would throw off
+/// the number of lines of each hunk.
+fn parse_diff(diff: &str) -> ChangedLines {
+    let mut changed_lines = ChangedLines::default();
+    let mut current: Option<PathBuf> = None;
+    // The number of lines of the current hunk which are still to be skipped, so that added or
+    // removed lines are never mistaken for headers.
+    let mut hunk_lines = 0;
+
+    for line in diff.lines() {
+        if hunk_lines > 0 {
+            if !line.starts_with('\\') {
+                hunk_lines -= 1;
+            }
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            current = path
+                .strip_prefix("b/")
+                .map(|path| PathBuf::from(path.trim_end()));
+            if let Some(path) = &current {
+                changed_lines.entry(path.clone()).or_default();
+            }
+        } else if let Some(((_, old_count), (start, count))) = parse_hunk_header(line) {
+            hunk_lines = old_count + count;
+            if let Some(path) = &current {
+                changed_lines
+                    .get_mut(path)
+                    .unwrap()
+                    .extend(start..start + count);
+            }
+        }
+    }
+
+    changed_lines
+}
+
+/// Returns the lines changed in the working tree of the repository containing `repo_dir` since
+/// `git_ref`, with paths relative to `repo_dir`.
+pub fn get_changed_lines(repo_dir: &Path, git_ref: &str) -> Result<ChangedLines, String> {
+    let output = run(
+        "git",
+        &[
+            "-C".as_ref(),
+            repo_dir.as_ref(),
+            "-c".as_ref(),
+            "core.quotepath=off".as_ref(),
+            "diff".as_ref(),
+            "--unified=0".as_ref(),
+            "--no-color".as_ref(),
+            "--no-ext-diff".as_ref(),
+            "--relative".as_ref(),
+            git_ref.as_ref(),
+            "--".as_ref(),
+        ],
+    )?;
+    Ok(parse_diff(&String::from_utf8_lossy(&output)))
+}
+
+/// Only keeps the files which changed, and their lines, branches and functions which were added or
+/// modified.
+pub fn restrict_to_changed_lines(
+    results: Vec<ResultTuple>,
+    changed_lines: &ChangedLines,
+) -> Vec<ResultTuple> {
+    results
+        .into_iter()
+        .filter_map(|(abs_path, rel_path, mut result)| {
+            let lines = changed_lines.get(&rel_path)?;
+            result.lines.retain(|line, _| lines.contains(line));
+            result.branches.retain(|line, _| lines.contains(line));
+            result.branch_ids.retain(|line, _| lines.contains(line));
+            result
+                .functions
+                .retain(|_, function| lines.contains(&function.start));
+            result.line_functions.retain(|line, _| lines.contains(line));
+            result.line_tests.retain(|line, _| lines.contains(line));
+            result.line_archives.retain(|line, _| lines.contains(line));
+            Some((abs_path, rel_path, result))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diff() {
+        let diff = "\
+diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -3 +3 @@ fn main() {
+-    old();
++    new();
+@@ -10,0 +11,2 @@ fn main() {
++++ looks_like_a_header();
++    added();
+@@ -20,3 +22,0 @@ fn main() {
+-    removed();
+-    removed();
+-    removed();
+diff --git a/src/new.rs b/src/new.rs
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1,3 @@
++fn new() {
++    added();
++}
+diff --git a/src/deleted.rs b/src/deleted.rs
+deleted file mode 100644
+index 4444444..0000000
+--- a/src/deleted.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+-fn deleted() {
+-}
+";
+        let changed_lines = parse_diff(diff);
+        assert_eq!(changed_lines.len(), 2);
+        assert_eq!(
+            changed_lines[Path::new("src/main.rs")],
+            [3, 11, 12].iter().cloned().collect()
+        );
+        assert_eq!(
+            changed_lines[Path::new("src/new.rs")],
+            [1, 2, 3].iter().cloned().collect()
+        );
+    }
+
+    #[test]
+    fn test_restrict_to_changed_lines() {
+        let mut functions = FunctionMap::default();
+        functions.insert(
+            "changed".to_string(),
+            Function {
+                start: 2,
+                executed: true,
+            },
+        );
+        functions.insert(
+            "unchanged".to_string(),
+            Function {
+                start: 5,
+                executed: true,
+            },
+        );
+        let results = vec![
+            (
+                PathBuf::from("/repo/src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                CovResult {
+                    lines: [(2, 1), (3, 0), (5, 1)].iter().cloned().collect(),
+                    branches: [(3, vec![true, false]), (5, vec![true])]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    functions,
+                    line_tests: [
+                        (2, ["test_a".to_string()].into()),
+                        (5, ["test_b".to_string()].into()),
+                    ]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                    line_archives: [
+                        (3, ["a.zip".to_string()].into()),
+                        (5, ["b.zip".to_string()].into()),
+                    ]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("/repo/src/other.rs"),
+                PathBuf::from("src/other.rs"),
+                CovResult::default(),
+            ),
+        ];
+        let changed_lines: ChangedLines = [(PathBuf::from("src/main.rs"), [2, 3].into())]
+            .iter()
+            .cloned()
+            .collect();
+
+        let results = restrict_to_changed_lines(results, &changed_lines);
+        assert_eq!(results.len(), 1);
+        let (_, rel_path, result) = &results[0];
+        assert_eq!(rel_path, Path::new("src/main.rs"));
+        assert_eq!(result.lines, [(2, 1), (3, 0)].iter().cloned().collect());
+        assert_eq!(
+            result.branches,
+            [(3, vec![true, false])].iter().cloned().collect()
+        );
+        assert_eq!(result.functions.len(), 1);
+        assert!(result.functions.contains_key("changed"));
+        assert_eq!(result.line_tests.keys().collect::<Vec<_>>(), vec![&2]);
+        assert_eq!(result.line_archives.keys().collect::<Vec<_>>(), vec![&3]);
+    }
+}
@@ -0,0 +1,188 @@
+use log::info;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::llvm_tools::output_with_timeout;
+
+const DEFAULT_MAX_DOWNLOAD_SIZE: u64 = 4 << 30;
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+fn parse_max_download_size(value: Option<String>) -> u64 {
+    value
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_DOWNLOAD_SIZE)
+}
+
+/// Returns the URL without its query string and fragment, which may contain secrets (e.g. the
+/// signature of a presigned URL), to show it in the logs and the errors.
+fn redact_url(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap()
+}
+
+/// Returns the name of the file an URL points to, ignoring its query string (e.g. the signature of
+/// a presigned URL) and fragment, so that its extension tells its type.
+fn get_file_name(url: &str) -> Option<&str> {
+    let (_, path) = redact_url(url).split_once("://")?;
+    let (_, path) = path.split_once('/')?;
+    path.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+// `curl --max-filesize` can only reject the responses which announce their size, so the size of the
+// downloaded file is checked too.
+fn check_download_size(dest: &Path, max_size: u64) -> Result<(), String> {
+    let size = fs::metadata(dest).map_err(|e| e.to_string())?.len();
+    if size > max_size {
+        let _ = fs::remove_file(dest);
+        return Err(format!(
+            "the download is {} bytes, bigger than the maximum of {} bytes",
+            size, max_size
+        ));
+    }
+    Ok(())
+}
+
+fn download(url: &str, dest: &Path) -> Result<(), String> {
+    // The maximum size of a download, in bytes, can be set with `GRCOV_MAX_DOWNLOAD_SIZE`.
+    let max_size = parse_max_download_size(env::var("GRCOV_MAX_DOWNLOAD_SIZE").ok());
+    let max_size_arg = max_size.to_string();
+    info!("Downloading {} to {:?}", redact_url(url), dest);
+    // The command isn't included in the errors, as it contains the full URL.
+    let output = output_with_timeout(
+        Command::new("curl")
+            .args([
+                "--fail",
+                "--location",
+                "--silent",
+                "--show-error",
+                "--max-filesize",
+                &max_size_arg,
+                "--output",
+            ])
+            .arg(dest)
+            .arg(url),
+    )
+    .map_err(|e| format!("failed to execute curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    check_download_size(dest, max_size)
+}
+
+/// Downloads the inputs which are HTTP(S) URLs to `tmp_dir` with `curl`, returning the paths with
+/// the URLs replaced by the paths of the downloaded files.
+pub fn download_inputs(paths: &[String], tmp_dir: &Path) -> Result<Vec<String>, String> {
+    paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            if !is_url(path) {
+                return Ok(path.clone());
+            }
+
+            let file_name = get_file_name(path).ok_or_else(|| {
+                format!(
+                    "Cannot load '{}': the URL has no file name.",
+                    redact_url(path)
+                )
+            })?;
+            // Keep the original file name, as it tells the type of the file and is used to match
+            // gcno and gcda files.
+            let dir: PathBuf = tmp_dir.join("downloads").join(i.to_string());
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("Failed to create the download directory: {}", e))?;
+            let dest = dir.join(file_name);
+            download(path, &dest)
+                .map_err(|e| format!("Cannot download '{}': {}", redact_url(path), e))?;
+            Ok(dest.to_str().unwrap().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/lcov.info"));
+        assert!(is_url("http://example.com/lcov.info"));
+        assert!(!is_url("lcov.info"));
+        assert!(!is_url("/tmp/https/lcov.info"));
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("https://bucket.s3.amazonaws.com/ci/code-coverage.zip?X-Amz-Signature=abc"),
+            "https://bucket.s3.amazonaws.com/ci/code-coverage.zip"
+        );
+        assert_eq!(
+            redact_url("http://example.com/lcov.info#top"),
+            "http://example.com/lcov.info"
+        );
+        assert_eq!(
+            redact_url("http://example.com/lcov.info"),
+            "http://example.com/lcov.info"
+        );
+    }
+
+    #[test]
+    fn test_get_file_name() {
+        assert_eq!(
+            get_file_name(
+                "https://bucket.s3.amazonaws.com/ci/code-coverage.zip?X-Amz-Signature=abc/def"
+            ),
+            Some("code-coverage.zip")
+        );
+        assert_eq!(
+            get_file_name("http://example.com/a/lcov.info#top"),
+            Some("lcov.info")
+        );
+        assert_eq!(get_file_name("https://example.com/"), None);
+        assert_eq!(get_file_name("https://example.com"), None);
+    }
+
+    #[test]
+    fn test_parse_max_download_size() {
+        assert_eq!(parse_max_download_size(None), DEFAULT_MAX_DOWNLOAD_SIZE);
+        assert_eq!(parse_max_download_size(Some("1024".to_string())), 1024);
+        assert_eq!(
+            parse_max_download_size(Some("big".to_string())),
+            DEFAULT_MAX_DOWNLOAD_SIZE
+        );
+    }
+
+    #[test]
+    fn test_check_download_size() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let dest = tmp_dir.path().join("lcov.info");
+        fs::write(&dest, "0123456789").unwrap();
+
+        assert!(check_download_size(&dest, 10).is_ok());
+        assert!(dest.exists());
+        let err = check_download_size(&dest, 9).unwrap_err();
+        assert!(err.contains("10 bytes"));
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn test_download_inputs_errors() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let paths = vec!["lcov.info".to_string()];
+        assert_eq!(download_inputs(&paths, tmp_dir.path()), Ok(paths));
+
+        let paths = vec![
+            "lcov.info".to_string(),
+            "https://example.com/?token=secret".to_string(),
+        ];
+        assert_eq!(
+            download_inputs(&paths, tmp_dir.path()),
+            Err("Cannot load 'https://example.com/': the URL has no file name.".to_string())
+        );
+    }
+}
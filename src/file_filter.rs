@@ -1,5 +1,9 @@
+use log::warn;
+use md5::{Digest, Md5};
 use regex::Regex;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub enum FilterType {
     Line(u32),
@@ -15,6 +19,97 @@ pub struct FileFilter {
     excl_br_line: Option<Regex>,
     excl_br_start: Option<Regex>,
     excl_br_stop: Option<Regex>,
+    excluded_hashes: FxHashSet<String>,
+    excluded_lines: FxHashMap<String, FxHashSet<u32>>,
+    ignore_macro_lines: Option<Regex>,
+}
+
+fn decode_utf16(buf: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    let chunks = buf.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    let units: Vec<u16> = chunks.map(|c| from_bytes([c[0], c[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Parses a listing of the MD5 hashes of the files to exclude, one per line, optionally followed
+/// by a path (i.e. the output of `md5sum`). Empty lines and lines starting with `#` are ignored.
+pub fn parse_hashes_listing(listing: &str) -> FxHashSet<String> {
+    listing
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let hash = line.split_whitespace().next().unwrap().to_ascii_lowercase();
+            if hash.len() == 32 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                Some(hash)
+            } else {
+                warn!(
+                    "Ignoring invalid MD5 hash in the excluded files listing: {}",
+                    line
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parses a listing of the lines to exclude, one `path:line` entry per line. Empty lines and lines
+/// starting with `#` are ignored. The paths are either relative, as in the outputs (i.e. after
+/// the path mapping and the removal of the prefix directory), or absolute.
+pub fn parse_lines_listing(listing: &str) -> FxHashMap<String, FxHashSet<u32>> {
+    let mut excluded_lines: FxHashMap<String, FxHashSet<u32>> = FxHashMap::default();
+    for line in listing.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line
+            .rsplit_once(':')
+            .and_then(|(path, number)| Some((path, number.trim().parse::<u32>().ok()?)))
+        {
+            Some((path, number)) => {
+                let path = path.trim().replace('\\', "/");
+                let path = path.strip_prefix("./").unwrap_or(&path).to_string();
+                excluded_lines.entry(path).or_default().insert(number);
+            }
+            None => warn!(
+                "Ignoring invalid entry in the excluded lines listing: {}",
+                line
+            ),
+        }
+    }
+    excluded_lines
+}
+
+// Read a source file as UTF-8, transcoding it first if it starts with a UTF-16 BOM.
+// Returns None if the file can't be read or if it isn't valid UTF-8/UTF-16.
+pub(crate) fn read_source(file: &Path) -> Option<String> {
+    decode_source(file, std::fs::read(file).ok()?)
+}
+
+// Decode the content of a source file read by the caller, see `read_source`.
+fn decode_source(file: &Path, buf: Vec<u8>) -> Option<String> {
+    static HAVE_PRINTED_WARNING: AtomicBool = AtomicBool::new(false);
+
+    let source = match buf.as_slice() {
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8(buf).ok(),
+    };
+
+    if source.is_none()
+        && HAVE_PRINTED_WARNING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    {
+        warn!(
+            "Source file {} is neither UTF-8 nor UTF-16 encoded, exclusion markers won't be applied to it (and other files with an unsupported encoding).",
+            file.display()
+        );
+    }
+
+    source
 }
 
 impl FileFilter {
@@ -25,6 +120,9 @@ impl FileFilter {
         excl_br_line: Option<Regex>,
         excl_br_start: Option<Regex>,
         excl_br_stop: Option<Regex>,
+        excluded_hashes: FxHashSet<String>,
+        excluded_lines: FxHashMap<String, FxHashSet<u32>>,
+        ignore_macro_lines: Option<Regex>,
     ) -> Self {
         Self {
             excl_line,
@@ -33,27 +131,69 @@ impl FileFilter {
             excl_br_line,
             excl_br_start,
             excl_br_stop,
+            excluded_hashes,
+            excluded_lines,
+            ignore_macro_lines,
         }
     }
 
-    pub fn create(&self, file: &Path) -> Vec<FilterType> {
-        if self.excl_line.is_none()
-            && self.excl_start.is_none()
-            && self.excl_br_line.is_none()
-            && self.excl_br_start.is_none()
-        {
+    /// Returns whether the content of the source files is needed by `is_excluded` or `create`,
+    /// so that it is only read when it is.
+    pub fn needs_source(&self) -> bool {
+        !self.excluded_hashes.is_empty() || self.has_markers()
+    }
+
+    fn has_markers(&self) -> bool {
+        self.excl_line.is_some()
+            || self.excl_start.is_some()
+            || self.excl_br_line.is_some()
+            || self.excl_br_start.is_some()
+            || self.ignore_macro_lines.is_some()
+    }
+
+    /// Returns whether the MD5 hash of the content of a source file is one of the excluded hashes.
+    pub fn is_excluded(&self, source: &[u8]) -> bool {
+        if self.excluded_hashes.is_empty() {
+            return false;
+        }
+
+        let hash = format!("{:x}", Md5::digest(source));
+        self.excluded_hashes.contains(&hash)
+    }
+
+    /// Returns the lines listed as excluded for the file, by its relative or absolute path.
+    pub fn excluded_lines(&self, rel_path: &Path, abs_path: &Path) -> Vec<u32> {
+        if self.excluded_lines.is_empty() {
             return Vec::new();
         }
 
-        let file = std::fs::read_to_string(file);
-        let file = if let Ok(file) = file {
+        [rel_path, abs_path]
+            .iter()
+            .filter_map(|path| {
+                self.excluded_lines
+                    .get(&path.to_string_lossy().replace('\\', "/"))
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Returns the lines of the source `file` excluded by the markers, given its content.
+    pub fn create(&self, file: &Path, source: Vec<u8>) -> Vec<FilterType> {
+        if !self.has_markers() {
+            return Vec::new();
+        }
+
+        let file = if let Some(file) = decode_source(file, source) {
             file
         } else {
             return Vec::new();
         };
 
-        let mut ignore_br = false;
-        let mut ignore = false;
+        // The regions can nest, e.g. in conditionally compiled blocks with their own markers, so
+        // they only end when as many stops as starts were found.
+        let mut ignore_br_depth: usize = 0;
+        let mut ignore_depth: usize = 0;
 
         file.split('\n')
             .enumerate()
@@ -66,33 +206,42 @@ impl FileFilter {
                 let line = line.strip_suffix('\r').unwrap_or(line);
 
                 // End a branch ignore region. Region endings are exclusive.
-                if ignore_br
-                    && self
-                        .excl_br_stop
-                        .as_ref()
-                        .map_or(false, |f| f.is_match(line))
+                if ignore_br_depth > 0
+                    && self.excl_br_stop.as_ref().is_some_and(|f| f.is_match(line))
                 {
-                    ignore_br = false
+                    ignore_br_depth -= 1;
                 }
 
                 // End a line ignore region. Region endings are exclusive.
-                if ignore && self.excl_stop.as_ref().map_or(false, |f| f.is_match(line)) {
-                    ignore = false
+                if ignore_depth > 0 && self.excl_stop.as_ref().is_some_and(|f| f.is_match(line)) {
+                    ignore_depth -= 1;
                 }
 
                 // Start a branch ignore region. Region starts are inclusive.
-                if !ignore_br
-                    && self
-                        .excl_br_start
-                        .as_ref()
-                        .map_or(false, |f| f.is_match(line))
+                if self
+                    .excl_br_start
+                    .as_ref()
+                    .is_some_and(|f| f.is_match(line))
                 {
-                    ignore_br = true;
+                    ignore_br_depth += 1;
                 }
 
                 // Start a line ignore region. Region starts are inclusive.
-                if !ignore && self.excl_start.as_ref().map_or(false, |f| f.is_match(line)) {
-                    ignore = true;
+                if self.excl_start.as_ref().is_some_and(|f| f.is_match(line)) {
+                    ignore_depth += 1;
+                }
+
+                let ignore_br = ignore_br_depth > 0;
+                let ignore = ignore_depth > 0;
+
+                // Lines of macro invocations are dropped altogether, as their counts come from
+                // the expansion.
+                if self
+                    .ignore_macro_lines
+                    .as_ref()
+                    .is_some_and(|f| f.is_match(line))
+                {
+                    return Some(FilterType::Both(number));
                 }
 
                 if ignore_br {
@@ -106,20 +255,16 @@ impl FileFilter {
                     }
                 } else if ignore {
                     Some(FilterType::Line(number))
-                } else if self
-                    .excl_br_line
-                    .as_ref()
-                    .map_or(false, |f| f.is_match(line))
-                {
+                } else if self.excl_br_line.as_ref().is_some_and(|f| f.is_match(line)) {
                     // Single line exclusion. If single line exclusions occur
                     // inside a region they are meaningless (would be applied
                     // anway), so they are lower priority.
-                    if self.excl_line.as_ref().map_or(false, |f| f.is_match(line)) {
+                    if self.excl_line.as_ref().is_some_and(|f| f.is_match(line)) {
                         Some(FilterType::Both(number))
                     } else {
                         Some(FilterType::Branch(number))
                     }
-                } else if self.excl_line.as_ref().map_or(false, |f| f.is_match(line)) {
+                } else if self.excl_line.as_ref().is_some_and(|f| f.is_match(line)) {
                     Some(FilterType::Line(number))
                 } else {
                     None
@@ -128,3 +273,237 @@ impl FileFilter {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_utf16(path: &Path, content: &str, little_endian: bool) {
+        let mut buf = if little_endian {
+            vec![0xFF, 0xFE]
+        } else {
+            vec![0xFE, 0xFF]
+        };
+        for unit in content.encode_utf16() {
+            if little_endian {
+                buf.extend(unit.to_le_bytes());
+            } else {
+                buf.extend(unit.to_be_bytes());
+            }
+        }
+        fs::write(path, buf).unwrap();
+    }
+
+    fn excluded_lines(filters: Vec<FilterType>) -> Vec<u32> {
+        filters
+            .into_iter()
+            .map(|filter| match filter {
+                FilterType::Line(number)
+                | FilterType::Branch(number)
+                | FilterType::Both(number) => number,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_utf16_source() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_filter = FileFilter::new(
+            Some(Regex::new("excluded line").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            FxHashSet::default(),
+            FxHashMap::default(),
+            None,
+        );
+        let content = "int a;\r\nint b; // excluded line\r\nint c;\r\n";
+
+        let le_path = tmp_dir.path().join("le.cpp");
+        write_utf16(&le_path, content, true);
+        assert_eq!(
+            excluded_lines(file_filter.create(&le_path, fs::read(&le_path).unwrap())),
+            vec![2]
+        );
+
+        let be_path = tmp_dir.path().join("be.cpp");
+        write_utf16(&be_path, content, false);
+        assert_eq!(
+            excluded_lines(file_filter.create(&be_path, fs::read(&be_path).unwrap())),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_invalid_encoding_source() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_filter = FileFilter::new(
+            Some(Regex::new("excluded line").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            FxHashSet::default(),
+            FxHashMap::default(),
+            None,
+        );
+
+        let path = tmp_dir.path().join("invalid.cpp");
+        fs::write(&path, b"int a; // excluded line\n\xC3\x28\n").unwrap();
+        assert!(file_filter
+            .create(&path, fs::read(&path).unwrap())
+            .is_empty());
+
+        // An odd number of bytes after the BOM can't be valid UTF-16.
+        fs::write(&path, b"\xFF\xFEa\x00b").unwrap();
+        assert!(file_filter
+            .create(&path, fs::read(&path).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_nested_exclusion_regions() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_filter = FileFilter::new(
+            None,
+            Some(Regex::new("EXCL_START").unwrap()),
+            Some(Regex::new("EXCL_STOP").unwrap()),
+            None,
+            Some(Regex::new("BR_START").unwrap()),
+            Some(Regex::new("BR_STOP").unwrap()),
+            FxHashSet::default(),
+            FxHashMap::default(),
+            None,
+        );
+        let path = tmp_dir.path().join("main.c");
+        fs::write(
+            &path,
+            "a\n// EXCL_START BR_START\nb\n// EXCL_START BR_START\nc\n// EXCL_STOP BR_STOP\nd\n// EXCL_STOP\ne\n// BR_STOP\nf\n",
+        )
+        .unwrap();
+
+        let filters: Vec<(u32, &str)> = file_filter
+            .create(&path, fs::read(&path).unwrap())
+            .into_iter()
+            .map(|filter| match filter {
+                FilterType::Line(number) => (number, "line"),
+                FilterType::Branch(number) => (number, "branch"),
+                FilterType::Both(number) => (number, "both"),
+            })
+            .collect();
+        // The regions only end with the stop of their outermost start.
+        assert_eq!(
+            filters,
+            vec![
+                (2, "both"),
+                (3, "both"),
+                (4, "both"),
+                (5, "both"),
+                (6, "both"),
+                (7, "both"),
+                (8, "branch"),
+                (9, "branch"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignore_macro_lines() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_filter = FileFilter::new(
+            Some(Regex::new("excluded line").unwrap()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            FxHashSet::default(),
+            FxHashMap::default(),
+            Some(Regex::new(r"\b(println|assert)!").unwrap()),
+        );
+        let path = tmp_dir.path().join("main.rs");
+        fs::write(
+            &path,
+            "fn main() {\n    println!(\"a\");\n    let a = 1; // excluded line\n    assert!(a == 1);\n    eprintln!(\"b\");\n}\n",
+        )
+        .unwrap();
+
+        let filters: Vec<(u32, bool)> = file_filter
+            .create(&path, fs::read(&path).unwrap())
+            .into_iter()
+            .map(|filter| match filter {
+                FilterType::Line(number) => (number, false),
+                FilterType::Both(number) => (number, true),
+                FilterType::Branch(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(filters, vec![(2, true), (3, false), (4, true)]);
+    }
+
+    #[test]
+    fn test_excluded_hashes() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let vendored = tmp_dir.path().join("vendored.c");
+        fs::write(&vendored, "int vendored;\n").unwrap();
+        let own = tmp_dir.path().join("own.c");
+        fs::write(&own, "int own;\n").unwrap();
+
+        let listing = format!(
+            "# Vendored files\n\n{:X}  third_party/vendored.c\nnot-a-hash\n",
+            Md5::digest(b"int vendored;\n")
+        );
+        let excluded_hashes = parse_hashes_listing(&listing);
+        assert_eq!(excluded_hashes.len(), 1);
+
+        let file_filter = FileFilter::new(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            excluded_hashes,
+            FxHashMap::default(),
+            None,
+        );
+        assert!(file_filter.needs_source());
+        assert!(file_filter.is_excluded(&fs::read(&vendored).unwrap()));
+        assert!(!file_filter.is_excluded(&fs::read(&own).unwrap()));
+        assert!(!FileFilter::default().needs_source());
+    }
+
+    #[test]
+    fn test_excluded_lines() {
+        let listing = "# Lint\n\nsrc/a.rs:3\n./src/a.rs:7\nsrc\\b.rs:1\n/abs/c.rs:2\nnot-an-entry\nsrc/a.rs:x\n";
+        let file_filter = FileFilter::new(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            FxHashSet::default(),
+            parse_lines_listing(listing),
+            None,
+        );
+
+        let mut lines = file_filter.excluded_lines(Path::new("src/a.rs"), Path::new("/abs/a.rs"));
+        lines.sort_unstable();
+        assert_eq!(lines, vec![3, 7]);
+        assert_eq!(
+            file_filter.excluded_lines(Path::new("src/b.rs"), Path::new("/abs/b.rs")),
+            vec![1]
+        );
+        assert_eq!(
+            file_filter.excluded_lines(Path::new("c.rs"), Path::new("/abs/c.rs")),
+            vec![2]
+        );
+        assert!(file_filter
+            .excluded_lines(Path::new("d.rs"), Path::new("/abs/d.rs"))
+            .is_empty());
+    }
+}
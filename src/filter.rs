@@ -1,4 +1,5 @@
 use crate::defs::*;
+use rustc_hash::FxHashMap;
 
 pub fn is_covered(result: &CovResult) -> bool {
     // For C/C++ source files, we can consider a file as being uncovered
@@ -20,10 +21,40 @@ pub fn is_covered(result: &CovResult) -> bool {
     result.functions.len() <= 1 || any_function_covered
 }
 
+/// Removes the functions without any line with coverage data, i.e. with no line between their
+/// start and the start of the next function of the file (or the lines attributed to them, when
+/// known). The functions with an unknown start (0) are kept.
+pub fn remove_empty_functions(result: &mut CovResult) {
+    let mut starts: Vec<u32> = result.functions.values().map(|f| f.start).collect();
+    starts.sort_unstable();
+    starts.dedup();
+
+    // Whether each function with attributed lines has one with coverage data.
+    let lines = &result.lines;
+    let mut attributed: FxHashMap<&str, bool> = FxHashMap::default();
+    for (line, name) in &result.line_functions {
+        *attributed.entry(name).or_default() |= lines.contains_key(line);
+    }
+
+    result.functions.retain(|name, function| {
+        if let Some(&has_line) = attributed.get(name.as_str()) {
+            return has_line;
+        }
+        if function.start == 0 {
+            return true;
+        }
+        let end = starts
+            .iter()
+            .find(|&&start| start > function.start)
+            .copied()
+            .unwrap_or(u32::MAX);
+        lines.range(function.start..end).next().is_some()
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustc_hash::FxHashMap;
 
     #[test]
     fn test_covered() {
@@ -46,6 +77,7 @@ mod tests {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -57,6 +89,7 @@ mod tests {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions: FxHashMap::default(),
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -83,6 +116,7 @@ mod tests {
             lines: [(1, 0), (2, 0), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions: FxHashMap::default(),
+            ..Default::default()
         };
 
         assert!(!is_covered(&result));
@@ -109,6 +143,7 @@ mod tests {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -128,6 +163,7 @@ mod tests {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -154,8 +190,45 @@ mod tests {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
             branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(!is_covered(&result));
     }
+
+    #[test]
+    fn test_remove_empty_functions() {
+        let function = |start| Function {
+            start,
+            executed: false,
+        };
+        let mut result = CovResult {
+            lines: [(1, 1), (2, 1), (10, 0), (20, 0)].iter().cloned().collect(),
+            branches: [].iter().cloned().collect(),
+            functions: [
+                ("f1".to_string(), function(1)),
+                ("empty".to_string(), function(5)),
+                ("f2".to_string(), function(8)),
+                ("attributed".to_string(), function(40)),
+                ("empty_attributed".to_string(), function(25)),
+                ("unknown_start".to_string(), function(0)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            line_functions: [
+                (20, "attributed".to_string()),
+                (30, "empty_attributed".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            ..Default::default()
+        };
+
+        remove_empty_functions(&mut result);
+        let mut names: Vec<&String> = result.functions.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["attributed", "f1", "f2", "unknown_start"]);
+    }
 }
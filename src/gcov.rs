@@ -1,13 +1,26 @@
 use lazy_static::lazy_static;
+use log::debug;
 use semver::Version;
 use std::env;
 use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+use crate::llvm_tools::output_with_timeout;
+use crate::reader::check_version_stamps;
+
+const DEFAULT_SPAWN_RETRIES: u32 = 3;
+const MAX_SPAWN_BACKOFF: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum GcovToolError {
     ProcessFailure,
+    VersionMismatch(String),
+    Timeout(String),
     Failure((String, String, String)),
 }
 
@@ -15,6 +28,8 @@ impl fmt::Display for GcovToolError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             GcovToolError::ProcessFailure => write!(f, "Failed to execute gcov process"),
+            GcovToolError::VersionMismatch(ref e) => write!(f, "{}", e),
+            GcovToolError::Timeout(ref path) => write!(f, "gcov timed out on {}", path),
             GcovToolError::Failure((ref path, ref stdout, ref stderr)) => {
                 writeln!(f, "gcov execution failed on {}", path)?;
                 writeln!(f, "gcov stdout: {}", stdout)?;
@@ -32,11 +47,79 @@ fn get_gcov() -> String {
     }
 }
 
+fn parse_spawn_retries(value: Option<String>) -> u32 {
+    value
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_SPAWN_RETRIES)
+}
+
+/// Number of times spawning gcov is retried on transient failures, which can be set with the
+/// `GRCOV_GCOV_RETRIES` environment variable.
+fn get_spawn_retries() -> u32 {
+    lazy_static! {
+        static ref R: u32 = parse_spawn_retries(env::var("GRCOV_GCOV_RETRIES").ok());
+    }
+    *R
+}
+
+// Failures to fork on loaded machines (EAGAIN, ENOMEM) are usually temporary.
+fn is_transient_spawn_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::OutOfMemory
+    )
+}
+
+fn spawn_backoff(attempt: u32) -> Duration {
+    // The shift is bounded as well, as 10 << 7 is already past the maximum backoff.
+    Duration::from_millis(10 << attempt.min(7)).min(MAX_SPAWN_BACKOFF)
+}
+
+fn output_with_retries(command: &mut Command) -> io::Result<Output> {
+    let retries = get_spawn_retries();
+    let mut attempt = 0;
+    loop {
+        match output_with_timeout(command) {
+            Err(e) if attempt < retries && is_transient_spawn_error(&e) => {
+                attempt += 1;
+                let backoff = spawn_backoff(attempt);
+                debug!(
+                    "Failed to spawn gcov ({}), retrying in {:?} ({}/{})",
+                    e, backoff, attempt, retries
+                );
+                thread::sleep(backoff);
+            }
+            res => return res,
+        }
+    }
+}
+
+fn read_header(path: &Path) -> Option<[u8; 8]> {
+    let mut header = [0; 8];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    Some(header)
+}
+
+// gcov only warns when the versions differ and silently produces no coverage.
+fn check_gcda_version(gcno_path: &Path) -> Result<(), GcovToolError> {
+    let gcda_path = gcno_path.with_extension("gcda");
+    if let (Some(gcno_header), Some(gcda_header)) =
+        (read_header(gcno_path), read_header(&gcda_path))
+    {
+        let stem = gcno_path.with_extension("");
+        check_version_stamps(stem.to_str().unwrap(), &gcno_header, &gcda_header)
+            .map_err(|e| GcovToolError::VersionMismatch(e.to_string()))?;
+    }
+    Ok(())
+}
+
 pub fn run_gcov(
     gcno_path: &Path,
     branch_enabled: bool,
     working_dir: &Path,
 ) -> Result<(), GcovToolError> {
+    check_gcda_version(gcno_path)?;
+
     let mut command = Command::new(get_gcov());
     let command = if branch_enabled {
         command.arg("-b").arg("-c")
@@ -48,10 +131,14 @@ pub fn run_gcov(
         .arg("-i") // Generate intermediate gcov format, faster to parse.
         .current_dir(working_dir);
 
-    let output = if let Ok(output) = status.output() {
-        output
-    } else {
-        return Err(GcovToolError::ProcessFailure);
+    let output = match output_with_retries(status) {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            return Err(GcovToolError::Timeout(
+                gcno_path.to_str().unwrap().to_string(),
+            ));
+        }
+        Err(_) => return Err(GcovToolError::ProcessFailure),
     };
 
     if !output.status.success() {
@@ -98,7 +185,7 @@ fn parse_version(gcov_output: &str) -> Version {
     let version = gcov_output
         .split([' ', '\n'])
         .filter_map(|value| Version::parse(value.trim()).ok())
-        .last();
+        .next_back();
     assert!(version.is_some(), "no version found for `gcov`.");
 
     version.unwrap()
@@ -108,6 +195,35 @@ fn parse_version(gcov_output: &str) -> Version {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_spawn_retries() {
+        assert_eq!(parse_spawn_retries(None), DEFAULT_SPAWN_RETRIES);
+        assert_eq!(parse_spawn_retries(Some("0".to_string())), 0);
+        assert_eq!(parse_spawn_retries(Some(" 7 ".to_string())), 7);
+        assert_eq!(
+            parse_spawn_retries(Some("many".to_string())),
+            DEFAULT_SPAWN_RETRIES
+        );
+    }
+
+    #[test]
+    fn test_spawn_backoff() {
+        assert_eq!(spawn_backoff(1), Duration::from_millis(20));
+        assert_eq!(spawn_backoff(3), Duration::from_millis(80));
+        assert_eq!(spawn_backoff(20), MAX_SPAWN_BACKOFF);
+        assert_eq!(spawn_backoff(100), MAX_SPAWN_BACKOFF);
+    }
+
+    #[test]
+    fn test_is_transient_spawn_error() {
+        assert!(is_transient_spawn_error(&io::Error::from(
+            io::ErrorKind::WouldBlock
+        )));
+        assert!(!is_transient_spawn_error(&io::Error::from(
+            io::ErrorKind::NotFound
+        )));
+    }
+
     #[test]
     fn test_parse_version() {
         assert_eq!(
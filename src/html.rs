@@ -5,12 +5,13 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::{btree_map, BTreeMap};
 use std::fs::{self, File};
-use std::io::{BufReader, Read, Write};
+use std::io::{self, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tera::try_get_value;
 
 use crate::defs::*;
+use crate::output::OutputFile;
 
 impl HtmlStats {
     #[inline(always)]
@@ -33,10 +34,12 @@ pub struct Config {
     branch_hi_limit: f64,
     branch_med_limit: f64,
     date: DateTime<Utc>,
+    title: Option<String>,
+    metadata: BTreeMap<String, String>,
 }
 
 impl Config {
-    fn new(cfg: &ConfigFile) -> Config {
+    fn new(cfg: &ConfigFile, report: &ReportMetadata) -> Config {
         Config {
             hi_limit: cfg.hi_limit.unwrap_or(90.),
             med_limit: cfg.med_limit.unwrap_or(75.),
@@ -45,8 +48,16 @@ impl Config {
             branch_hi_limit: cfg.branch_hi_limit.unwrap_or(90.),
             branch_med_limit: cfg.branch_med_limit.unwrap_or(75.),
             date: Utc::now(),
+            title: report.title.clone().or_else(|| cfg.title.clone()),
+            metadata: report.metadata.clone(),
         }
     }
+
+    /// Adds the title and the metadata of the report to the context of a page.
+    fn insert_report(&self, ctx: &mut Context) {
+        ctx.insert("report_title", &self.title);
+        ctx.insert("report_metadata", &self.metadata);
+    }
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -57,6 +68,7 @@ pub struct ConfigFile {
     fn_med_limit: Option<f64>,
     branch_hi_limit: Option<f64>,
     branch_med_limit: Option<f64>,
+    title: Option<String>,
     templates: Option<HashMap<String, String>>,
 }
 
@@ -118,9 +130,9 @@ fn get_templates(user_templates: &Option<HashMap<String, String>>) -> HashMap<St
     result
 }
 
-pub fn get_config(output_config_file: Option<&Path>) -> (Tera, Config) {
+pub fn get_config(output_config_file: Option<&Path>, report: &ReportMetadata) -> (Tera, Config) {
     let user_conf = ConfigFile::load(output_config_file);
-    let conf = Config::new(&user_conf);
+    let conf = Config::new(&user_conf, report);
 
     let mut tera = Tera::default();
 
@@ -162,11 +174,27 @@ impl tera::Filter for Config {
     }
 }
 
-fn create_parent(path: &Path) {
-    let dest_parent = path.parent().unwrap();
-    if !dest_parent.exists() && fs::create_dir_all(dest_parent).is_err() {
-        panic!("Cannot create parent directory: {:?}", dest_parent);
-    }
+/// Writes a file of the report, creating its parent directories if needed. Like the other outputs,
+/// it's written to a temporary file renamed into place.
+fn write_output_file(output_file: &Path, content: &[u8]) -> io::Result<()> {
+    let parent = output_file.parent().unwrap();
+    fs::create_dir_all(parent).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot create the directory {}: {}", parent.display(), e),
+        )
+    })?;
+    let write = || {
+        let mut file = OutputFile::create(output_file)?;
+        file.write_all(content)?;
+        file.persist()
+    };
+    write().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot write the file {}: {}", output_file.display(), e),
+        )
+    })
 }
 
 fn add_html_ext(path: &Path) -> PathBuf {
@@ -180,36 +208,21 @@ fn add_html_ext(path: &Path) -> PathBuf {
 }
 
 fn get_stats(result: &CovResult) -> HtmlStats {
-    let total_lines = result.lines.len();
-    let covered_lines = result.lines.values().filter(|x| **x > 0).count();
-    let total_funs = result.functions.len();
-    let covered_funs = result.functions.values().filter(|f| f.executed).count();
-    let total_branches = result.branches.values().map(|v| v.len()).sum();
-    let covered_branches = result
-        .branches
-        .values()
-        .map(|v| v.iter().filter(|x| **x).count())
-        .sum();
+    let totals = CoverageTotals::of(result);
 
     HtmlStats {
-        total_lines,
-        covered_lines,
-        total_funs,
-        covered_funs,
-        total_branches,
-        covered_branches,
+        total_lines: totals.total_lines,
+        covered_lines: totals.covered_lines,
+        total_funs: totals.total_functions,
+        covered_funs: totals.covered_functions,
+        total_branches: totals.total_branches,
+        covered_branches: totals.covered_branches,
     }
 }
 
 #[inline(always)]
 fn get_percentage_of_covered_lines(covered_lines: usize, total_lines: usize) -> f64 {
-    if total_lines != 0 {
-        covered_lines as f64 / total_lines as f64 * 100.0
-    } else {
-        // If the file is empty (no lines) then the coverage
-        // must be 100% (0% means "bad" which is not the case).
-        100.0
-    }
+    coverage_percentage(covered_lines, total_lines).unwrap_or(100.0)
 }
 
 fn percent(args: &HashMap<String, Value>) -> tera::Result<Value> {
@@ -232,12 +245,24 @@ fn get_base(rel_path: &Path) -> String {
     "../".repeat(count)
 }
 
-fn get_dirs_result(global: Arc<Mutex<HtmlGlobalStats>>, rel_path: &Path, stats: &HtmlStats) {
+// The informational files are listed in their directory, without counting their stats in the totals.
+fn get_dirs_result(
+    global: Arc<Mutex<HtmlGlobalStats>>,
+    rel_path: &Path,
+    stats: &HtmlStats,
+    informational: bool,
+) {
     let parent = rel_path.parent().unwrap().to_str().unwrap().to_string();
     let file_name = rel_path.file_name().unwrap().to_str().unwrap().to_string();
     let fs = HtmlFileStats {
         stats: stats.clone(),
     };
+    let counted_stats = if informational {
+        HtmlStats::default()
+    } else {
+        stats.clone()
+    };
+    let stats = &counted_stats;
     let mut global = global.lock().unwrap();
     global.stats.add(stats);
     let entry = global.dirs.entry(parent);
@@ -275,20 +300,11 @@ pub fn gen_index(
     output: &Path,
     branch_enabled: bool,
     precision: usize,
-) {
-    let output_file = output.join("index.html");
-    create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
-
+) -> io::Result<()> {
     let mut ctx = make_context();
     let empty: &[&str] = &[];
     ctx.insert("date", &conf.date);
+    conf.insert_report(&mut ctx);
     ctx.insert("current", "top_level");
     ctx.insert("parents", empty);
     ctx.insert("stats", &global.stats);
@@ -297,12 +313,8 @@ pub fn gen_index(
     ctx.insert("kind", "Directory");
     ctx.insert("branch_enabled", &branch_enabled);
 
-    let out = tera.render("index.html", &ctx).unwrap();
-
-    if output_stream.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-        return;
-    }
+    let out = tera.render("index.html", &ctx).map_err(io::Error::other)?;
+    write_output_file(&output.join("index.html"), out.as_bytes())?;
 
     for (dir_name, dir_stats) in global.dirs.iter() {
         gen_dir_index(
@@ -313,8 +325,10 @@ pub fn gen_index(
             output,
             branch_enabled,
             precision,
-        );
+        )?;
     }
+
+    Ok(())
 }
 
 pub fn gen_dir_index(
@@ -325,22 +339,14 @@ pub fn gen_dir_index(
     output: &Path,
     branch_enabled: bool,
     precision: usize,
-) {
+) -> io::Result<()> {
     let index = Path::new(dir_name).join("index.html");
     let layers = index.components().count() - 1;
     let prefix = "../".repeat(layers) + "index.html";
-    let output_file = output.join(index);
-    create_parent(&output_file);
-    let mut output = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
 
     let mut ctx = make_context();
     ctx.insert("date", &conf.date);
+    conf.insert_report(&mut ctx);
     ctx.insert("bulma_version", BULMA_VERSION);
     ctx.insert("current", dir_name);
     ctx.insert("parents", &[(prefix, "top_level")]);
@@ -350,11 +356,8 @@ pub fn gen_dir_index(
     ctx.insert("branch_enabled", &branch_enabled);
     ctx.insert("precision", &precision);
 
-    let out = tera.render("index.html", &ctx).unwrap();
-
-    if output.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+    let out = tera.render("index.html", &ctx).map_err(io::Error::other)?;
+    write_output_file(&output.join(index), out.as_bytes())
 }
 
 fn gen_html(
@@ -367,31 +370,25 @@ fn gen_html(
     global: Arc<Mutex<HtmlGlobalStats>>,
     branch_enabled: bool,
     precision: usize,
-) {
+    keep_not_existing: bool,
+) -> io::Result<()> {
     if !rel_path.is_relative() {
-        return;
+        return Ok(());
     }
 
-    let mut f = match File::open(path) {
-        Err(_) => {
+    // Files whose source is missing are skipped, unless they're kept to have stable totals.
+    let f = match File::open(path) {
+        Err(_) if !keep_not_existing => {
             //eprintln!("Warning: cannot open file {:?}", path);
-            return;
+            return Ok(());
         }
-        Ok(f) => f,
+        Err(_) => None,
+        Ok(f) => Some(f),
     };
 
     let stats = get_stats(result);
-    get_dirs_result(global, rel_path, &stats);
-
-    let output_file = output.join(add_html_ext(rel_path));
-    create_parent(&output_file);
-    let mut output = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
+    get_dirs_result(global, rel_path, &stats, result.informational);
+
     let base_url = get_base(rel_path);
     let filename = rel_path.file_name().unwrap().to_str().unwrap();
     let parent = rel_path.parent().unwrap().to_str().unwrap().to_string();
@@ -400,6 +397,7 @@ fn gen_html(
 
     let mut ctx = make_context();
     ctx.insert("date", &conf.date);
+    conf.insert_report(&mut ctx);
     ctx.insert("bulma_version", BULMA_VERSION);
     ctx.insert("current", filename);
     ctx.insert(
@@ -414,9 +412,16 @@ fn gen_html(
     ctx.insert("precision", &precision);
 
     let mut file_buf = Vec::new();
-    if let Err(e) = f.read_to_end(&mut file_buf) {
-        eprintln!("Failed to read {}: {}", path.display(), e);
-        return;
+    if let Some(mut f) = f {
+        if let Err(e) = f.read_to_end(&mut file_buf) {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            return Ok(());
+        }
+    } else {
+        ctx.insert("source_unavailable", &true);
+        // Render empty lines, up to the last one with coverage data.
+        let last_line = result.lines.keys().next_back().copied().unwrap_or(0);
+        file_buf = vec![b'\n'; last_line as usize];
     }
 
     let file_utf8 = String::from_utf8_lossy(&file_buf);
@@ -438,18 +443,31 @@ fn gen_html(
                 .get(&(index as u32))
                 .map(|&v| v as i64)
                 .unwrap_or(-1);
+            let branches = if branch_enabled {
+                result
+                    .branches
+                    .get(&(index as u32))
+                    .map(|branches| get_taken_branches(branches))
+            } else {
+                None
+            };
 
-            (index, count, l)
+            (index, count, l, branches)
         })
         .collect::<Vec<_>>();
 
     ctx.insert("items", &items);
 
-    let out = tera.render("file.html", &ctx).unwrap();
+    let out = tera.render("file.html", &ctx).map_err(io::Error::other)?;
+    write_output_file(&output.join(add_html_ext(rel_path)), out.as_bytes())
+}
 
-    if output.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+/// Returns the number of taken branches and the total number of branches of a line.
+fn get_taken_branches(branches: &[bool]) -> (usize, usize) {
+    (
+        branches.iter().filter(|&&taken| taken).count(),
+        branches.len(),
+    )
 }
 
 pub fn consumer_html(
@@ -460,7 +478,8 @@ pub fn consumer_html(
     conf: Config,
     branch_enabled: bool,
     precision: usize,
-) {
+    keep_not_existing: bool,
+) -> io::Result<()> {
     while let Ok(job) = receiver.recv() {
         if job.is_none() {
             break;
@@ -476,8 +495,11 @@ pub fn consumer_html(
             global.clone(),
             branch_enabled,
             precision,
-        );
+            keep_not_existing,
+        )?;
     }
+
+    Ok(())
 }
 
 /// Different available styles to render badges with [`gen_badge`].
@@ -529,17 +551,13 @@ impl BadgeStyle {
 
 /// Generate coverage badges, typically for use in a README.md if the HTML output is hosted on a
 /// website like GitHub Pages.
-pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, style: BadgeStyle) {
-    let output_file = output.join(style.path());
-    create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
-
+pub fn gen_badge(
+    tera: &Tera,
+    stats: &HtmlStats,
+    conf: &Config,
+    output: &Path,
+    style: BadgeStyle,
+) -> io::Result<()> {
     let mut ctx = make_context();
     ctx.insert(
         "current",
@@ -548,11 +566,10 @@ pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, s
     ctx.insert("hi_limit", &conf.hi_limit);
     ctx.insert("med_limit", &conf.med_limit);
 
-    let out = tera.render(style.template_name(), &ctx).unwrap();
-
-    if output_stream.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+    let out = tera
+        .render(style.template_name(), &ctx)
+        .map_err(io::Error::other)?;
+    write_output_file(&output.join(style.path()), out.as_bytes())
 }
 
 /// Generate a coverage.json file that can be used with shields.io/endpoint to dynamically create
@@ -568,7 +585,12 @@ pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, s
 ///
 /// `<username>` and `<project>` should be replaced with a real username and project name
 /// respectively, for the URL to work.
-pub fn gen_coverage_json(stats: &HtmlStats, conf: &Config, output: &Path, precision: usize) {
+pub fn gen_coverage_json(
+    stats: &HtmlStats,
+    conf: &Config,
+    output: &Path,
+    precision: usize,
+) -> io::Result<()> {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
     struct CoverageData {
@@ -578,42 +600,26 @@ pub fn gen_coverage_json(stats: &HtmlStats, conf: &Config, output: &Path, precis
         color: &'static str,
     }
 
-    let output_file = output.join("coverage.json");
-    create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
-
     let coverage = get_percentage_of_covered_lines(stats.covered_lines, stats.total_lines);
 
-    let res = serde_json::to_writer(
-        &mut output_stream,
-        &CoverageData {
-            schema_version: 1,
-            label: "coverage",
-            message: format!("{:.precision$}%", coverage),
-            color: if coverage >= conf.hi_limit {
-                "green"
-            } else if coverage >= conf.med_limit {
-                "yellow"
-            } else {
-                "red"
-            },
+    let content = serde_json::to_vec(&CoverageData {
+        schema_version: 1,
+        label: "coverage",
+        message: format!("{:.precision$}%", coverage),
+        color: if coverage >= conf.hi_limit {
+            "green"
+        } else if coverage >= conf.med_limit {
+            "yellow"
+        } else {
+            "red"
         },
-    );
-
-    if res.is_err() {
-        eprintln!("cannot write the file {:?}", output_file);
-    }
+    })?;
+    write_output_file(&output.join("coverage.json"), &content)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::get_percentage_of_covered_lines;
+    use super::*;
 
     #[test]
     fn test_get_percentage_of_covered_lines() {
@@ -623,4 +629,140 @@ mod tests {
         assert_eq!(get_percentage_of_covered_lines(0, 0), 100.0);
         assert_eq!(get_percentage_of_covered_lines(5, 0), 100.0);
     }
+
+    #[test]
+    fn test_gen_html_branches() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_path = tmp_dir.path().join("foo.c");
+        std::fs::write(&source_path, "if (a) {\n  b();\n}\n").unwrap();
+        let result = CovResult {
+            lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+            branches: [(1, vec![true, false])].iter().cloned().collect(),
+            functions: Default::default(),
+            ..Default::default()
+        };
+        let (tera, conf) = get_config(None, &ReportMetadata::default());
+        let global = Arc::new(Mutex::new(HtmlGlobalStats::default()));
+
+        gen_html(
+            &tera,
+            &source_path,
+            &result,
+            &conf,
+            tmp_dir.path(),
+            Path::new("foo.c"),
+            global.clone(),
+            true,
+            2,
+            false,
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(tmp_dir.path().join("foo.c.html")).unwrap();
+        assert!(html.contains("title=\"1/2 branches taken\""));
+
+        gen_html(
+            &tera,
+            &source_path,
+            &result,
+            &conf,
+            tmp_dir.path(),
+            Path::new("foo.c"),
+            global,
+            false,
+            2,
+            false,
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(tmp_dir.path().join("foo.c.html")).unwrap();
+        assert!(!html.contains("branches taken"));
+    }
+
+    #[test]
+    fn test_gen_index_report_metadata() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let report = ReportMetadata {
+            title: Some("Nightly report".to_string()),
+            metadata: [("build".to_string(), "1234".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        let (tera, conf) = get_config(None, &report);
+
+        gen_index(
+            &tera,
+            &HtmlGlobalStats::default(),
+            &conf,
+            tmp_dir.path(),
+            false,
+            2,
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(tmp_dir.path().join("index.html")).unwrap();
+        assert!(html.contains("<title>Nightly report - Grcov report"));
+        assert!(html.contains("<h1 class=\"title\">Nightly report</h1>"));
+        assert!(html.contains("build: 1234"));
+
+        let (tera, conf) = get_config(None, &ReportMetadata::default());
+        gen_index(
+            &tera,
+            &HtmlGlobalStats::default(),
+            &conf,
+            tmp_dir.path(),
+            false,
+            2,
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(tmp_dir.path().join("index.html")).unwrap();
+        assert!(!html.contains("<h1"));
+    }
+
+    #[test]
+    fn test_gen_html_keep_not_existing() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_path = tmp_dir.path().join("missing.c");
+        let result = CovResult {
+            lines: [(1, 1), (3, 0)].iter().cloned().collect(),
+            branches: Default::default(),
+            functions: Default::default(),
+            ..Default::default()
+        };
+        let (tera, conf) = get_config(None, &ReportMetadata::default());
+        let global = Arc::new(Mutex::new(HtmlGlobalStats::default()));
+
+        gen_html(
+            &tera,
+            &source_path,
+            &result,
+            &conf,
+            tmp_dir.path(),
+            Path::new("missing.c"),
+            global.clone(),
+            false,
+            2,
+            false,
+        )
+        .unwrap();
+        assert!(!tmp_dir.path().join("missing.c.html").exists());
+        assert!(global.lock().unwrap().dirs.is_empty());
+
+        gen_html(
+            &tera,
+            &source_path,
+            &result,
+            &conf,
+            tmp_dir.path(),
+            Path::new("missing.c"),
+            global.clone(),
+            false,
+            2,
+            true,
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(tmp_dir.path().join("missing.c.html")).unwrap();
+        assert!(html.contains("Source unavailable"));
+        assert!(html.contains("id=\"3\""));
+        assert!(!html.contains("id=\"4\""));
+        assert_eq!(global.lock().unwrap().dirs[""].stats.total_lines, 2);
+    }
 }
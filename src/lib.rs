@@ -1,5 +1,6 @@
 #![recursion_limit = "1024"]
 #![allow(clippy::too_many_arguments)]
+#![allow(clippy::collapsible_match)]
 
 mod defs;
 pub use crate::defs::*;
@@ -35,30 +36,67 @@ pub use crate::reader::*;
 
 mod covdir;
 
+mod cache;
+pub use crate::cache::*;
+
+mod diff;
+pub use crate::diff::*;
+
+mod baseline;
+pub use crate::baseline::*;
+
+#[cfg(feature = "net")]
+mod download;
+
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "python")]
+pub use crate::python::*;
+
+#[cfg(feature = "blame")]
+mod blame;
+#[cfg(feature = "blame")]
+pub use crate::blame::*;
+
 pub mod html;
 
 mod file_filter;
 pub use crate::file_filter::*;
 
-use log::{error, warn};
+use crossbeam_channel::bounded;
+use log::{debug, error, warn};
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use serde_json::Value;
 use std::fs;
 use std::io::{BufReader, Cursor};
+use std::sync::Mutex;
+use std::thread;
 use std::{
-    collections::{btree_map, hash_map},
-    path::Path,
+    collections::{btree_map, hash_map, BTreeMap},
+    path::{Path, PathBuf},
 };
 use walkdir::WalkDir;
 
 // Merge results, without caring about duplicate lines (they will be removed at the end).
-pub fn merge_results(result: &mut CovResult, result2: CovResult) -> bool {
+pub fn merge_results(
+    result: &mut CovResult,
+    result2: CovResult,
+    merge_strategy: MergeStrategy,
+) -> bool {
     let mut warn_overflow = false;
     for (&line_no, &execution_count) in &result2.lines {
         match result.lines.entry(line_no) {
             btree_map::Entry::Occupied(c) => {
-                let v = c.get().checked_add(execution_count).unwrap_or_else(|| {
-                    warn_overflow = true;
-                    u64::MAX
-                });
+                let v = match merge_strategy {
+                    MergeStrategy::Sum => {
+                        c.get().checked_add(execution_count).unwrap_or_else(|| {
+                            warn_overflow = true;
+                            u64::MAX
+                        })
+                    }
+                    MergeStrategy::Max => (*c.get()).max(execution_count),
+                };
 
                 *c.into_mut() = v;
             }
@@ -68,20 +106,47 @@ pub fn merge_results(result: &mut CovResult, result2: CovResult) -> bool {
         };
     }
 
+    let mut branch_ids2 = result2.branch_ids;
     for (line_no, taken) in result2.branches {
+        let ids2 = branch_ids2
+            .remove(&line_no)
+            .filter(|ids2| ids2.len() == taken.len());
         match result.branches.entry(line_no) {
             btree_map::Entry::Occupied(c) => {
                 let v = c.into_mut();
-                for (x, y) in taken.iter().zip(v.iter_mut()) {
-                    *y |= x;
-                }
-                let l = v.len();
-                if taken.len() > l {
-                    v.extend(&taken[l..]);
+                let ids = result
+                    .branch_ids
+                    .get_mut(&line_no)
+                    .filter(|ids| ids.len() == v.len());
+                if let (Some(ids), Some(ids2)) = (ids, ids2) {
+                    // Both inputs tell which branch is which, they don't need to be in the same
+                    // order.
+                    for (id, x) in ids2.into_iter().zip(taken) {
+                        match ids.iter().position(|&other_id| other_id == id) {
+                            Some(i) => v[i] |= x,
+                            None => {
+                                ids.push(id);
+                                v.push(x);
+                            }
+                        }
+                    }
+                } else {
+                    for (x, y) in taken.iter().zip(v.iter_mut()) {
+                        *y |= x;
+                    }
+                    let l = v.len();
+                    if taken.len() > l {
+                        v.extend(&taken[l..]);
+                    }
+                    // The branches were matched by their position, so their ids don't apply.
+                    result.branch_ids.remove(&line_no);
                 }
             }
             btree_map::Entry::Vacant(v) => {
                 v.insert(taken);
+                if let Some(ids2) = ids2 {
+                    result.branch_ids.insert(line_no, ids2);
+                }
             }
         };
     }
@@ -95,13 +160,37 @@ pub fn merge_results(result: &mut CovResult, result2: CovResult) -> bool {
         };
     }
 
+    for (line_no, function) in result2.line_functions {
+        result.line_functions.entry(line_no).or_insert(function);
+    }
+
+    merge_line_tags(&mut result.line_tests, result2.line_tests);
+    merge_line_tags(&mut result.line_archives, result2.line_archives);
+
     warn_overflow
 }
 
+fn merge_line_tags(tags: &mut LineTags, tags2: LineTags) {
+    for (line_no, names) in tags2 {
+        tags.entry(line_no).or_default().extend(names);
+    }
+}
+
+/// Tags each covered line of `lines` with `name` (e.g. of the test or the input archive which
+/// executed it).
+fn tag_covered_lines(tags: &mut LineTags, lines: &BTreeMap<u32, u64>, name: &str) {
+    for (&line_no, &count) in lines {
+        if count > 0 {
+            tags.entry(line_no).or_default().insert(name.to_string());
+        }
+    }
+}
+
 fn add_results(
     results: Vec<(String, CovResult)>,
     result_map: &SyncCovResultMap,
     source_dir: Option<&Path>,
+    merge_strategy: MergeStrategy,
 ) {
     let mut map = result_map.lock().unwrap();
     let mut warn_overflow = false;
@@ -115,12 +204,17 @@ fn add_results(
                     result.0
                 }
             }
+            // Without a source directory, the paths can only be normalized lexically. Paths going
+            // up from the current directory can't be, so they're kept as they are.
+            None if !Path::new(&result.0).starts_with("..") => normalize_path(&result.0)
+                .and_then(|p| p.to_str().map(String::from))
+                .unwrap_or(result.0),
             None => result.0,
         };
         let entry = map.entry(path);
         match entry {
             hash_map::Entry::Occupied(obj) => {
-                warn_overflow |= merge_results(obj.into_mut(), result.1);
+                warn_overflow |= merge_results(obj.into_mut(), result.1, merge_strategy);
             }
             hash_map::Entry::Vacant(v) => {
                 v.insert(result.1);
@@ -155,41 +249,103 @@ enum GcovType {
     MultipleFiles,
 }
 
+pub(crate) fn report_error(
+    parse_errors: &SyncParseErrors,
+    file: &str,
+    format: ItemFormat,
+    error: String,
+) {
+    parse_errors.lock().unwrap().push(ParseError {
+        file: file.to_string(),
+        format: format.to_string(),
+        error,
+    });
+}
+
 macro_rules! try_parse {
-    ($v:expr, $f:expr) => {
+    ($v:expr, $f:expr, $format:expr, $errors:expr) => {
         match $v {
             Ok(val) => val,
             Err(err) => {
                 error!("Error parsing file {}: {}", $f, err);
+                report_error($errors, &$f, $format, err.to_string());
                 continue;
             }
         }
     };
 }
 
+/// Parses the work items received from the producer with the parsing options of `config`, and
+/// merges their results into `result_map`.
 pub fn consumer(
     working_dir: &Path,
-    source_dir: Option<&Path>,
     result_map: &SyncCovResultMap,
     receiver: JobReceiver,
-    branch_enabled: bool,
-    guess_directory: bool,
-    binary_path: Option<&Path>,
+    parse_errors: &SyncParseErrors,
+    config: &GrcovConfig,
 ) {
+    let source_dir = config.source_dir.as_deref();
+    let branch_enabled = config.branch_enabled;
+    let guess_directory = config.guess_directory;
+    let binary_paths = &config.binary_paths;
+    let cache = config.cache.as_ref();
+    let exclude_branch_throw = config.exclude_branch_throw;
+    let keep_branch_ids = config.merge_branches_by_id;
+    let lenient_lcov = config.lenient_lcov;
+    let merge_strategy = config.merge_strategy;
+    let exclude_test_coverage = config.exclude_test_coverage.as_ref();
+    let attribute_headers_to_includers = config.attribute_headers_to_includers;
+    let per_test_profraw = config.per_test_profraw;
     let mut gcov_type = GcovType::Unknown;
+    let parse_lcov_content = |buffer| match exclude_test_coverage {
+        Some(excluded_tests) => parse_lcov_excluding_tests(
+            buffer,
+            branch_enabled,
+            lenient_lcov,
+            keep_branch_ids,
+            excluded_tests,
+        ),
+        None => parse_lcov(buffer, branch_enabled, lenient_lcov, keep_branch_ids),
+    };
 
     while let Ok(work_item) = receiver.recv() {
         if work_item.is_none() {
             break;
         }
         let work_item = work_item.unwrap();
-        let new_results = match work_item.format {
+
+        let cache_key = cache.and_then(|_| {
+            get_cache_key(
+                &work_item,
+                branch_enabled,
+                guess_directory,
+                exclude_branch_throw,
+                keep_branch_ids,
+                attribute_headers_to_includers,
+            )
+        });
+        if let Some(mut cached_results) = cache
+            .zip(cache_key.as_deref())
+            .and_then(|(cache, key)| cache.get(key))
+        {
+            if let Some(archive) = &work_item.archive {
+                for (_, result) in &mut cached_results {
+                    tag_covered_lines(&mut result.line_archives, &result.lines, archive);
+                }
+            }
+            add_results(cached_results, result_map, source_dir, merge_strategy);
+            continue;
+        }
+        let error_count = parse_errors.lock().unwrap().len();
+
+        let mut new_results = match work_item.format {
             ItemFormat::Gcno => {
                 match work_item.item {
                     ItemType::Path((stem, gcno_path)) => {
                         // GCC
                         if let Err(e) = run_gcov(&gcno_path, branch_enabled, working_dir) {
                             error!("Error when running gcov: {}", e);
+                            report_error(parse_errors, &stem, work_item.format, e.to_string());
                             continue;
                         };
                         let gcov_ext = get_gcov_output_ext();
@@ -207,13 +363,15 @@ pub fn consumer(
                         let mut new_results = if gcov_type == GcovType::SingleFile {
                             let new_results = try_parse!(
                                 if gcov_ext.ends_with("gz") {
-                                    parse_gcov_gz(&gcov_path)
+                                    parse_gcov_gz(&gcov_path, exclude_branch_throw, keep_branch_ids)
                                 } else if gcov_ext.ends_with("gcov") {
-                                    parse_gcov(&gcov_path)
+                                    parse_gcov(&gcov_path, exclude_branch_throw)
                                 } else {
                                     panic!("Invalid gcov extension: {}", gcov_ext);
                                 },
-                                work_item.name
+                                work_item.name,
+                                work_item.format,
+                                parse_errors
                             );
                             fs::remove_file(gcov_path).unwrap();
                             new_results
@@ -226,11 +384,17 @@ pub fn consumer(
 
                                 new_results.append(&mut try_parse!(
                                     if gcov_path.extension().unwrap() == "gz" {
-                                        parse_gcov_gz(gcov_path)
+                                        parse_gcov_gz(
+                                            gcov_path,
+                                            exclude_branch_throw,
+                                            keep_branch_ids,
+                                        )
                                     } else {
-                                        parse_gcov(gcov_path)
+                                        parse_gcov(gcov_path, exclude_branch_throw)
                                     },
-                                    work_item.name
+                                    work_item.name,
+                                    work_item.format,
+                                    parse_errors
                                 ));
 
                                 fs::remove_file(gcov_path).unwrap();
@@ -253,6 +417,9 @@ pub fn consumer(
                             branch_enabled,
                         ) {
                             Ok(mut r) => {
+                                if attribute_headers_to_includers {
+                                    attribute_headers_to_includer(&mut r);
+                                }
                                 if guess_directory {
                                     rename_single_files(&mut r, &buffers.stem);
                                 }
@@ -261,6 +428,12 @@ pub fn consumer(
                             Err(e) => {
                                 // Just print the error, don't panic and continue
                                 error!("Error in computing counters: {}", e);
+                                report_error(
+                                    parse_errors,
+                                    &buffers.stem,
+                                    work_item.format,
+                                    e.to_string(),
+                                );
                                 Vec::new()
                             }
                         }
@@ -276,31 +449,56 @@ pub fn consumer(
                 }
             }
             ItemFormat::Profraw => {
-                if binary_path.is_none() {
+                if binary_paths.is_empty() {
                     error!("The path to the compiled binary must be given as an argument when source-based coverage is used");
+                    report_error(
+                        parse_errors,
+                        &work_item.name,
+                        work_item.format,
+                        "The path to the compiled binary wasn't given".to_string(),
+                    );
                     continue;
                 }
 
                 if let ItemType::Paths(profraw_paths) = work_item.item {
                     match llvm_tools::profraws_to_lcov(
                         profraw_paths.as_slice(),
-                        binary_path.as_ref().unwrap(),
+                        binary_paths,
                         working_dir,
+                        per_test_profraw,
                     ) {
                         Ok(lcovs) => {
                             let mut new_results: Vec<(String, CovResult)> = Vec::new();
 
-                            for lcov in lcovs {
-                                new_results.append(&mut try_parse!(
-                                    parse_lcov(lcov, branch_enabled),
-                                    work_item.name
-                                ));
+                            for (test_name, lcov) in lcovs {
+                                let mut results = try_parse!(
+                                    parse_lcov_content(lcov),
+                                    work_item.name,
+                                    work_item.format,
+                                    parse_errors
+                                );
+                                if let Some(test_name) = test_name {
+                                    for (_, result) in &mut results {
+                                        tag_covered_lines(
+                                            &mut result.line_tests,
+                                            &result.lines,
+                                            &test_name,
+                                        );
+                                    }
+                                }
+                                new_results.append(&mut results);
                             }
 
                             new_results
                         }
                         Err(e) => {
                             error!("Error while executing llvm tools: {}", e);
+                            report_error(
+                                parse_errors,
+                                &work_item.name,
+                                work_item.format,
+                                e.to_string(),
+                            );
                             continue;
                         }
                     }
@@ -309,13 +507,59 @@ pub fn consumer(
                     continue;
                 }
             }
+            ItemFormat::Gcov => {
+                if let ItemType::Path((_, gcov_path)) = work_item.item {
+                    try_parse!(
+                        if gcov_path.extension().is_some_and(|ext| ext == "gz") {
+                            parse_gcov_gz(&gcov_path, exclude_branch_throw, keep_branch_ids)
+                        } else {
+                            parse_gcov(&gcov_path, exclude_branch_throw)
+                        },
+                        work_item.name,
+                        work_item.format,
+                        parse_errors
+                    )
+                } else {
+                    error!("Invalid content type");
+                    continue;
+                }
+            }
+            #[cfg(feature = "python")]
+            ItemFormat::PythonCoverage => {
+                if let ItemType::Path((_, path)) = work_item.item {
+                    try_parse!(
+                        parse_python_coverage(&path),
+                        work_item.name,
+                        work_item.format,
+                        parse_errors
+                    )
+                } else {
+                    error!("Invalid content type");
+                    continue;
+                }
+            }
+            #[cfg(not(feature = "python"))]
+            ItemFormat::PythonCoverage => {
+                error!("grcov was built without the python feature");
+                continue;
+            }
             ItemFormat::Info | ItemFormat::JacocoXml => {
                 if let ItemType::Content(content) = work_item.item {
                     if work_item.format == ItemFormat::Info {
-                        try_parse!(parse_lcov(content, branch_enabled), work_item.name)
+                        try_parse!(
+                            parse_lcov_content(content),
+                            work_item.name,
+                            work_item.format,
+                            parse_errors
+                        )
                     } else {
                         let buffer = BufReader::new(Cursor::new(content));
-                        try_parse!(parse_jacoco_xml_report(buffer), work_item.name)
+                        try_parse!(
+                            parse_jacoco_xml_report(buffer),
+                            work_item.name,
+                            work_item.format,
+                            parse_errors
+                        )
                     }
                 } else {
                     error!("Invalid content type");
@@ -324,10 +568,156 @@ pub fn consumer(
             }
         };
 
-        add_results(new_results, result_map, source_dir);
+        if let Some((cache, key)) = cache.zip(cache_key.as_deref()) {
+            // Don't cache incomplete results, so that the errors are reported again.
+            if parse_errors.lock().unwrap().len() == error_count {
+                cache.put(key, &new_results);
+            }
+        }
+
+        if let Some(archive) = &work_item.archive {
+            for (_, result) in &mut new_results {
+                tag_covered_lines(&mut result.line_archives, &result.lines, archive);
+            }
+        }
+        add_results(new_results, result_map, source_dir, merge_strategy);
     }
 }
 
+/// The options of `collect_coverage`.
+#[derive(Clone, Debug, Default)]
+pub struct GrcovConfig {
+    /// The input paths (files, directories or ZIP archives).
+    pub paths: Vec<String>,
+    /// The number of threads parsing the inputs, one less than the number of CPUs by default.
+    pub num_threads: Option<usize>,
+    /// The root of the sources, used to find the source files of the gcno files.
+    pub source_dir: Option<PathBuf>,
+    /// The compiled binaries (or directories containing them), for source-based coverage.
+    pub binary_paths: Vec<PathBuf>,
+    pub branch_enabled: bool,
+    pub guess_directory: bool,
+    pub ignore_orphan_gcno: bool,
+    /// Whether the coverage data is exclusively coming from a LLVM build.
+    pub is_llvm: bool,
+    pub cache: Option<ResultCache>,
+    pub exclude_branch_throw: bool,
+    /// Keeps the (block, branch) ids of the branches of the lcov and gcov JSON inputs, so that the
+    /// branches of the same line are merged by them rather than by their position.
+    pub merge_branches_by_id: bool,
+    pub lenient_lcov: bool,
+    /// The format to parse all the plain files with, whatever their extension or content.
+    pub input_format: Option<ItemFormat>,
+    /// How the results of the same file in several inputs are merged.
+    pub merge_strategy: MergeStrategy,
+    /// The tests whose coverage is excluded from the lcov inputs.
+    pub exclude_test_coverage: Option<Regex>,
+    /// Experimental: moves the functions defined in headers to the file including them, for the
+    /// gcno files parsed by grcov itself (i.e. the LLVM ones).
+    pub attribute_headers_to_includers: bool,
+    /// Converts each profraw on its own, keeping the tests executing each line.
+    pub per_test_profraw: bool,
+    /// The checksums the inputs are verified against.
+    pub manifest: Option<Manifest>,
+    /// Records the input archives executing each line.
+    pub per_archive: bool,
+    /// Copies the files of the input directories to the temporary directory when they need to be
+    /// extracted, instead of symlinking them.
+    pub copy_extract: bool,
+}
+
+/// The results collected by `collect_coverage`, before their paths are rewritten.
+#[derive(Debug, Default)]
+pub struct GrcovResults {
+    pub result_map: CovResultMap,
+    /// The content of the `linked-files-map.json` file found in the inputs, if any.
+    pub path_mapping: Option<Value>,
+    pub parse_errors: Vec<ParseError>,
+    /// Whether no coverage artifacts were found in the inputs, the results are then empty.
+    pub no_input_files: bool,
+}
+
+/// Parses all the inputs of `config`, running the producer and the consumers in their own
+/// threads. Returns an error if one of them panicked.
+pub fn collect_coverage(config: &GrcovConfig) -> Result<GrcovResults, String> {
+    let num_threads = config
+        .num_threads
+        .unwrap_or_else(|| 1.max(num_cpus::get() - 1));
+
+    let tmp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let tmp_path = tmp_dir.path();
+
+    let result_map: SyncCovResultMap = Mutex::new(FxHashMap::with_capacity_and_hasher(
+        20_000,
+        Default::default(),
+    ));
+    let parse_errors: SyncParseErrors = Mutex::new(Vec::new());
+    let (sender, receiver) = bounded(2 * num_threads);
+
+    let path_mapping = thread::scope(|scope| {
+        let producer = thread::Builder::new()
+            .name(String::from("Producer"))
+            .spawn_scoped(scope, || producer(tmp_path, &sender, &parse_errors, config))
+            .unwrap();
+
+        let mut parsers = Vec::new();
+        for i in 0..num_threads {
+            let receiver = receiver.clone();
+            let working_dir = tmp_path.join(format!("{}", i));
+            let result_map = &result_map;
+            let parse_errors = &parse_errors;
+
+            let t = thread::Builder::new()
+                .name(format!("Consumer {}", i))
+                .spawn_scoped(scope, move || {
+                    fs::create_dir(&working_dir).expect("Failed to create working directory");
+                    consumer(&working_dir, result_map, receiver, parse_errors, config);
+                })
+                .unwrap();
+
+            parsers.push(t);
+        }
+
+        let path_mapping = producer.join();
+
+        // Poison the receiver, now that the producer is finished.
+        for _ in 0..num_threads {
+            sender.send(None).unwrap();
+        }
+
+        // Join all the consumers, otherwise the scope would panic.
+        let results: Vec<_> = parsers.into_iter().map(|parser| parser.join()).collect();
+        if results.iter().any(Result::is_err) {
+            return Err("A consumer thread panicked".to_string());
+        }
+
+        path_mapping.map_err(|_| "The producer thread panicked".to_string())
+    })?;
+
+    let (path_mapping, no_input_files) = match path_mapping {
+        Ok(path_mapping) => (path_mapping, false),
+        Err(e) if e == NO_INPUT_FILES => {
+            debug!("{}", e);
+            (None, true)
+        }
+        Err(e) => return Err(e),
+    };
+    let path_mapping = match path_mapping {
+        Some(path_mapping) => Some(
+            serde_json::from_slice(&path_mapping)
+                .map_err(|e| format!("Failed to parse the path mapping: {}", e))?,
+        ),
+        None => None,
+    };
+
+    Ok(GrcovResults {
+        result_map: result_map.into_inner().unwrap(),
+        path_mapping,
+        parse_errors: parse_errors.into_inner().unwrap(),
+        no_input_files,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,6 +754,7 @@ mod tests {
             .cloned()
             .collect(),
             functions: functions1,
+            ..Default::default()
         };
         let mut functions2: FunctionMap = FxHashMap::default();
         functions2.insert(
@@ -394,9 +785,10 @@ mod tests {
             .cloned()
             .collect(),
             functions: functions2,
+            ..Default::default()
         };
 
-        merge_results(&mut result, result2);
+        merge_results(&mut result, result2, MergeStrategy::Sum);
         assert_eq!(
             result.lines,
             [(1, 42), (2, 7), (3, 42), (4, 7), (7, 0), (8, 0)]
@@ -426,13 +818,126 @@ mod tests {
         assert!(func.executed);
     }
 
+    #[test]
+    fn test_merge_results_max() {
+        let mut result = CovResult {
+            lines: [(1, 21), (2, 7), (3, u64::MAX)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        let result2 = CovResult {
+            lines: [(1, 3), (2, 9), (3, 1), (4, 0)].iter().cloned().collect(),
+            ..Default::default()
+        };
+
+        assert!(!merge_results(&mut result, result2, MergeStrategy::Max));
+        assert_eq!(
+            result.lines,
+            [(1, 21), (2, 9), (3, u64::MAX), (4, 0)]
+                .iter()
+                .cloned()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_merge_results_line_tests() {
+        let mut result = CovResult {
+            lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        tag_covered_lines(&mut result.line_tests, &result.lines, "test_a");
+        let mut result2 = CovResult {
+            lines: [(1, 2), (2, 1)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        tag_covered_lines(&mut result2.line_tests, &result2.lines, "test_b");
+
+        merge_results(&mut result, result2, MergeStrategy::Sum);
+        let tests = |line_no| -> Vec<&str> {
+            result.line_tests[&line_no]
+                .iter()
+                .map(String::as_str)
+                .collect()
+        };
+        assert_eq!(tests(1), vec!["test_a", "test_b"]);
+        assert_eq!(tests(2), vec!["test_b"]);
+    }
+
+    #[test]
+    fn test_merge_results_line_archives() {
+        let mut result = CovResult {
+            lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        tag_covered_lines(&mut result.line_archives, &result.lines, "shard1.zip");
+        let mut result2 = CovResult {
+            lines: [(1, 2), (2, 1)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        tag_covered_lines(&mut result2.line_archives, &result2.lines, "shard2.zip");
+
+        merge_results(&mut result, result2, MergeStrategy::Sum);
+        let archives = |line_no| -> Vec<&str> {
+            result.line_archives[&line_no]
+                .iter()
+                .map(String::as_str)
+                .collect()
+        };
+        assert_eq!(archives(1), vec!["shard1.zip", "shard2.zip"]);
+        assert_eq!(archives(2), vec!["shard2.zip"]);
+    }
+
+    #[test]
+    fn test_merge_results_branch_ids() {
+        let mut result = CovResult {
+            branches: [(1, vec![true, false]), (2, vec![false, true])]
+                .iter()
+                .cloned()
+                .collect(),
+            branch_ids: [(1, vec![(0, 0), (0, 1)]), (2, vec![(0, 0), (0, 1)])]
+                .iter()
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        // The first line has a block the other input doesn't have, and its branches in another
+        // order. The second line has no ids, so its branches are merged by position.
+        let result2 = CovResult {
+            branches: [(1, vec![false, true, true]), (2, vec![true, false])]
+                .iter()
+                .cloned()
+                .collect(),
+            branch_ids: [(1, vec![(1, 0), (0, 1), (0, 0)])]
+                .iter()
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        merge_results(&mut result, result2, MergeStrategy::Sum);
+        assert_eq!(
+            result.branches,
+            [(1, vec![true, true, false]), (2, vec![true, true])]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert_eq!(
+            result.branch_ids,
+            [(1, vec![(0, 0), (0, 1), (1, 0)])]
+                .iter()
+                .cloned()
+                .collect()
+        );
+    }
+
     #[test]
     fn test_merge_relative_path() {
         let mut f = File::open("./test/relative_path/relative_path.info")
             .expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, false).unwrap();
+        let results = parse_lcov(buf, false, false, false).unwrap();
         let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
             FxHashMap::with_capacity_and_hasher(1, Default::default()),
         ));
@@ -440,6 +945,7 @@ mod tests {
             results,
             &result_map,
             Some(Path::new("./test/relative_path")),
+            MergeStrategy::Sum,
         );
         let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
 
@@ -461,18 +967,107 @@ mod tests {
     }
 
     #[test]
-    fn test_ignore_relative_path() {
+    fn test_merge_relative_path_without_source_dir() {
         let mut f = File::open("./test/relative_path/relative_path.info")
             .expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, false).unwrap();
+        let mut results = parse_lcov(buf, false, false, false).unwrap();
+        results.push(("../oof.cpp".to_string(), results[0].1.clone()));
         let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
-            FxHashMap::with_capacity_and_hasher(3, Default::default()),
+            FxHashMap::with_capacity_and_hasher(2, Default::default()),
         ));
-        add_results(results, &result_map, None);
+        add_results(results, &result_map, None, MergeStrategy::Sum);
         let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
 
-        assert!(result_map.len() == 3);
+        assert_eq!(result_map.len(), 2);
+        let cov_result = result_map.get("foo/bar/oof.cpp").unwrap();
+        assert_eq!(
+            cov_result.lines,
+            [(1, 63), (2, 63), (3, 84), (4, 42)]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert!(result_map.contains_key("../oof.cpp"));
+    }
+
+    #[test]
+    fn test_collect_coverage() {
+        let config = GrcovConfig {
+            paths: vec![
+                "./test/relative_path/relative_path.info".to_string(),
+                "./test/invalid_DA_record.info".to_string(),
+            ],
+            num_threads: Some(2),
+            ..Default::default()
+        };
+        let results = collect_coverage(&config).unwrap();
+
+        assert_eq!(results.result_map.len(), 1);
+        assert_eq!(results.path_mapping, None);
+        assert_eq!(results.parse_errors.len(), 1);
+        assert!(results.parse_errors[0]
+            .file
+            .ends_with("invalid_DA_record.info"));
+    }
+
+    #[test]
+    fn test_collect_coverage_no_input_files() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let config = GrcovConfig {
+            paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+            num_threads: Some(1),
+            ..Default::default()
+        };
+        let results = collect_coverage(&config).unwrap();
+
+        assert!(results.no_input_files);
+        assert!(results.result_map.is_empty());
+    }
+
+    #[test]
+    fn test_collect_coverage_merge_branches_by_id() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        // The second report has a branch of another block, and the branches in another order.
+        fs::write(
+            tmp_dir.path().join("a.info"),
+            "SF:a.c\nDA:1,1\nBRDA:1,0,0,1\nBRDA:1,0,1,-\nend_of_record\n",
+        )
+        .unwrap();
+        fs::write(
+            tmp_dir.path().join("b.info"),
+            "SF:a.c\nDA:1,1\nBRDA:1,0,1,1\nBRDA:1,1,0,-\nend_of_record\n",
+        )
+        .unwrap();
+
+        // By position, the taken branch of the second report is mixed up with the first branch.
+        for (merge_branches_by_id, expected) in
+            [(false, vec![true, false]), (true, vec![true, true, false])]
+        {
+            let config = GrcovConfig {
+                paths: vec![tmp_dir.path().to_str().unwrap().to_string()],
+                num_threads: Some(1),
+                branch_enabled: true,
+                merge_branches_by_id,
+                ..Default::default()
+            };
+            let results = collect_coverage(&config).unwrap();
+            assert_eq!(results.result_map["a.c"].branches[&1], expected);
+        }
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_collect_coverage_producer_error() {
+        let config = GrcovConfig {
+            paths: vec!["https://example.com/".to_string()],
+            num_threads: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            collect_coverage(&config).unwrap_err(),
+            "Cannot load 'https://example.com/': the URL has no file name."
+        );
     }
 }
@@ -1,19 +1,108 @@
 use once_cell::sync::OnceCell;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use std::env;
 use std::env::consts::EXE_SUFFIX;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fs;
-use std::io::Write;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::warn;
+use log::{error, warn};
 use walkdir::WalkDir;
 
 pub static LLVM_PATH: OnceCell<PathBuf> = OnceCell::new();
 
+/// Suffix of the names of the LLVM tools, e.g. "-17" for `llvm-cov-17`. The unsuffixed names are
+/// used when the suffixed ones aren't found.
+pub static LLVM_TOOL_SUFFIX: OnceCell<String> = OnceCell::new();
+
+/// Maximum time the gcov and llvm subprocesses are allowed to run for, unlimited if unset.
+pub static SUBPROCESS_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn wait_with_optional_timeout(
+    mut child: Child,
+    command: &Command,
+    timeout: Option<Duration>,
+) -> io::Result<Output> {
+    let Some(timeout) = timeout else {
+        return child.wait_with_output();
+    };
+
+    // Read the pipes while waiting, so the child doesn't block on a full pipe.
+    drop(child.stdin.take());
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            error!(
+                "{:?} timed out after {} seconds, killing it",
+                command,
+                timeout.as_secs()
+            );
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Timed out after {} seconds", timeout.as_secs()),
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    })
+}
+
+fn output_with_optional_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> io::Result<Output> {
+    if timeout.is_none() {
+        return command.output();
+    }
+
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    wait_with_optional_timeout(child, command, timeout)
+}
+
+/// Waits for the child to exit and collects its output like `Child::wait_with_output`, killing it
+/// if it runs for longer than `SUBPROCESS_TIMEOUT`.
+pub fn wait_with_timeout(child: Child, command: &Command) -> io::Result<Output> {
+    wait_with_optional_timeout(child, command, SUBPROCESS_TIMEOUT.get().copied())
+}
+
+/// Runs the command and collects its output like `Command::output`, killing it if it runs for
+/// longer than `SUBPROCESS_TIMEOUT`.
+pub fn output_with_timeout(command: &mut Command) -> io::Result<Output> {
+    output_with_optional_timeout(command, SUBPROCESS_TIMEOUT.get().copied())
+}
+
 pub fn is_binary(path: impl AsRef<Path>) -> bool {
     if let Ok(oty) = infer::get_from_path(path) {
         if let Some("dll" | "exe" | "elf" | "mach") = oty.map(|x| x.extension()) {
@@ -36,14 +125,14 @@ pub fn run_with_stdin(
         .stdin(Stdio::piped())
         .stdout(Stdio::piped());
     let mut child = command.spawn().map_err(err_fn)?;
-    child
-        .stdin
-        .as_mut()
-        .unwrap()
-        .write_all(stdin.as_ref().as_bytes())
-        .map_err(err_fn)?;
-
-    let output = child.wait_with_output().map_err(err_fn)?;
+    // Write the input from another thread, so that a child which doesn't read it can't block us
+    // before the timeout starts. The pipe is closed once the input is written.
+    let mut child_stdin = child.stdin.take().unwrap();
+    let stdin = stdin.as_ref().to_string();
+    let writer = thread::spawn(move || child_stdin.write_all(stdin.as_bytes()));
+
+    let output = wait_with_timeout(child, &command).map_err(err_fn)?;
+    writer.join().unwrap().map_err(err_fn)?;
     if !output.status.success() {
         return Err(format!(
             "Failure while running {:?}\n{}",
@@ -59,8 +148,7 @@ pub fn run(cmd: impl AsRef<OsStr>, args: &[&OsStr]) -> Result<Vec<u8>, String> {
     let mut command = Command::new(cmd);
     command.args(args);
 
-    let output = command
-        .output()
+    let output = output_with_timeout(&mut command)
         .map_err(|e| format!("Failed to execute {:?}\n{}", command, e))?;
 
     if !output.status.success() {
@@ -74,13 +162,111 @@ pub fn run(cmd: impl AsRef<OsStr>, args: &[&OsStr]) -> Result<Vec<u8>, String> {
     Ok(output.stdout)
 }
 
-pub fn profraws_to_lcov(
+/// Extensions of the build artifacts which aren't binaries, but are next to them (e.g. in
+/// `target/debug/deps`).
+const NON_BINARY_EXTENSIONS: &[&str] = &["d", "rlib", "rmeta"];
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+/// Returns the binary itself, or the binaries in the directory, skipping the non-executable files
+/// (e.g. object files) and the other build artifacts.
+fn find_binaries(binary_path: &Path) -> Vec<PathBuf> {
+    let metadata = fs::metadata(binary_path)
+        .unwrap_or_else(|e| panic!("Failed to open directory '{:?}': {:?}.", binary_path, e));
+
+    if metadata.is_file() {
+        vec![binary_path.to_owned()]
+    } else {
+        let mut paths = vec![];
+
+        for entry in WalkDir::new(binary_path).follow_links(true) {
+            let entry = entry
+                .unwrap_or_else(|e| panic!("Failed to open directory '{:?}': {}", binary_path, e));
+
+            if entry
+                .path()
+                .extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|ext| NON_BINARY_EXTENSIONS.contains(&ext))
+            {
+                continue;
+            }
+            let metadata = entry.metadata().unwrap();
+            if metadata.is_file()
+                && metadata.len() > 0
+                && is_executable(&metadata)
+                && is_binary(entry.path())
+            {
+                paths.push(entry.into_path());
+            }
+        }
+
+        paths
+    }
+}
+
+/// Returns the length of the stem of `binary` if the file name of `profraw` starts with it, followed
+/// by a separator or by the extension.
+fn get_matching_stem_len(binary: &Path, profraw: &Path) -> Option<usize> {
+    let stem = binary.file_stem()?.to_str()?;
+    let name = profraw.file_name()?.to_str()?;
+    let rest = name.strip_prefix(stem)?;
+    match rest.chars().next() {
+        Some('-' | '_' | '.') => Some(stem.len()),
+        _ => None,
+    }
+}
+
+/// Groups the profraws with the binaries which produced them.
+///
+/// A profraw belongs to the binary whose file stem its file name starts with (e.g. the binary
+/// `my_test` produces `my_test-%p.profraw` when `LLVM_PROFILE_FILE` is set accordingly), picking
+/// the longest stem when several match. The binaries without any such profraw are exported
+/// together against all the profraws, as their coverage may have been recorded by the other
+/// binaries (e.g. for a shared library) or in profraws named differently.
+fn group_profraws_by_binary(
     profraw_paths: &[PathBuf],
-    binary_path: &Path,
-    working_dir: &Path,
-) -> Result<Vec<Vec<u8>>, String> {
-    let profdata_path = working_dir.join("grcov.profdata");
+    binaries: Vec<PathBuf>,
+) -> Vec<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut matched: Vec<Vec<PathBuf>> = vec![Vec::new(); binaries.len()];
+
+    for profraw in profraw_paths {
+        let owner = binaries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, binary)| get_matching_stem_len(binary, profraw).map(|len| (len, i)))
+            .max();
+        if let Some((_, i)) = owner {
+            matched[i].push(profraw.clone());
+        }
+    }
 
+    let mut groups = Vec::new();
+    let mut others = Vec::new();
+    for (binary, profraws) in binaries.into_iter().zip(matched) {
+        if profraws.is_empty() {
+            others.push(binary);
+        } else {
+            groups.push((vec![binary], profraws));
+        }
+    }
+    if !others.is_empty() {
+        groups.push((others, profraw_paths.to_vec()));
+    }
+
+    groups
+}
+
+fn merge_profraws(profraw_paths: &[PathBuf], profdata_path: &Path) -> Result<(), String> {
     let args = vec![
         "merge".as_ref(),
         "-f".as_ref(),
@@ -98,40 +284,51 @@ pub fn profraws_to_lcov(
 
     get_profdata_path().and_then(|p| run_with_stdin(p, &stdin_paths, &args))?;
 
-    let metadata = fs::metadata(binary_path)
-        .unwrap_or_else(|e| panic!("Failed to open directory '{:?}': {:?}.", binary_path, e));
+    Ok(())
+}
 
-    let binaries = if metadata.is_file() {
-        vec![binary_path.to_owned()]
-    } else {
-        let mut paths = vec![];
+fn export_lcov(
+    cov_tool_path: &Path,
+    binaries: &[PathBuf],
+    profdata_path: &Path,
+) -> Result<Vec<u8>, String> {
+    let mut args: Vec<&OsStr> = vec!["export".as_ref(), binaries[0].as_ref()];
+    for binary in &binaries[1..] {
+        args.push("-object".as_ref());
+        args.push(binary.as_ref());
+    }
+    args.extend_from_slice(&[
+        "--instr-profile".as_ref(),
+        profdata_path.as_ref(),
+        "--format".as_ref(),
+        "lcov".as_ref(),
+    ]);
 
-        for entry in WalkDir::new(binary_path).follow_links(true) {
-            let entry = entry
-                .unwrap_or_else(|e| panic!("Failed to open directory '{:?}': {}", binary_path, e));
+    run(cov_tool_path, &args)
+}
 
-            if is_binary(entry.path()) && entry.metadata().unwrap().len() > 0 {
-                paths.push(entry.into_path());
-            }
+/// A lcov exported by `llvm-cov`, with the name of its test if it was converted on its own.
+pub type TestLcov = (Option<String>, Vec<u8>);
+
+/// Exports the lcov of a group of binaries sharing the same profdata.
+///
+/// The binaries of a group are exported together with `-object`. If that fails (e.g. because
+/// one of them isn't instrumented), they are exported one by one, suppressing their errors.
+fn export_group_lcov(
+    cov_tool_path: &Path,
+    binaries: &[PathBuf],
+    profdata_path: &Path,
+) -> Vec<Vec<u8>> {
+    if binaries.len() > 1 {
+        if let Ok(result) = export_lcov(cov_tool_path, binaries, profdata_path) {
+            return vec![result];
         }
+    }
 
-        paths
-    };
-
-    let cov_tool_path = get_cov_path()?;
-    let results = binaries
-        .into_par_iter()
+    binaries
+        .par_iter()
         .filter_map(|binary| {
-            let args = [
-                "export".as_ref(),
-                binary.as_ref(),
-                "--instr-profile".as_ref(),
-                profdata_path.as_ref(),
-                "--format".as_ref(),
-                "lcov".as_ref(),
-            ];
-
-            match run(&cov_tool_path, &args) {
+            match export_lcov(cov_tool_path, std::slice::from_ref(binary), profdata_path) {
                 Ok(result) => Some(result),
                 Err(err_str) => {
                     warn!(
@@ -142,7 +339,53 @@ pub fn profraws_to_lcov(
                 }
             }
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+/// Converts the profraws to lcov with `llvm-cov`, once for each group of binaries sharing the same
+/// profraws (see `group_profraws_by_binary`), returning them with the name of their test.
+///
+/// With `per_test`, the profraws aren't merged: each one (e.g. written by a test) is converted on
+/// its own, tagged with its file stem as the test name (in a TN record of the lcov too).
+pub fn profraws_to_lcov(
+    profraw_paths: &[PathBuf],
+    binary_paths: &[PathBuf],
+    working_dir: &Path,
+    per_test: bool,
+) -> Result<Vec<TestLcov>, String> {
+    let binaries: Vec<PathBuf> = binary_paths
+        .iter()
+        .flat_map(|binary_path| find_binaries(binary_path))
+        .collect();
+    let groups = group_profraws_by_binary(profraw_paths, binaries);
+
+    let cov_tool_path = get_cov_path()?;
+    let mut results = Vec::new();
+    for (i, (binaries, profraws)) in groups.into_iter().enumerate() {
+        if !per_test {
+            let profdata_path = working_dir.join(format!("grcov_{}.profdata", i));
+            merge_profraws(&profraws, &profdata_path)?;
+            results.extend(
+                export_group_lcov(&cov_tool_path, &binaries, &profdata_path)
+                    .into_iter()
+                    .map(|lcov| (None, lcov)),
+            );
+            continue;
+        }
+
+        for (j, profraw) in profraws.iter().enumerate() {
+            let test_name = profraw
+                .file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+            let profdata_path = working_dir.join(format!("grcov_{}_{}.profdata", i, j));
+            merge_profraws(std::slice::from_ref(profraw), &profdata_path)?;
+            for lcov in export_group_lcov(&cov_tool_path, &binaries, &profdata_path) {
+                let mut tagged = format!("TN:{}\n", test_name).into_bytes();
+                tagged.extend(lcov);
+                results.push((Some(test_name.clone()), tagged));
+            }
+        }
+    }
 
     Ok(results)
 }
@@ -172,34 +415,48 @@ fn llvm_tool_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
     Ok(path)
 }
 
-fn get_profdata_path() -> Result<PathBuf, String> {
-    let path = if let Some(mut path) = LLVM_PATH.get().cloned() {
-        path.push(format!("llvm-profdata{}", EXE_SUFFIX));
+/// Finds the executable with the given name in the directories of the PATH.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Finds a LLVM tool (e.g. "cov" for `llvm-cov`): the one in the `--llvm-path` directory, the
+/// binary given by the `env_var` environment variable, or the one in the PATH with a
+/// `--llvm-tool-suffix`, or in the Rust toolchain.
+fn get_tool_path(name: &str, env_var: &str) -> Result<PathBuf, String> {
+    let suffixed_name = LLVM_TOOL_SUFFIX
+        .get()
+        .map(|suffix| format!("llvm-{}{}{}", name, suffix, EXE_SUFFIX));
+    let path = if let Some(path) = LLVM_PATH.get() {
+        suffixed_name
+            .map(|suffixed_name| path.join(suffixed_name))
+            .filter(|path| path.exists())
+            .unwrap_or_else(|| path.join(format!("llvm-{}{}", name, EXE_SUFFIX)))
+    } else if let Some(path) = env::var_os(env_var) {
+        // The environment variable may name a binary to look up in the PATH.
+        return Ok(PathBuf::from(path));
+    } else if let Some(path) = suffixed_name.and_then(|suffixed_name| find_in_path(&suffixed_name))
+    {
         path
     } else {
-        llvm_tool_path("profdata").map_err(|x| x.to_string())?
+        llvm_tool_path(name).map_err(|x| x.to_string())?
     };
 
     if !path.exists() {
-        Err(String::from("We couldn't find llvm-profdata. Try installing the llvm-tools component with `rustup component add llvm-tools-preview` or specifying the --llvm-path option."))
+        Err(format!("We couldn't find llvm-{}. Try installing the llvm-tools component with `rustup component add llvm-tools-preview` or specifying the --llvm-path option.", name))
     } else {
         Ok(path)
     }
 }
 
-fn get_cov_path() -> Result<PathBuf, String> {
-    let path = if let Some(mut path) = LLVM_PATH.get().cloned() {
-        path.push(format!("llvm-cov{}", EXE_SUFFIX));
-        path
-    } else {
-        llvm_tool_path("cov").map_err(|x| x.to_string())?
-    };
+fn get_profdata_path() -> Result<PathBuf, String> {
+    get_tool_path("profdata", "LLVM_PROFDATA")
+}
 
-    if !path.exists() {
-        Err(String::from("We couldn't find llvm-cov. Try installing the llvm-tools component with `rustup component add llvm-tools-preview` or specifying the --llvm-path option."))
-    } else {
-        Ok(path)
-    }
+fn get_cov_path() -> Result<PathBuf, String> {
+    get_tool_path("cov", "LLVM_COV")
 }
 
 #[cfg(test)]
@@ -207,6 +464,122 @@ mod tests {
     use super::*;
     use std::fs;
 
+    #[cfg(unix)]
+    #[test]
+    fn test_get_tool_path() {
+        env::set_var("GRCOV_TEST_LLVM_FOO", "/opt/llvm/bin/llvm-foo-17");
+        assert_eq!(
+            get_tool_path("foo", "GRCOV_TEST_LLVM_FOO"),
+            Ok(PathBuf::from("/opt/llvm/bin/llvm-foo-17"))
+        );
+
+        assert!(find_in_path("echo").is_some());
+        assert!(find_in_path("llvm-grcov-does-not-exist").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout() {
+        let timeout = Some(Duration::from_secs(1));
+
+        let output =
+            output_with_optional_timeout(Command::new("echo").arg("hello"), timeout).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+
+        let start = Instant::now();
+        let err =
+            output_with_optional_timeout(Command::new("sleep").arg("30"), timeout).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_with_stdin() {
+        let input = "hello\n".repeat(100_000);
+        assert_eq!(
+            run_with_stdin("cat", &input, &[]).unwrap(),
+            input.as_bytes()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_binaries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut elf = b"\x7fELF".to_vec();
+        elf.resize(64, 0);
+        for (name, mode) in &[
+            ("my_test-1234", 0o755),
+            ("libmy_test-1234.rlib", 0o755),
+            ("my_test-1234.o", 0o644),
+        ] {
+            let path = tmp_dir.path().join(name);
+            fs::write(&path, &elf).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(*mode)).unwrap();
+        }
+        fs::write(
+            tmp_dir.path().join("my_test-1234.d"),
+            "my_test-1234: src/lib.rs",
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_binaries(tmp_dir.path()),
+            vec![tmp_dir.path().join("my_test-1234")]
+        );
+    }
+
+    #[test]
+    fn test_group_profraws_by_binary() {
+        let binaries = vec![
+            PathBuf::from("target/debug/deps/foo"),
+            PathBuf::from("target/debug/deps/foo-bar.exe"),
+            PathBuf::from("target/debug/deps/baz"),
+            PathBuf::from("target/debug/deps/qux"),
+        ];
+        let profraws = vec![
+            PathBuf::from("foo-1.profraw"),
+            PathBuf::from("foo-bar-2.profraw"),
+            PathBuf::from("foo.profraw"),
+            PathBuf::from("bazooka-3.profraw"),
+            PathBuf::from("default.profraw"),
+        ];
+
+        let groups = group_profraws_by_binary(&profraws, binaries);
+        // The binaries without a profraw named after them are exported against all the profraws.
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    vec![PathBuf::from("target/debug/deps/foo")],
+                    vec![PathBuf::from("foo-1.profraw"), PathBuf::from("foo.profraw")]
+                ),
+                (
+                    vec![PathBuf::from("target/debug/deps/foo-bar.exe")],
+                    vec![PathBuf::from("foo-bar-2.profraw")]
+                ),
+                (
+                    vec![
+                        PathBuf::from("target/debug/deps/baz"),
+                        PathBuf::from("target/debug/deps/qux")
+                    ],
+                    profraws.clone()
+                ),
+            ]
+        );
+
+        let binaries = vec![PathBuf::from("a"), PathBuf::from("b")];
+        let profraws = vec![PathBuf::from("default.profraw")];
+        assert_eq!(
+            group_profraws_by_binary(&profraws, binaries.clone()),
+            vec![(binaries, profraws)]
+        );
+    }
+
     #[test]
     fn test_profraws_to_lcov() {
         let output = Command::new("rustc").arg("--version").output().unwrap();
@@ -240,8 +613,9 @@ mod tests {
 
         let lcovs = profraws_to_lcov(
             &[tmp_path.join("default.profraw")],
-            &PathBuf::from("src"),
+            &[PathBuf::from("src")],
             &tmp_path,
+            false,
         );
         assert!(lcovs.is_ok());
         let lcovs = lcovs.unwrap();
@@ -260,13 +634,15 @@ mod tests {
 
         let lcovs = profraws_to_lcov(
             &[tmp_path.join("default.profraw")],
-            &tmp_path.join(binary_path),
+            &[tmp_path.join(&binary_path)],
             &tmp_path,
+            false,
         );
         assert!(lcovs.is_ok());
         let lcovs = lcovs.unwrap();
         assert_eq!(lcovs.len(), 1);
-        let output_lcov = String::from_utf8_lossy(&lcovs[0]);
+        assert_eq!(lcovs[0].0, None);
+        let output_lcov = String::from_utf8_lossy(&lcovs[0].1);
         println!("{}", output_lcov);
         assert!(output_lcov
             .lines()
@@ -313,5 +689,17 @@ mod tests {
             assert!(output_lcov.lines().any(|line| line == "LH:5"));
         }
         assert!(output_lcov.lines().any(|line| line == "end_of_record"));
+
+        let lcovs = profraws_to_lcov(
+            &[tmp_path.join("default.profraw")],
+            &[tmp_path.join(binary_path)],
+            &tmp_path,
+            true,
+        )
+        .unwrap();
+        assert_eq!(lcovs.len(), 1);
+        assert_eq!(lcovs[0].0.as_deref(), Some("default"));
+        let per_test_lcov = String::from_utf8_lossy(&lcovs[0].1);
+        assert_eq!(per_test_lcov, format!("TN:default\n{}", output_lcov));
     }
 }
@@ -2,20 +2,19 @@
 #[global_allocator]
 static GLOBAL: tcmalloc::TCMalloc = tcmalloc::TCMalloc;
 
-use clap::{builder::PossibleValue, ArgGroup, Parser, ValueEnum};
-use crossbeam_channel::bounded;
-use log::error;
+use clap::{builder::PossibleValue, ArgGroup, CommandFactory, Parser, ValueEnum};
+use log::{error, warn};
 use regex::Regex;
-use rustc_hash::FxHashMap;
-use serde_json::Value;
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger};
+use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::ops::Deref;
 use std::panic;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-use std::{process, thread};
+use std::sync::Mutex;
+use std::time::Duration;
 
 use grcov::*;
 
@@ -31,6 +30,15 @@ enum OutputType {
     Cobertura,
     CoberturaPretty,
     Markdown,
+    Annotated,
+    Sarif,
+    Percentage,
+    DeadFunctions,
+    BranchesJson,
+    TestsJson,
+    Junit,
+    #[cfg(feature = "blame")]
+    Blame,
 }
 
 impl FromStr for OutputType {
@@ -48,6 +56,15 @@ impl FromStr for OutputType {
             "cobertura" => Self::Cobertura,
             "cobertura-pretty" => Self::CoberturaPretty,
             "markdown" => Self::Markdown,
+            "annotated" => Self::Annotated,
+            "sarif" => Self::Sarif,
+            "percentage" => Self::Percentage,
+            "dead-functions" => Self::DeadFunctions,
+            "branches-json" => Self::BranchesJson,
+            "tests-json" => Self::TestsJson,
+            "junit" => Self::Junit,
+            #[cfg(feature = "blame")]
+            "blame" => Self::Blame,
             _ => return Err(format!("{} is not a supported output type", s)),
         })
     }
@@ -69,6 +86,15 @@ impl OutputType {
                         path.join("cobertura.xml")
                     }
                     OutputType::Markdown => path.join("markdown.md"),
+                    OutputType::Annotated => path.join("annotated.json"),
+                    OutputType::Sarif => path.join("coverage.sarif"),
+                    OutputType::Percentage => path.join("percentage.txt"),
+                    OutputType::DeadFunctions => path.join("dead_functions.json"),
+                    OutputType::BranchesJson => path.join("branches.json"),
+                    OutputType::TestsJson => path.join("tests.json"),
+                    OutputType::Junit => path.join("junit.xml"),
+                    #[cfg(feature = "blame")]
+                    OutputType::Blame => path.join("blame.json"),
                 }
             } else {
                 path.to_path_buf()
@@ -77,6 +103,166 @@ impl OutputType {
     }
 }
 
+/// The names of the output types supported by this build, as accepted by `--output-types`.
+fn supported_output_types() -> Vec<&'static str> {
+    let mut output_types = vec![
+        "ade",
+        "lcov",
+        "coveralls",
+        "coveralls+",
+        "files",
+        "covdir",
+        "html",
+        "cobertura",
+        "cobertura-pretty",
+        "markdown",
+        "annotated",
+        "sarif",
+        "percentage",
+        "dead-functions",
+        "branches-json",
+        "tests-json",
+        "junit",
+    ];
+    if cfg!(feature = "blame") {
+        output_types.push("blame");
+    }
+    output_types
+}
+
+/// The cargo features this build was compiled with, among the ones changing what grcov supports.
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("tc", cfg!(feature = "tc")),
+        ("net", cfg!(feature = "net")),
+        ("blame", cfg!(feature = "blame")),
+        ("python", cfg!(feature = "python")),
+        ("deflate", cfg!(feature = "deflate")),
+        ("deflate-miniz", cfg!(feature = "deflate-miniz")),
+        ("deflate-zlib", cfg!(feature = "deflate-zlib")),
+        ("demangle-no-swift", cfg!(feature = "demangle-no-swift")),
+        ("demangle-with-swift", cfg!(feature = "demangle-with-swift")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| *feature)
+    .collect()
+}
+
+/// The formats of the inputs supported by this build, which are detected automatically.
+fn supported_input_formats() -> Vec<&'static str> {
+    let mut input_formats = vec!["gcno", "profraw", "lcov", "jacoco", "gcov", "gcov-json"];
+    if cfg!(feature = "python") {
+        input_formats.push("python-coverage");
+    }
+    input_formats
+}
+
+/// Describes what this build supports, as JSON, so that wrappers can check it before invoking
+/// grcov.
+fn get_capabilities() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "inputFormats": supported_input_formats(),
+        "outputTypes": supported_output_types(),
+        "features": enabled_features(),
+    })
+}
+
+/// How an output type demangles the function names, overriding `--demangler`/`--no-demangle`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DemangleSpec {
+    /// `nodemangle`: the names are kept as they are.
+    Off,
+    /// `demangle`: the names are demangled with the `--demangler` strategy, even with
+    /// `--no-demangle`.
+    On,
+    /// `demangle=STRATEGY`: the names are demangled with the given strategy.
+    With(Demangler),
+}
+
+/// An output type, with optional settings overriding the global ones, separated by colons (e.g.
+/// `covdir:4` for the precision, `lcov:nodemangle` for the demangling).
+#[derive(Clone)]
+struct OutputSpec {
+    output_type: OutputType,
+    precision: Option<usize>,
+    demangle: Option<DemangleSpec>,
+}
+
+impl OutputSpec {
+    /// Returns the demangler of the output type, given the global one (`Demangler::None` with
+    /// `--no-demangle`) and the `--demangler` strategy.
+    fn get_demangler(&self, global: Demangler, strategy: Demangler) -> Demangler {
+        match self.demangle {
+            Some(DemangleSpec::Off) => Demangler::None,
+            Some(DemangleSpec::On) => strategy,
+            Some(DemangleSpec::With(demangler)) => demangler,
+            None => global,
+        }
+    }
+}
+
+impl FromStr for OutputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap();
+        let output_type = OutputType::from_str(name)?;
+        let mut precision = None;
+        let mut demangle = None;
+        for part in parts {
+            let demangle_spec = match part {
+                "nodemangle" => Some(DemangleSpec::Off),
+                "demangle" => Some(DemangleSpec::On),
+                _ => match part.strip_prefix("demangle=") {
+                    Some(demangler) => Some(DemangleSpec::With(Demangler::from_str(demangler)?)),
+                    None => None,
+                },
+            };
+            if let Some(demangle_spec) = demangle_spec {
+                if !matches!(
+                    output_type,
+                    OutputType::Ade
+                        | OutputType::Lcov
+                        | OutputType::Coveralls
+                        | OutputType::CoverallsPlus
+                        | OutputType::Cobertura
+                        | OutputType::CoberturaPretty
+                        | OutputType::Sarif
+                        | OutputType::DeadFunctions
+                ) {
+                    return Err(format!("{} doesn't output function names", name));
+                }
+                demangle = Some(demangle_spec);
+                continue;
+            }
+
+            if !matches!(
+                output_type,
+                OutputType::Covdir
+                    | OutputType::Html
+                    | OutputType::Markdown
+                    | OutputType::Files
+                    | OutputType::Percentage
+                    | OutputType::Junit
+            ) {
+                return Err(format!("{} doesn't support setting a precision", name));
+            }
+            precision = Some(
+                part.parse()
+                    .map_err(|_| format!("{} is not a valid precision", part))?,
+            );
+        }
+        Ok(Self {
+            output_type,
+            precision,
+            demangle,
+        })
+    }
+}
+
 #[derive(clap::ValueEnum, Clone)]
 enum Filter {
     Covered,
@@ -95,6 +281,66 @@ impl FromStr for Filter {
     }
 }
 
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum InputFormat {
+    Lcov,
+    Jacoco,
+    Gcov,
+}
+
+impl From<InputFormat> for ItemFormat {
+    fn from(input_format: InputFormat) -> Self {
+        match input_format {
+            InputFormat::Lcov => ItemFormat::Info,
+            InputFormat::Jacoco => ItemFormat::JacocoXml,
+            InputFormat::Gcov => ItemFormat::Gcov,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum MergeStrategyArg {
+    Sum,
+    Max,
+}
+
+impl From<MergeStrategyArg> for MergeStrategy {
+    fn from(merge_strategy: MergeStrategyArg) -> Self {
+        match merge_strategy {
+            MergeStrategyArg::Sum => MergeStrategy::Sum,
+            MergeStrategyArg::Max => MergeStrategy::Max,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum PathsArg {
+    Relative,
+    Absolute,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Returns the color choice of the terminal logger. In auto mode, colors are disabled when the
+    /// `NO_COLOR` environment variable is set to a non-empty value.
+    fn to_color_choice(self, no_color: Option<&OsStr>) -> ColorChoice {
+        match self {
+            Color::Auto if no_color.is_some_and(|no_color| !no_color.is_empty()) => {
+                ColorChoice::Never
+            }
+            Color::Auto => ColorChoice::Auto,
+            Color::Always => ColorChoice::Always,
+            Color::Never => ColorChoice::Never,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 struct LevelFilterArg(LevelFilter);
 
@@ -146,15 +392,26 @@ impl ValueEnum for LevelFilterArg {
         .multiple(true),
 )]
 struct Opt {
-    /// Sets the input paths to use.
-    #[arg(required = true)]
+    /// Sets the input paths to use (or HTTP(S) URLs when built with the net feature).
+    #[arg(required_unless_present_any = ["coveralls_done", "capabilities", "generate_completions"])]
     paths: Vec<String>,
-    /// Sets the path to the compiled binary to be used.
+    /// Sets the path to the compiled binary to be used. Can be given multiple times, e.g. when
+    /// each test binary of a workspace produces its own profraws.
     #[arg(short, long, value_name = "PATH")]
-    binary_path: Option<PathBuf>,
+    binary_path: Vec<PathBuf>,
     /// Sets the path to the LLVM bin directory.
     #[arg(long, value_name = "PATH")]
     llvm_path: Option<PathBuf>,
+    /// Sets the suffix of the names of the LLVM tools, e.g. '-17' for 'llvm-cov-17', as installed
+    /// by some distributions. The unsuffixed names are used when the suffixed ones aren't found.
+    /// The LLVM_PROFDATA and LLVM_COV environment variables override the tools, unless --llvm-path
+    /// is given.
+    #[arg(long, value_name = "SUFFIX")]
+    llvm_tool_suffix: Option<String>,
+    /// Kills the gcov and llvm-cov processes running for longer than this (the corresponding files
+    /// are skipped). Unlimited by default.
+    #[arg(long, value_name = "SECONDS")]
+    subprocess_timeout: Option<u64>,
     /// Sets a custom output type.
     #[arg(
         short = 't',
@@ -164,13 +421,27 @@ struct Opt {
             - *html* for a HTML coverage report;\n\
             - *coveralls* for the Coveralls specific format;\n\
             - *lcov* for the lcov INFO format;\n\
-            - *covdir* for the covdir recursive JSON format;\n\
+            - *covdir* for the covdir recursive JSON format, with per-line coverage for each file;\n\
             - *coveralls+* for the Coveralls specific format with function information;\n\
             - *ade* for the ActiveData-ETL specific format;\n\
-            - *files* to only return a list of files.\n\
+            - *files* to only return a list of files (with their line and branch coverage \
+            percentages when --files-with-stats is given).\n\
             - *markdown* for human easy read.\n\
             - *cobertura* for output in cobertura format.\n\
             - *cobertura-pretty* to pretty-print in cobertura format.\n\
+            - *annotated* for the source of the uncovered lines of each file, as JSON.\n\
+            - *sarif* for the uncovered lines and functions as SARIF results, for code scanning.\n\
+            - *percentage* for only the percentage of covered lines over all the files.\n\
+            - *dead-functions* for the functions which were never executed, as JSON.\n\
+            - *branches-json* for whether each branch of each line was taken, as JSON.\n\
+            - *tests-json* for the lines executed by each test (see '--per-test-profraw'), as JSON.\n\
+            - *junit* for a JUnit XML with a test case per file, failing below '--junit-threshold'.\n\
+            - *blame* for the author and commit which last touched each uncovered line, as JSON \
+            (only when built with the blame feature).\n\
+            The precision of the *html*, *covdir*, *markdown*, *files*, *percentage* and *junit* outputs can be \
+            overridden per output type, e.g. *covdir:4*.\n\
+            The demangling of the function names can be overridden per output type with *nodemangle*, \
+            *demangle* or *demangle=STRATEGY*, e.g. *lcov:nodemangle*.\n\
         ",
         value_name = "OUTPUT TYPE",
         requires_ifs = [
@@ -182,17 +453,28 @@ struct Opt {
         alias = "output-type",
         default_value = "lcov",
     )]
-    output_types: Vec<OutputType>,
+    output_types: Vec<OutputSpec>,
     /// Specifies the output path. This is a file for a single output type and must be a folder
-    /// for multiple output types.
+    /// for multiple output types. '-' writes the output of a single output type (other than html)
+    /// to the standard output.
     #[arg(short, long, value_name = "PATH", alias = "output-file")]
     output_path: Option<PathBuf>,
+    /// Writes the html output into a zip archive rather than a directory. The '.zip' extension
+    /// is added to its output path when missing.
+    #[arg(long)]
+    output_zip: bool,
     /// Specifies the output config file.
     #[arg(long, value_name = "PATH", alias = "output-config-file")]
     output_config_file: Option<PathBuf>,
-    /// Specifies the root directory of the source files.
+    /// Specifies the root directory of the source files, or a ZIP file of the source files, which
+    /// is extracted to a temporary directory (in which case the prefix directory defaults to the
+    /// current directory).
     #[arg(short, long, value_name = "DIRECTORY")]
     source_dir: Option<PathBuf>,
+    /// Ignores the source files in the temporary directory (e.g. given by the TMPDIR environment
+    /// variable), such as scratch files, unless the source directory is in it.
+    #[arg(long)]
+    ignore_tmp_dir: bool,
     /// Specifies a prefix to remove from the paths (e.g. if grcov is run on a different machine
     /// than the one that generated the code coverage information).
     #[arg(short, long, value_name = "PATH")]
@@ -200,17 +482,221 @@ struct Opt {
     /// Ignore source files that can't be found on the disk.
     #[arg(long)]
     ignore_not_existing: bool,
-    /// Ignore files/directories specified as globs.
+    /// Keeps the source paths as they are, only normalized, instead of resolving their symlinks
+    /// (e.g. for a source tree which is a symlink farm). '--ignore-not-existing' still checks that
+    /// the targets of the symlinks exist.
+    #[arg(long)]
+    no_canonicalize: bool,
+    /// Keep the source files that can't be found on the disk in the html output, shown without
+    /// their source, so that the totals don't depend on the available sources. The other outputs
+    /// always keep them.
+    #[arg(long, conflicts_with = "ignore_not_existing")]
+    keep_not_existing: bool,
+    /// Skip files ignored by .gitignore when traversing the source directory.
+    #[arg(long)]
+    respect_gitignore: bool,
+    /// Only reports the files changed since the given git reference, restricted to their added or
+    /// modified lines (as given by `git diff` in the source directory).
+    #[arg(long, value_name = "GIT_REF", conflicts_with = "merge_only")]
+    changed_since: Option<String>,
+    /// Doesn't warn about the unknown records of lcov files, which are always skipped.
+    #[arg(long)]
+    lenient_lcov: bool,
+    /// Excludes the coverage of the tests whose name (the TN records of lcov files) matches this
+    /// regex: their execution counts are subtracted, and the lines only executed by them are
+    /// dropped. The tests are only matched with the records of the same lcov file.
+    #[arg(long, value_name = "regex")]
+    exclude_test_coverage: Option<Regex>,
+    /// Converts each profraw on its own instead of merging them, tagging its coverage with its file
+    /// name as the test name (e.g. for '--exclude-test-coverage' or the tests-json output).
+    #[arg(long)]
+    per_test_profraw: bool,
+    /// Reports the line coverage of each input archive (e.g. the ZIP file of each test shard)
+    /// besides the total, in the markdown output and the '--summary-file'. The plain files given
+    /// as input paths count as one archive.
+    #[arg(long)]
+    per_archive: bool,
+    /// Verifies each input file read or extracted against the SHA-256 checksum in this manifest,
+    /// made of a 'path:sha256' line per file, the path being relative to the archive or directory
+    /// containing the file. The files whose checksum doesn't match, or which are missing from the
+    /// manifest, are skipped and reported as errors.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+    /// Only warns about the inputs whose checksum doesn't match the '--manifest', using them
+    /// anyway.
+    #[arg(long, requires = "manifest")]
+    keep_going: bool,
+    /// Copies the files of the input directories which need to be extracted to a temporary
+    /// directory (e.g. the gcno and gcda files), instead of symlinking them. Slower, but works on
+    /// the filesystems where the symlinks can't be used (e.g. some overlay filesystems).
+    #[arg(long)]
+    copy_extract: bool,
+    /// Experimental: moves the functions defined in headers (e.g. inline functions) to the file
+    /// including them. Only for the gcno files parsed by grcov itself (i.e. with '--llvm'). The
+    /// gcno files don't record the includes, so the includer is the only file of the gcno which
+    /// isn't a header, if any. The lines stay in the headers, and the moved functions have no
+    /// start line.
+    #[arg(long)]
+    attribute_headers_to_includers: bool,
+    /// Whether the functions without any line with coverage data are counted in the function
+    /// totals of all the outputs. When false, a function is removed if no line from its start up
+    /// to the start of the next function of the file (or none of the lines attributed to it, when
+    /// the coverage data tells) has coverage data.
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    include_empty_functions_in_denominator: bool,
+    /// Merges the functions whose names, demangled with the '--demangler' strategy, only differ by
+    /// their generic parameters and which start on the same line (e.g. the instances of a Rust
+    /// generic function or of a C++ template), in all the outputs. A merged function is executed if
+    /// any of its instances is.
+    #[arg(long)]
+    collapse_generics: bool,
+    /// Drops the branches which are exception edges (e.g. C++ calls which can throw). They are
+    /// only known precisely with the JSON gcov format (gcc >= 9.1): the text format only marks
+    /// the edges of the blocks which were executed.
+    #[arg(long)]
+    exclude_branch_throw: bool,
+    /// Caches the results of the gcno/gcda files in this directory, so that the unchanged ones
+    /// aren't parsed again by the following runs.
+    #[arg(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+    /// Limits the depth of the traversal of the source directory.
+    #[arg(long, value_name = "N")]
+    source_walk_max_depth: Option<usize>,
+    /// Doesn't traverse the directories of the source directory matching an --ignore glob, which
+    /// is much faster for big vendored trees.
+    #[arg(long)]
+    prune_ignored_dirs: bool,
+    /// Comma separated list of the extensions of the files whose partial paths (e.g. relative to
+    /// their package, as in JaCoCo reports) are mapped to the full paths found in the source
+    /// directory.
+    #[arg(
+        long,
+        value_name = "EXTENSIONS",
+        value_delimiter = ',',
+        default_value = "java,kt,scala,groovy"
+    )]
+    partial_path_extensions: Vec<String>,
+    /// Include the files of the source directory matching the glob which have no coverage data at
+    /// all, so that they are accounted for as uncovered. Every non-blank line of these files is
+    /// counted as an uncovered line.
+    #[arg(long, value_name = "GLOB", num_args = 1, requires = "source_dir")]
+    include_all_sources: Vec<String>,
+    /// Merge the coverage data of files whose paths only differ by their case, as they are the same
+    /// file on case-insensitive file systems.
+    #[arg(long)]
+    ignore_path_case: bool,
+    /// Writes the list of the inputs which couldn't be parsed, with the reason, to the given
+    /// file as JSON.
+    #[arg(long, value_name = "FILE")]
+    error_report: Option<PathBuf>,
+    /// Exits with a non-zero status, after writing the outputs, if any input couldn't be
+    /// extracted or parsed.
+    #[arg(long)]
+    strict: bool,
+    /// Parses all the input files (but not the content of the input directories and archives)
+    /// with the given format, whatever their extension or content.
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    input_format: Option<InputFormat>,
+    /// How the execution counts of the same line in several inputs are merged: 'sum' adds them
+    /// up, 'max' keeps the highest one (i.e. whether the line was covered by any input).
+    #[arg(long, value_name = "STRATEGY", default_value = "sum", value_enum)]
+    merge_strategy: MergeStrategyArg,
+    /// Excludes the source files whose content has one of the MD5 hashes listed in the given
+    /// file, one per line (e.g. the output of `md5sum`), such as vendored files.
+    #[arg(long, value_name = "FILE")]
+    exclude_files_listing: Option<PathBuf>,
+    /// Excludes the lines listed in the given file, one `path:line` entry per line (e.g. from a
+    /// linter). The paths are either relative, as in the outputs (i.e. after the path mapping and
+    /// the removal of the prefix directory), or absolute.
+    #[arg(long, value_name = "FILE")]
+    exclude_lines_file: Option<PathBuf>,
+    /// Fails, listing the offending files, if any of the paths in the outputs is absolute (e.g.
+    /// because of a wrong '--prefix-dir').
+    #[arg(long)]
+    require_relative_paths: bool,
+    /// Makes all the outputs use either the relative paths of the files (i.e. after the path
+    /// mapping and the removal of the prefix directory) or their absolute paths. By default, the
+    /// 'coveralls' and 'coveralls+' outputs make the paths relative to the '--vcs-root' instead.
+    /// The absolute paths aren't supported by the 'html' and 'covdir' outputs, which lay out the
+    /// files by their relative paths.
+    #[arg(
+        long = "paths",
+        value_name = "KIND",
+        value_enum,
+        conflicts_with = "vcs_root"
+    )]
+    paths_kind: Option<PathsArg>,
+    /// Splits the lcov output into one file per group of source files sharing their first
+    /// DEPTH directories, written to the output directory. Each file is named after the directories
+    /// of its group joined with '_' (in which '%' and '_' are percent-encoded), or '_root' for the
+    /// source files without any directory.
+    #[arg(long, value_name = "DEPTH", requires = "output_path")]
+    split_output_by: Option<usize>,
+    /// Omits the lines of the lcov output which were never executed, unless they have branches or
+    /// start a function, as they might not be executable.
+    #[arg(long)]
+    lcov_omit_zero: bool,
+    /// Appends the line and branch coverage percentages to each path of the files output, as
+    /// `path lines% branches%`. The percentages of the files without any line or branch are
+    /// shown as '-', as they don't apply.
+    #[arg(long)]
+    files_with_stats: bool,
+    /// Number of lines of context to show around the uncovered lines in the annotated output.
+    #[arg(long, value_name = "LINES", default_value = "0")]
+    annotated_context: u32,
+    /// Encodes the lists of lines of the ade output as ranges of consecutive lines, `[first, last]`,
+    /// which is much more compact for big files. The records then have the schema version 2.
+    #[arg(long)]
+    ade_line_ranges: bool,
+    /// Only merge the coverage data of the inputs, keeping the source file paths as they are
+    /// instead of resolving them. The '--ignore' and '--keep-only' globs are matched against these
+    /// paths.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "source_dir",
+            "prefix_dir",
+            "path_mapping",
+            "compilation_database",
+            "ignore_not_existing",
+        ]
+    )]
+    merge_only: bool,
+    /// Ignore files/directories specified as globs. '${VAR}' is replaced with the value of the
+    /// environment variable VAR.
     #[arg(long = "ignore", value_name = "PATH", num_args = 1)]
     ignore_dir: Vec<String>,
-    /// Keep only files/directories specified as globs.
+    /// Keep only files/directories specified as globs. '${VAR}' is replaced with the value of the
+    /// environment variable VAR.
     #[arg(long = "keep-only", value_name = "PATH", num_args = 1)]
     keep_dir: Vec<String>,
+    /// Lists the files/directories specified as globs separately in the markdown output and the
+    /// '--summary-file', without counting them in the totals of any output (nor in the html and
+    /// covdir directories, the junit failures and '--fail-on-decrease').
+    #[arg(long, value_name = "PATH", num_args = 1)]
+    informational: Vec<String>,
+    /// A JSON file mapping the paths of the coverage data to the paths of the sources. '${VAR}' is
+    /// replaced with the value of the environment variable VAR in its keys and values.
     #[arg(long, value_name = "PATH")]
     path_mapping: Option<PathBuf>,
+    /// Resolves the relative source paths with a compilation database (compile_commands.json),
+    /// e.g. for CMake out-of-tree builds: each file is relative to the directory of its entry (or
+    /// to the one containing it, when it is in several entries).
+    #[arg(long, value_name = "FILE")]
+    compilation_database: Option<PathBuf>,
     /// Enables parsing branch coverage information.
     #[arg(long)]
     branch: bool,
+    /// Merges the branches of the same line in several inputs by their (block, branch) numbers
+    /// rather than by their position, for the lcov files and the gcov JSON reports of gcc >= 14.
+    /// The branches of the different blocks of a lcov line are then kept apart.
+    #[arg(long, requires = "branch")]
+    merge_branches_by_id: bool,
     /// Filters out covered/uncovered files. Use 'covered' to only return covered files, 'uncovered'
     /// to only return uncovered files.
     #[arg(long, value_enum)]
@@ -224,6 +710,10 @@ struct Opt {
         default_value = "markdown"
     )]
     sort_output_types: Vec<OutputType>,
+    /// Sorts the files of all the output types, so that the reports are reproducible byte for
+    /// byte. The functions are always sorted by start line and name.
+    #[arg(long)]
+    deterministic: bool,
     /// Speeds-up parsing, when the code coverage information is exclusively coming from a llvm
     /// build.
     #[arg(long)]
@@ -232,9 +722,18 @@ struct Opt {
     /// formats.
     #[arg(long, value_name = "TOKEN")]
     token: Option<String>,
-    /// Sets the hash of the commit used to generate the code coverage data.
+    /// Sets the hash of the commit used to generate the code coverage data. By default, the HEAD
+    /// commit of the git repository of the source directory is used.
     #[arg(long, value_name = "COMMIT HASH")]
     commit_sha: Option<String>,
+    /// Don't detect the commit hash from git when --commit-sha isn't given.
+    #[arg(long)]
+    no_auto_git: bool,
+    /// Sets the root of the repository, to which the file paths in the 'coveralls' and
+    /// 'coveralls+' formats are made relative. By default, they use the same relative paths as
+    /// the other outputs.
+    #[arg(long, value_name = "PATH")]
+    vcs_root: Option<PathBuf>,
     /// Sets the service name.
     #[arg(long, value_name = "SERVICE NAME")]
     service_name: Option<String>,
@@ -258,6 +757,21 @@ struct Opt {
     /// Sets the build type to be parallel for 'coveralls' and 'coveralls+' formats.
     #[arg(long)]
     parallel: bool,
+    /// Don't read the author, message and remotes of the commit from the repository for the
+    /// 'coveralls' and 'coveralls+' formats.
+    #[arg(long)]
+    no_coveralls_git_info: bool,
+    /// Notifies Coveralls that all the jobs of a parallel build are done, without parsing any
+    /// coverage data.
+    #[arg(long, requires = "coveralls-auth", requires = "service_number")]
+    coveralls_done: bool,
+    /// Prints the supported input formats, output types and cargo features of this build, as
+    /// JSON, and exits.
+    #[arg(long)]
+    capabilities: bool,
+    /// Prints the completion script of the given shell and exits.
+    #[arg(long, value_name = "SHELL", hide = true)]
+    generate_completions: Option<clap_complete::Shell>,
     #[arg(long, value_name = "NUMBER")]
     threads: Option<usize>,
     /// Sets coverage decimal point precision on output reports.
@@ -274,10 +788,15 @@ struct Opt {
     /// Set the log level.
     #[arg(long, value_name = "LEVEL", default_value = "ERROR", value_enum)]
     log_level: LevelFilterArg,
+    /// When to use colors in the log. 'auto' doesn't use them when the NO_COLOR environment
+    /// variable is set.
+    #[arg(long, value_name = "WHEN", default_value = "auto", value_enum)]
+    color: Color,
     /// Lines in covered files containing this marker will be excluded.
     #[arg(long, value_name = "regex")]
     excl_line: Option<Regex>,
     /// Marks the beginning of an excluded section. The current line is part of this section.
+    /// Sections can be nested: they end with the stop of their outermost start.
     #[arg(long, value_name = "regex")]
     excl_start: Option<Regex>,
     /// Marks the end of an excluded section. The current line is part of this section.
@@ -287,24 +806,98 @@ struct Opt {
     #[arg(long, value_name = "regex")]
     excl_br_line: Option<Regex>,
     /// Marks the beginning of a section excluded from branch coverage. The current line is part of
-    /// this section.
+    /// this section. Sections can be nested: they end with the stop of their outermost start.
     #[arg(long, value_name = "regex")]
     excl_br_start: Option<Regex>,
     /// Marks the end of a section excluded from branch coverage. The current line is part of this
     /// section.
     #[arg(long, value_name = "regex")]
     excl_br_stop: Option<Regex>,
-    /// No symbol demangling.
+    /// Lines in covered files matching this regex (e.g. 'println!|assert!') are dropped from the
+    /// line and branch coverage, as a workaround for the counts of macro invocations.
+    #[arg(long, value_name = "regex")]
+    ignore_macro_lines: Option<Regex>,
+    /// The lcov output of a previous run (e.g. of the base branch), to compare the coverage with.
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
+    /// Fails, listing the offending files, if the line coverage of the total or of any file
+    /// dropped versus the '--baseline' by more than '--decrease-tolerance' percentage points.
+    #[arg(long, requires = "baseline")]
+    fail_on_decrease: bool,
+    /// The drop of the line coverage, in percentage points, tolerated by '--fail-on-decrease'.
+    #[arg(long, value_name = "PERCENT", default_value = "0")]
+    decrease_tolerance: f64,
+    /// The line coverage, in percent, below which a file is a failure in the junit output.
+    #[arg(long, value_name = "PERCENT", default_value = "100")]
+    junit_threshold: f64,
+    /// Also writes the totals of the lines, branches and functions to this file, as JSON, whatever
+    /// the output types.
+    #[arg(long, value_name = "PATH")]
+    summary_file: Option<PathBuf>,
+    /// Sets the title of the report, shown in the html output and added to the covdir and summary
+    /// outputs.
+    #[arg(long, value_name = "TITLE")]
+    report_title: Option<String>,
+    /// Adds a metadata entry (e.g. 'build=1234') to the report, shown in the html output and added
+    /// to the covdir and summary outputs. Can be given multiple times.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    report_metadata: Vec<(String, String)>,
+    /// Demangles the function names of the 'dead-functions' output, which are kept as they are in
+    /// the coverage data by default.
     #[arg(long)]
+    dead_functions_demangle: bool,
+    /// No symbol demangling, same as `--demangler none`.
+    #[arg(long, conflicts_with = "demangler")]
     no_demangle: bool,
+    /// Sets the strategy used to demangle function names.
+    #[arg(
+        long,
+        long_help = "\
+            Sets the strategy used to demangle function names:\n\
+            - *auto* to detect the mangling scheme of each name;\n\
+            - *rust* for Rust names, with the legacy or v0 mangling scheme;\n\
+            - *rust-v0* for Rust names with the v0 mangling scheme only;\n\
+            - *cpp* for C++ names;\n\
+            - *none* to disable demangling.\n\
+        ",
+        value_name = "DEMANGLER",
+        default_value = "auto"
+    )]
+    demangler: Demangler,
+}
+
+/// Parses a `KEY=VALUE` pair.
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("{} isn't a KEY=VALUE pair", s)),
+    }
+}
+
+/// The temporary directory a ZIP of the source files is extracted to, see `exit`.
+static SOURCE_TMP_DIR: Mutex<Option<tempfile::TempDir>> = Mutex::new(None);
+
+/// Removes the temporary directory of the source files, if any, and exits with `code`, as
+/// `process::exit` doesn't run the destructors.
+fn exit(code: i32) -> ! {
+    drop(SOURCE_TMP_DIR.lock().unwrap().take());
+    process::exit(code)
 }
 
 fn main() {
-    let opt = Opt::parse();
+    let mut opt = Opt::parse();
 
     if let Some(path) = opt.llvm_path {
         LLVM_PATH.set(path).unwrap();
     }
+    if let Some(suffix) = opt.llvm_tool_suffix {
+        LLVM_TOOL_SUFFIX.set(suffix).unwrap();
+    }
+    if let Some(timeout) = opt.subprocess_timeout {
+        SUBPROCESS_TIMEOUT
+            .set(Duration::from_secs(timeout))
+            .unwrap();
+    }
 
     let filter_option = opt.filter.map(|filter| match filter {
         Filter::Covered => true,
@@ -312,20 +905,23 @@ fn main() {
     });
     let stdout = Path::new("stdout");
     let stderr = Path::new("stderr");
+    let color_choice = opt
+        .color
+        .to_color_choice(std::env::var_os("NO_COLOR").as_deref());
 
     if opt.log == stdout {
         let _ = TermLogger::init(
             opt.log_level.0,
             Config::default(),
             TerminalMode::Stdout,
-            ColorChoice::Auto,
+            color_choice,
         );
     } else if opt.log == stderr {
         let _ = TermLogger::init(
             opt.log_level.0,
             Config::default(),
             TerminalMode::Stderr,
-            ColorChoice::Auto,
+            color_choice,
         );
     } else if let Ok(file) = File::create(&opt.log) {
         let _ = WriteLogger::init(opt.log_level.0, Config::default(), file);
@@ -334,7 +930,7 @@ fn main() {
             opt.log_level.0,
             Config::default(),
             TerminalMode::Stderr,
-            ColorChoice::Auto,
+            color_choice,
         );
         error!(
             "Unable to create log file: {}. Switch to stderr",
@@ -342,6 +938,62 @@ fn main() {
         );
     }
 
+    if opt.capabilities {
+        println!("{}", get_capabilities());
+        return;
+    }
+
+    if let Some(shell) = opt.generate_completions {
+        clap_complete::generate(shell, &mut Opt::command(), "grcov", &mut std::io::stdout());
+        return;
+    }
+
+    for glob in opt.ignore_dir.iter_mut().chain(opt.keep_dir.iter_mut()) {
+        match expand_env_vars(glob) {
+            Ok(expanded) => *glob = expanded,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if opt.coveralls_done {
+        if let Err(e) = send_coveralls_done(
+            opt.token.as_deref(),
+            opt.service_name.as_deref(),
+            opt.service_number.as_deref().unwrap_or_default(),
+            opt.service_job_id.as_deref(),
+        ) {
+            error!("Failed to notify Coveralls: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let excluded_hashes = opt
+        .exclude_files_listing
+        .as_ref()
+        .map(|path| {
+            let listing = fs::read_to_string(path).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to read the excluded files listing {:?}: {}",
+                    path, e
+                )
+            });
+            parse_hashes_listing(&listing)
+        })
+        .unwrap_or_default();
+    let excluded_lines = opt
+        .exclude_lines_file
+        .as_ref()
+        .map(|path| {
+            let listing = fs::read_to_string(path).unwrap_or_else(|e| {
+                panic!("Failed to read the excluded lines file {:?}: {}", path, e)
+            });
+            parse_lines_listing(&listing)
+        })
+        .unwrap_or_default();
     let file_filter = FileFilter::new(
         opt.excl_line,
         opt.excl_start,
@@ -349,8 +1001,15 @@ fn main() {
         opt.excl_br_line,
         opt.excl_br_start,
         opt.excl_br_stop,
+        excluded_hashes,
+        excluded_lines,
+        opt.ignore_macro_lines,
     );
-    let demangle = !opt.no_demangle;
+    let global_demangler = if opt.no_demangle {
+        Demangler::None
+    } else {
+        opt.demangler
+    };
 
     panic::set_hook(Box::new(|panic_info| {
         let (filename, line) = panic_info
@@ -372,124 +1031,317 @@ fn main() {
     }));
 
     let num_threads: usize = opt.threads.unwrap_or_else(|| 1.max(num_cpus::get() - 1));
+    // A ZIP of the source files is extracted to a temporary directory, which is the source root.
+    let mut sources_from_zip = false;
     let source_root = opt
         .source_dir
         .filter(|source_dir| source_dir != Path::new(""))
+        .map(|source_dir| {
+            if source_dir.is_file() && source_dir.extension() == Some(OsStr::new("zip")) {
+                let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+                let tmp_path = tmp_dir.path().to_path_buf();
+                *SOURCE_TMP_DIR.lock().unwrap() = Some(tmp_dir);
+                if let Err(e) = extract_zip(&source_dir, &tmp_path) {
+                    error!("{}", e);
+                    exit(1);
+                }
+                sources_from_zip = true;
+                tmp_path
+            } else {
+                source_dir
+            }
+        })
         .map(|source_dir| canonicalize_path(source_dir).expect("Source directory does not exist."));
 
-    let prefix_dir = opt.prefix_dir.or_else(|| source_root.clone());
-
-    let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-    let tmp_path = tmp_dir.path().to_owned();
-    assert!(tmp_path.exists());
-
-    let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
-        FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
-    ));
-    let (sender, receiver) = bounded(2 * num_threads);
-    let path_mapping: Arc<Mutex<Option<Value>>> = Arc::new(Mutex::new(None));
-
-    let producer = {
-        let sender: JobSender = sender.clone();
-        let tmp_path = tmp_path.clone();
-        let path_mapping_file = opt.path_mapping;
-        let path_mapping = Arc::clone(&path_mapping);
-        let paths = opt.paths;
-        let is_llvm = opt.llvm;
-
-        thread::Builder::new()
-            .name(String::from("Producer"))
-            .spawn(move || {
-                let producer_path_mapping_buf = producer(
-                    &tmp_path,
-                    &paths,
-                    &sender,
-                    filter_option.is_some() && filter_option.unwrap(),
-                    is_llvm,
-                );
+    // The paths of the coverage data don't point to the temporary directory of the sources
+    // extracted from a ZIP file, they're made relative to the current directory instead.
+    let prefix_dir = opt.prefix_dir.or_else(|| {
+        if sources_from_zip {
+            std::env::current_dir().ok()
+        } else {
+            source_root.clone()
+        }
+    });
 
-                let mut path_mapping = path_mapping.lock().unwrap();
-                *path_mapping = if let Some(path) = path_mapping_file {
-                    let file = File::open(path).unwrap();
-                    Some(serde_json::from_reader(file).unwrap())
-                } else {
-                    producer_path_mapping_buf.map(|producer_path_mapping_buf| {
-                        serde_json::from_slice(&producer_path_mapping_buf).unwrap()
-                    })
-                };
+    let ignored_tmp_dir = if opt.ignore_tmp_dir {
+        canonicalize_path(std::env::temp_dir())
+            .ok()
+            .filter(|tmp_dir| {
+                let contains_sources = source_root
+                    .as_deref()
+                    .is_some_and(|source_root| source_root.starts_with(tmp_dir));
+                // The sources extracted from a ZIP file are always in the temporary directory.
+                if contains_sources && !sources_from_zip {
+                    warn!(
+                        "The source directory is in the temporary directory {}, which isn't ignored.",
+                        tmp_dir.display()
+                    );
+                }
+                !contains_sources
             })
-            .unwrap()
+    } else {
+        None
     };
 
-    let mut parsers = Vec::new();
-
-    for i in 0..num_threads {
-        let receiver = receiver.clone();
-        let result_map = Arc::clone(&result_map);
-        let working_dir = tmp_path.join(format!("{}", i));
-        let source_root = source_root.clone();
-        let binary_path = opt.binary_path.clone();
-        let branch_enabled = opt.branch;
-        let guess_directory = opt.guess_directory;
-
-        let t = thread::Builder::new()
-            .name(format!("Consumer {}", i))
-            .spawn(move || {
-                fs::create_dir(&working_dir).expect("Failed to create working directory");
-                consumer(
-                    &working_dir,
-                    source_root.as_deref(),
-                    &result_map,
-                    receiver,
-                    branch_enabled,
-                    guess_directory,
-                    binary_path.as_deref(),
-                );
-            })
-            .unwrap();
-
-        parsers.push(t);
+    // The HTML output is a directory (or a zip archive), which can't be written to the standard
+    // output.
+    if opt.output_path.as_deref().is_some_and(is_stdout_path)
+        && opt
+            .output_types
+            .iter()
+            .any(|spec| spec.output_type == OutputType::Html)
+    {
+        error!("The html output can't be written to the standard output, '-o -' isn't supported.");
+        exit(1);
     }
-
-    if producer.join().is_err() {
-        process::exit(1);
+    // The outputs would be mixed together on the standard output.
+    if opt.output_path.as_deref().is_some_and(is_stdout_path) && opt.output_types.len() > 1 {
+        error!("Only one output type can be written to the standard output with '-o -'.");
+        exit(1);
     }
-
-    // Poison the receiver, now that the producer is finished.
-    for _ in 0..num_threads {
-        sender.send(None).unwrap();
+    if opt.paths_kind == Some(PathsArg::Absolute)
+        && opt
+            .output_types
+            .iter()
+            .any(|spec| matches!(spec.output_type, OutputType::Html | OutputType::Covdir))
+    {
+        error!("The html and covdir outputs can't use absolute paths, '--paths absolute' isn't supported with them.");
+        exit(1);
     }
 
-    for parser in parsers {
-        if parser.join().is_err() {
-            process::exit(1);
+    // The outputs are written after all the coverage data was parsed, so their directory is
+    // checked first.
+    if let Some(output_path) = opt.output_path.as_deref() {
+        let is_dir = opt.output_types.len() > 1
+            || opt.split_output_by.is_some()
+            || (opt
+                .output_types
+                .first()
+                .is_some_and(|spec| spec.output_type == OutputType::Html)
+                && !opt.output_zip);
+        let output_dir = if is_dir {
+            Some(output_path)
+        } else {
+            output_path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+        };
+        if let Some(output_dir) = output_dir.filter(|_| !is_stdout_path(output_path)) {
+            if let Err(e) = prepare_output_dir(output_dir) {
+                error!(
+                    "Cannot write to the output directory {}: {}",
+                    output_dir.display(),
+                    e
+                );
+                exit(1);
+            }
         }
     }
 
-    let result_map_mutex = Arc::try_unwrap(result_map).unwrap();
-    let result_map = result_map_mutex.into_inner().unwrap();
+    let keep_going = opt.keep_going;
+    let manifest = opt.manifest.as_ref().map(|manifest_path| {
+        let content = fs::read_to_string(manifest_path).unwrap_or_else(|e| {
+            error!("Failed to read the manifest {:?}: {}", manifest_path, e);
+            exit(1);
+        });
+        Manifest::parse(&content, keep_going).unwrap_or_else(|e| {
+            error!("Failed to parse the manifest {:?}: {}", manifest_path, e);
+            exit(1);
+        })
+    });
 
-    let path_mapping_mutex = Arc::try_unwrap(path_mapping).unwrap();
-    let path_mapping = path_mapping_mutex.into_inner().unwrap();
+    let cache = opt.cache_dir.as_deref().map(|cache_dir| {
+        ResultCache::new(cache_dir).expect("Failed to create the cache directory")
+    });
 
-    let iterator = rewrite_paths(
+    let config = GrcovConfig {
+        paths: opt.paths,
+        num_threads: Some(num_threads),
+        source_dir: source_root.clone(),
+        binary_paths: opt.binary_path,
+        branch_enabled: opt.branch,
+        guess_directory: opt.guess_directory,
+        ignore_orphan_gcno: filter_option.is_some() && filter_option.unwrap(),
+        is_llvm: opt.llvm,
+        cache,
+        exclude_branch_throw: opt.exclude_branch_throw,
+        merge_branches_by_id: opt.merge_branches_by_id,
+        lenient_lcov: opt.lenient_lcov,
+        input_format: opt.input_format.map(ItemFormat::from),
+        merge_strategy: opt.merge_strategy.into(),
+        exclude_test_coverage: opt.exclude_test_coverage,
+        attribute_headers_to_includers: opt.attribute_headers_to_includers,
+        per_test_profraw: opt.per_test_profraw,
+        manifest,
+        per_archive: opt.per_archive,
+        copy_extract: opt.copy_extract,
+    };
+    let GrcovResults {
         result_map,
         path_mapping,
-        source_root.as_deref(),
-        prefix_dir.as_deref(),
-        opt.ignore_not_existing,
-        &opt.ignore_dir,
-        &opt.keep_dir,
-        filter_option,
-        file_filter,
+        mut parse_errors,
+        no_input_files,
+    } = match collect_coverage(&config) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    };
+    if no_input_files {
+        error!("No input files found");
+        exit(1);
+    }
+
+    if let Some(error_report) = &opt.error_report {
+        parse_errors.sort_by(|a, b| (&a.file, &a.error).cmp(&(&b.file, &b.error)));
+        if let Err(e) = output_error_report(&parse_errors, Some(error_report)) {
+            error!("Failed to write the error report: {}", e);
+            exit(1);
+        }
+    }
+
+    let mut path_mapping = match &opt.path_mapping {
+        Some(path) => {
+            let file = File::open(path).unwrap();
+            match expand_mapping_env_vars(serde_json::from_reader(file).unwrap()) {
+                Ok(mapping) => Some(mapping),
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    exit(1);
+                }
+            }
+        }
+        None => path_mapping,
+    };
+    if let Some(database) = &opt.compilation_database {
+        let added = fs::read_to_string(database)
+            .map_err(|e| e.to_string())
+            .and_then(|database| add_compilation_database_mapping(&mut path_mapping, &database));
+        if let Err(e) = added {
+            error!("Failed to read {}: {}", database.display(), e);
+            exit(1);
+        }
+    }
+
+    let iterator = if opt.merge_only {
+        keep_original_paths(result_map, &opt.ignore_dir, &opt.keep_dir)
+    } else {
+        rewrite_paths(
+            result_map,
+            path_mapping,
+            source_root.as_deref(),
+            prefix_dir.as_deref(),
+            opt.ignore_not_existing,
+            &opt.ignore_dir,
+            &opt.keep_dir,
+            filter_option,
+            file_filter,
+            &RewriteOptions {
+                respect_gitignore: opt.respect_gitignore,
+                ignore_path_case: opt.ignore_path_case,
+                source_walk_max_depth: opt.source_walk_max_depth,
+                prune_ignored_dirs: opt.prune_ignored_dirs,
+                merge_strategy: opt.merge_strategy.into(),
+                partial_path_extensions: opt.partial_path_extensions.clone(),
+                ignored_dir: ignored_tmp_dir.clone(),
+                canonicalize: !opt.no_canonicalize,
+            },
+        )
+    };
+    let mut iterator = iterator;
+    if let Some(source_root) = &source_root {
+        // Files without coverage data can't be covered, so there's nothing to add when only
+        // covered files are kept.
+        if !opt.include_all_sources.is_empty() && filter_option != Some(true) {
+            add_missing_sources(
+                &mut iterator,
+                source_root,
+                &opt.include_all_sources,
+                &opt.ignore_dir,
+                &opt.keep_dir,
+            );
+        }
+    }
+    if let Some(git_ref) = &opt.changed_since {
+        let repo_dir = source_root
+            .clone()
+            .unwrap_or_else(|| std::env::current_dir().unwrap());
+        match get_changed_lines(&repo_dir, git_ref) {
+            Ok(changed_lines) => iterator = restrict_to_changed_lines(iterator, &changed_lines),
+            Err(e) => {
+                error!("Failed to get the changes since {}: {}", git_ref, e);
+                exit(1);
+            }
+        }
+    }
+    if !opt.include_empty_functions_in_denominator {
+        for (_, _, result) in iterator.iter_mut() {
+            remove_empty_functions(result);
+        }
+    }
+    if opt.collapse_generics {
+        for (_, _, result) in iterator.iter_mut() {
+            collapse_generic_functions(result, opt.demangler);
+        }
+    }
+    mark_informational(
+        iterator
+            .iter_mut()
+            .map(|(_, rel_path, result)| (rel_path.as_path(), result)),
+        &opt.informational,
     );
+    if opt.require_relative_paths {
+        let absolute_paths: Vec<_> = iterator
+            .iter()
+            .filter(|(_, rel_path, _)| rel_path.is_absolute())
+            .map(|(_, rel_path, _)| rel_path.display().to_string())
+            .collect();
+        if !absolute_paths.is_empty() {
+            error!("Some paths aren't relative:\n{}", absolute_paths.join("\n"));
+            exit(1);
+        }
+    }
+    if opt.paths_kind == Some(PathsArg::Absolute) {
+        for (abs_path, rel_path, _) in iterator.iter_mut() {
+            *rel_path = abs_path.clone();
+        }
+    }
     let mut sorted_iterator: Option<Vec<ResultTuple>> = None;
 
     let service_number = opt.service_number.unwrap_or_default();
     let service_pull_request = opt.service_pull_request.unwrap_or_default();
-    let commit_sha = opt.commit_sha.unwrap_or_default();
+    let auto_git = !opt.no_auto_git
+        && opt.output_types.iter().any(|spec| {
+            matches!(
+                spec.output_type,
+                OutputType::Coveralls | OutputType::CoverallsPlus
+            )
+        });
+    let commit_sha = opt
+        .commit_sha
+        .or_else(|| {
+            if !auto_git {
+                return None;
+            }
+            let repo_dir = source_root
+                .clone()
+                .unwrap_or_else(|| std::env::current_dir().unwrap());
+            get_git_head_sha(&repo_dir)
+        })
+        .unwrap_or_default();
+    let vcs_root = opt
+        .vcs_root
+        .map(|vcs_root| canonicalize_path(vcs_root).expect("VCS root does not exist."));
 
+    let report = ReportMetadata {
+        title: opt.report_title,
+        metadata: opt.report_metadata.into_iter().collect(),
+    };
     let output_types = opt.output_types;
+    // The relative paths of the sources extracted from a ZIP file are relative to the current
+    // directory, not to the temporary directory.
+    let cobertura_source = source_root.as_deref().filter(|_| !sources_from_zip);
 
     let output_path = match output_types.len() {
         0 => unreachable!("Output types has a default value"),
@@ -499,16 +1351,20 @@ fn main() {
                 if output_path.is_dir() {
                     Some(output_path)
                 } else {
-                    panic!("output_path must be a directory when using multiple outputs");
+                    error!("The output path must be a directory when using multiple outputs");
+                    exit(1);
                 }
             }
             _ => None,
         },
     };
 
-    for output_type in &output_types {
+    for output_spec in &output_types {
+        let output_type = &output_spec.output_type;
+        let precision = output_spec.precision.unwrap_or(opt.precision);
+        let demangler = output_spec.get_demangler(global_demangler, opt.demangler);
         let output_path = output_type.to_file_name(output_path);
-        let results = if opt.sort_output_types.contains(output_type) {
+        let results = if opt.deterministic || opt.sort_output_types.contains(output_type) {
             // compute and cache the sorted results if not already used
             sorted_iterator = sorted_iterator.or_else(|| {
                 let mut results = iterator.clone();
@@ -520,9 +1376,32 @@ fn main() {
             &iterator
         };
 
-        match output_type {
-            OutputType::Ade => output_activedata_etl(results, output_path.as_deref(), demangle),
-            OutputType::Lcov => output_lcov(results, output_path.as_deref(), demangle),
+        let written = match output_type {
+            OutputType::Ade => output_activedata_etl(
+                results,
+                output_path.as_deref(),
+                demangler,
+                opt.ade_line_ranges,
+            ),
+            OutputType::Lcov => match opt.split_output_by {
+                Some(depth) => {
+                    let output_dir = opt
+                        .output_path
+                        .as_deref()
+                        .filter(|output_dir| !is_stdout_path(output_dir))
+                        .expect("--split-output-by requires an output directory");
+                    let lcov_omit_zero = opt.lcov_omit_zero;
+                    fs::create_dir_all(output_dir).and_then(|_| {
+                        output_lcov_split(results, output_dir, demangler, lcov_omit_zero, depth)
+                    })
+                }
+                None => output_lcov(
+                    results,
+                    output_path.as_deref(),
+                    demangler,
+                    opt.lcov_omit_zero,
+                ),
+            },
             OutputType::Coveralls => output_coveralls(
                 results,
                 opt.token.as_deref(),
@@ -536,7 +1415,9 @@ fn main() {
                 output_path.as_deref(),
                 &opt.vcs_branch,
                 opt.parallel,
-                demangle,
+                demangler,
+                vcs_root.as_deref(),
+                !opt.no_coveralls_git_info,
             ),
             OutputType::CoverallsPlus => output_coveralls(
                 results,
@@ -551,45 +1432,257 @@ fn main() {
                 output_path.as_deref(),
                 &opt.vcs_branch,
                 opt.parallel,
-                demangle,
+                demangler,
+                vcs_root.as_deref(),
+                !opt.no_coveralls_git_info,
             ),
-            OutputType::Files => output_files(results, output_path.as_deref()),
-            OutputType::Covdir => output_covdir(results, output_path.as_deref(), opt.precision),
+            OutputType::Files => output_files(
+                results,
+                output_path.as_deref(),
+                opt.files_with_stats,
+                precision,
+            ),
+            OutputType::Covdir => {
+                output_covdir(results, output_path.as_deref(), precision, &report)
+            }
+            OutputType::Html if opt.output_zip => {
+                let output_path = output_path.map(|path| {
+                    if path.extension() == Some(OsStr::new("zip")) {
+                        path
+                    } else {
+                        let mut path = path.into_os_string();
+                        path.push(".zip");
+                        PathBuf::from(path)
+                    }
+                });
+                output_html_zip(
+                    results,
+                    output_path.as_deref(),
+                    num_threads,
+                    opt.branch,
+                    opt.output_config_file.as_deref(),
+                    precision,
+                    opt.keep_not_existing,
+                    &report,
+                )
+            }
             OutputType::Html => output_html(
                 results,
                 output_path.as_deref(),
                 num_threads,
                 opt.branch,
                 opt.output_config_file.as_deref(),
-                opt.precision,
+                precision,
+                opt.keep_not_existing,
+                &report,
             ),
             OutputType::Cobertura => output_cobertura(
-                source_root.as_deref(),
+                cobertura_source,
                 results,
                 output_path.as_deref(),
-                demangle,
+                demangler,
                 false,
             ),
             OutputType::CoberturaPretty => output_cobertura(
-                source_root.as_deref(),
+                cobertura_source,
                 results,
                 output_path.as_deref(),
-                demangle,
+                demangler,
                 true,
             ),
-            OutputType::Markdown => output_markdown(results, output_path.as_deref(), opt.precision),
+            OutputType::Markdown => output_markdown(results, output_path.as_deref(), precision),
+            OutputType::Percentage => {
+                output_total_percentage(results, output_path.as_deref(), precision)
+            }
+            OutputType::DeadFunctions => output_dead_functions(
+                results,
+                output_path.as_deref(),
+                if output_spec.demangle.is_some() || opt.dead_functions_demangle {
+                    demangler
+                } else {
+                    Demangler::None
+                },
+            ),
+            OutputType::BranchesJson => output_branches_json(results, output_path.as_deref()),
+            OutputType::TestsJson => output_tests_json(results, output_path.as_deref()),
+            OutputType::Junit => output_junit(
+                results,
+                output_path.as_deref(),
+                precision,
+                opt.junit_threshold,
+            ),
+            OutputType::Annotated => {
+                output_annotated(results, output_path.as_deref(), opt.annotated_context)
+            }
+            OutputType::Sarif => output_sarif(results, output_path.as_deref(), demangler),
+            #[cfg(feature = "blame")]
+            OutputType::Blame => {
+                let repo_dir = source_root
+                    .clone()
+                    .unwrap_or_else(|| std::env::current_dir().unwrap());
+                output_blame(results, output_path.as_deref(), &repo_dir)
+            }
         };
+        if let Err(e) = written {
+            error!("Failed to write the output: {}", e);
+            exit(1);
+        }
+    }
+
+    if let Some(summary_file) = &opt.summary_file {
+        if let Err(e) = output_summary(&iterator, Some(summary_file), opt.precision, &report) {
+            error!("Failed to write the summary: {}", e);
+            exit(1);
+        }
+    }
+
+    if opt.fail_on_decrease {
+        let baseline_path = opt.baseline.as_ref().unwrap();
+        let buffer = fs::read(baseline_path)
+            .unwrap_or_else(|e| panic!("Failed to read the baseline {:?}: {}", baseline_path, e));
+        let mut baseline = parse_lcov(buffer, false, true, false)
+            .unwrap_or_else(|e| panic!("Failed to parse the baseline {:?}: {}", baseline_path, e));
+        mark_informational(
+            baseline
+                .iter_mut()
+                .map(|(path, result)| (Path::new(path.as_str()), result)),
+            &opt.informational,
+        );
+        let decreases = get_coverage_decreases(&iterator, &baseline, opt.decrease_tolerance);
+        if !decreases.is_empty() {
+            for decrease in &decreases {
+                error!(
+                    "The coverage of {} decreased from {:.2}% to {:.2}%",
+                    decrease.path.as_deref().unwrap_or("the total"),
+                    decrease.baseline,
+                    decrease.current
+                );
+            }
+            exit(1);
+        }
+    }
+
+    if opt.strict && !parse_errors.is_empty() {
+        error!(
+            "{} input(s) couldn't be extracted or parsed, the coverage is incomplete",
+            parse_errors.len()
+        );
+        exit(1);
     }
+
+    drop(SOURCE_TMP_DIR.lock().unwrap().take());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use clap::CommandFactory;
-
     #[test]
     fn clap_debug_assert() {
         Opt::command().debug_assert();
     }
+
+    #[test]
+    fn test_generate_completions() {
+        let mut script = Vec::new();
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut Opt::command(),
+            "grcov",
+            &mut script,
+        );
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("--output-types"));
+        assert!(script.contains("--binary-path"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = get_capabilities();
+        let output_types = capabilities["outputTypes"].as_array().unwrap();
+        assert!(output_types.contains(&serde_json::json!("lcov")));
+        for output_type in output_types {
+            assert!(OutputType::from_str(output_type.as_str().unwrap()).is_ok());
+        }
+        assert_eq!(
+            output_types.contains(&serde_json::json!("blame")),
+            cfg!(feature = "blame")
+        );
+        assert_eq!(
+            capabilities["version"],
+            serde_json::json!(env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn test_output_spec_from_str() {
+        let spec = OutputSpec::from_str("covdir:4").unwrap();
+        assert!(spec.output_type == OutputType::Covdir);
+        assert_eq!(spec.precision, Some(4));
+
+        let spec = OutputSpec::from_str("markdown").unwrap();
+        assert!(spec.output_type == OutputType::Markdown);
+        assert_eq!(spec.precision, None);
+
+        let spec = OutputSpec::from_str("lcov:nodemangle").unwrap();
+        assert!(spec.output_type == OutputType::Lcov);
+        assert_eq!(spec.demangle, Some(DemangleSpec::Off));
+
+        let spec = OutputSpec::from_str("sarif:demangle=cpp").unwrap();
+        assert_eq!(spec.demangle, Some(DemangleSpec::With(Demangler::Cpp)));
+
+        let spec = OutputSpec::from_str("cobertura:demangle").unwrap();
+        assert_eq!(spec.demangle, Some(DemangleSpec::On));
+        assert_eq!(spec.precision, None);
+        assert_eq!(
+            spec.get_demangler(Demangler::None, Demangler::Rust),
+            Demangler::Rust
+        );
+        assert_eq!(
+            OutputSpec::from_str("lcov:nodemangle")
+                .unwrap()
+                .get_demangler(Demangler::Auto, Demangler::Auto),
+            Demangler::None
+        );
+        assert_eq!(
+            OutputSpec::from_str("lcov")
+                .unwrap()
+                .get_demangler(Demangler::None, Demangler::Auto),
+            Demangler::None
+        );
+
+        assert!(OutputSpec::from_str("html:demangle").is_err());
+        assert!(OutputSpec::from_str("lcov:demangle=unknown").is_err());
+        assert!(OutputSpec::from_str("lcov:2").is_err());
+        assert!(OutputSpec::from_str("html:two").is_err());
+        assert!(OutputSpec::from_str("unknown:2").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        assert_eq!(
+            parse_key_value("build=a=b"),
+            Ok(("build".to_string(), "a=b".to_string()))
+        );
+        assert!(parse_key_value("build").is_err());
+        assert!(parse_key_value("=1234").is_err());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert_eq!(Color::Auto.to_color_choice(None), ColorChoice::Auto);
+        assert_eq!(
+            Color::Auto.to_color_choice(Some(OsStr::new(""))),
+            ColorChoice::Auto
+        );
+        assert_eq!(
+            Color::Auto.to_color_choice(Some(OsStr::new("1"))),
+            ColorChoice::Never
+        );
+        assert_eq!(
+            Color::Always.to_color_choice(Some(OsStr::new("1"))),
+            ColorChoice::Always
+        );
+        assert_eq!(Color::Never.to_color_choice(None), ColorChoice::Never);
+    }
 }
@@ -1,79 +1,308 @@
 use crossbeam_channel::unbounded;
+use log::info;
 use md5::{Digest, Md5};
-use rustc_hash::FxHashMap;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde_json::{self, json, Value};
 use std::cell::RefCell;
 use std::collections::{hash_map, BTreeMap, BTreeSet};
+use std::env;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{self, BufWriter, Read, Write};
-use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::{
-    process::{self, Command, Stdio},
+    process::{Command, Stdio},
     thread,
 };
-use symbolic_common::Name;
+use symbolic_common::{Language, Name, NameMangling};
 use symbolic_demangle::{Demangle, DemangleOptions};
 use tabled::settings::Style;
 use tabled::{Table, Tabled};
 use uuid::Uuid;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
 
+use crate::covdir::split_path;
 use crate::defs::*;
 use crate::html;
+use crate::llvm_tools::run_with_stdin;
+use crate::path_rewriting::split_informational;
 
 macro_rules! demangle {
-    ($name: expr, $demangle: expr, $options: expr) => {{
-        if $demangle {
-            if let Some(name) = Name::from($name).demangle($options) {
-                StringOrRef::S(name)
-            } else {
-                StringOrRef::R($name)
-            }
+    ($name: expr, $demangler: expr) => {{
+        if let Some(name) = $demangler.demangle($name) {
+            StringOrRef::S(name)
         } else {
             StringOrRef::R($name)
         }
     }};
 }
 
-pub fn get_target_output_writable(output_file: Option<&Path>) -> Box<dyn Write> {
-    let write_target: Box<dyn Write> = match output_file {
-        Some(output) => {
-            if output.is_dir() {
-                panic!(
-                    "The output file {} is a directory, but must be a regular file.",
-                    output.display()
-                )
+/// The strategy used to demangle function names in the outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Demangler {
+    /// Detect the mangling scheme of each name.
+    #[default]
+    Auto,
+    /// Rust names, using either the legacy or the v0 mangling scheme.
+    Rust,
+    /// Rust names using the v0 mangling scheme only (starting with `_R`), other names are kept
+    /// as they are.
+    RustV0,
+    /// C++ names, using the Itanium mangling scheme.
+    Cpp,
+    /// No demangling.
+    None,
+}
+
+impl FromStr for Demangler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => Self::Auto,
+            "rust" => Self::Rust,
+            "rust-v0" => Self::RustV0,
+            "cpp" => Self::Cpp,
+            "none" => Self::None,
+            _ => return Err(format!("{} is not a supported demangler", s)),
+        })
+    }
+}
+
+impl Demangler {
+    /// Demangles `name`, returning `None` if it can't be demangled with this strategy.
+    pub fn demangle(self, name: &str) -> Option<String> {
+        let name = match self {
+            Self::Auto => Name::from(name),
+            Self::Rust => Name::new(name, NameMangling::Mangled, Language::Rust),
+            Self::RustV0 if name.starts_with("_R") => {
+                Name::new(name, NameMangling::Mangled, Language::Rust)
             }
-            Box::new(File::create(output).unwrap_or_else(|_| {
-                let parent = output.parent();
-                if let Some(parent_path) = parent {
-                    if !parent_path.exists() {
-                        panic!(
-                            "Cannot create file {} to dump coverage data, as the parent directory {} doesn't exist.",
-                            output.display(),
-                            parent_path.display()
-                        )
-                    }
-                }
-                panic!(
-                    "Cannot create the file {} to dump coverage data.",
-                    output.display()
-                )
-            }))
+            Self::Cpp => Name::new(name, NameMangling::Mangled, Language::Cpp),
+            Self::RustV0 | Self::None => return None,
+        };
+        name.demangle(DemangleOptions::name_only())
+    }
+}
+
+/// Whether the given output path is `-`, which means the standard output.
+pub fn is_stdout_path(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Creates the output directory if needed, and checks that files can be created in it, so that
+/// the outputs don't fail after all the coverage data was parsed.
+pub fn prepare_output_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    tempfile::tempfile_in(dir).map(|_| ())
+}
+
+/// Creates a temporary file next to the output file, to be renamed into place once it's fully
+/// written, so that the readers of the output never see a partial file.
+fn create_temp_output(output: &Path) -> io::Result<tempfile::NamedTempFile> {
+    // The temporary file must be on the same filesystem as the output for the rename to be atomic.
+    let dir = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".grcov");
+    // Temporary files are only readable by their owner, the outputs get the usual permissions.
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    builder.tempfile_in(dir)
+}
+
+/// Follows the symlinks of `path`, even when the final target doesn't exist yet.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    // Bounded, like the resolution of the OS, in case of a symlink loop.
+    for _ in 0..40 {
+        match fs::read_link(&path) {
+            Ok(target) => path = path.parent().unwrap_or(Path::new("")).join(target),
+            Err(_) => break,
+        }
+    }
+    path
+}
+
+/// An output file being written.
+pub(crate) enum OutputFile {
+    /// A temporary file, which is renamed to the output path by `persist`.
+    Atomic(tempfile::NamedTempFile, PathBuf),
+    /// The output path itself, when it's a special file which can't be replaced (e.g. a FIFO or
+    /// `/dev/stdout`).
+    Direct(File),
+}
+
+impl OutputFile {
+    /// Creates the output file. A regular file (or a missing one) is written to a temporary file,
+    /// and a symlink is resolved so that its target is replaced instead of the symlink itself.
+    pub(crate) fn create(output: &Path) -> io::Result<Self> {
+        let target = resolve_symlinks(output);
+        match fs::metadata(&target) {
+            Ok(metadata) if !metadata.is_file() => Ok(OutputFile::Direct(
+                fs::OpenOptions::new().write(true).open(&target)?,
+            )),
+            _ => Ok(OutputFile::Atomic(create_temp_output(&target)?, target)),
+        }
+    }
+
+    /// Completes the output, renaming the temporary file into place.
+    pub(crate) fn persist(self) -> io::Result<()> {
+        match self {
+            OutputFile::Atomic(file, path) => file.persist(path).map(|_| ()).map_err(|e| e.error),
+            OutputFile::Direct(mut file) => file.flush(),
         }
-        None => {
-            let stdout = io::stdout();
-            Box::new(stdout)
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Atomic(file, _) => file.write(buf),
+            OutputFile::Direct(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Atomic(file, _) => file.flush(),
+            OutputFile::Direct(file) => file.flush(),
+        }
+    }
+}
+
+/// An output file which is persisted when flushed. If the output fails before, the temporary
+/// file is removed and the output path is untouched.
+struct AtomicOutputFile {
+    file: Option<OutputFile>,
+    path: PathBuf,
+}
+
+impl Write for AtomicOutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file.as_mut() {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other(format!(
+                "The output file {} was already written.",
+                self.path.display()
+            ))),
         }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+            file.persist()?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the output file, or the standard output when it's `None` or `-`.
+///
+/// The file is only created, or replaced, when the writer is flushed.
+pub fn get_target_output_writable(output_file: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    let output = match output_file.filter(|output| !is_stdout_path(output)) {
+        Some(output) => output,
+        None => return Ok(Box::new(io::stdout())),
     };
-    write_target
+    if output.is_dir() {
+        return Err(io::Error::other(format!(
+            "The output file {} is a directory, but must be a regular file.",
+            output.display()
+        )));
+    }
+    match OutputFile::create(output) {
+        Ok(file) => Ok(Box::new(AtomicOutputFile {
+            file: Some(file),
+            path: output.to_path_buf(),
+        })),
+        Err(e) => {
+            let message = match output.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => format!(
+                    "Cannot create the file {} to dump coverage data, as the parent directory {} doesn't exist.",
+                    output.display(),
+                    parent.display()
+                ),
+                _ => format!(
+                    "Cannot create the file {} to dump coverage data: {}",
+                    output.display(),
+                    e
+                ),
+            };
+            Err(io::Error::new(e.kind(), message))
+        }
+    }
+}
+
+/// A visitor over the coverage results, allowing library users to implement their own output
+/// format.
+pub trait CovResultVisitor {
+    /// Called once for each source file, with its absolute and relative paths.
+    fn visit_file(&mut self, abs_path: &Path, rel_path: &Path, result: &CovResult);
+
+    /// Called once after all the files have been visited.
+    fn finish(&mut self) {}
+}
+
+/// Drives `visitor` over all the `results`, in order.
+pub fn for_each_result(results: &[ResultTuple], visitor: &mut impl CovResultVisitor) {
+    for (abs_path, rel_path, result) in results {
+        visitor.visit_file(abs_path, rel_path, result);
+    }
+    visitor.finish();
+}
+
+/// Version of the shape of the ActiveData-ETL records, only bumped on breaking changes.
+pub const ADE_SCHEMA_VERSION: u32 = 1;
+/// Version of the ActiveData-ETL records with the lines encoded as ranges (`--ade-line-ranges`):
+/// each list of lines is a list of `[first, last]` ranges of consecutive lines, inclusive.
+pub const ADE_LINE_RANGES_SCHEMA_VERSION: u32 = 2;
+/// Version of the shape of the covdir output, only bumped on breaking changes.
+pub const COVDIR_SCHEMA_VERSION: u32 = 1;
+/// Version of the shape of the summary output (`--summary-file`), only bumped on breaking changes.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+/// Collapses the sorted `lines` into ranges of consecutive lines, e.g. `[1, 2, 3, 5]` into
+/// `[[1, 3], [5, 5]]`.
+fn get_line_ranges(lines: &[u32]) -> Vec<[u32; 2]> {
+    let mut ranges: Vec<[u32; 2]> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some(range) if range[1].checked_add(1) == Some(line) => range[1] = line,
+            _ => ranges.push([line, line]),
+        }
+    }
+    ranges
 }
 
-pub fn output_activedata_etl(results: &[ResultTuple], output_file: Option<&Path>, demangle: bool) {
-    let demangle_options = DemangleOptions::name_only();
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
+pub fn output_activedata_etl(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    demangler: Demangler,
+    line_ranges: bool,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    let schema_version = if line_ranges {
+        ADE_LINE_RANGES_SCHEMA_VERSION
+    } else {
+        ADE_SCHEMA_VERSION
+    };
+    let encode_lines = |lines: &[u32]| -> Value {
+        if line_ranges {
+            json!(get_line_ranges(lines))
+        } else {
+            json!(lines)
+        }
+    };
 
     for (_, rel_path, result) in results {
         let covered: Vec<u32> = result
@@ -102,11 +331,12 @@ pub fn output_activedata_etl(results: &[ResultTuple], output_file: Option<&Path>
         }
         start_indexes.sort_unstable();
 
-        for (name, function) in &result.functions {
+        for (name, function) in sorted_functions(&result.functions) {
             // println!("{} {} {}", name, function.executed, function.start);
-            let mut func_end = end;
+            // The functions with an unknown start (0) have no known lines.
+            let mut func_end = if function.start == 0 { 0 } else { end };
 
-            for start in &start_indexes {
+            for start in start_indexes.iter().filter(|_| function.start != 0) {
                 if *start > function.start {
                     func_end = *start;
                     break;
@@ -135,20 +365,22 @@ pub fn output_activedata_etl(results: &[ResultTuple], output_file: Option<&Path>
                 writer,
                 "{}",
                 json!({
+                    "grcov_version": env!("CARGO_PKG_VERSION"),
+                    "schema_version": schema_version,
                     "language": "c/c++",
                     "file": {
                         "name": rel_path,
                     },
                     "method": {
-                        "name": demangle!(name, demangle, demangle_options),
-                        "covered": lines_covered,
-                        "uncovered": lines_uncovered,
+                        "name": demangle!(name, demangler),
+                        "covered": encode_lines(&lines_covered),
+                        "uncovered": encode_lines(&lines_uncovered),
                         "total_covered": lines_covered.len(),
                         "total_uncovered": lines_uncovered.len(),
                         "percentage_covered": lines_covered.len() as f32 / (lines_covered.len() + lines_uncovered.len()) as f32,
                     }
                 })
-            ).unwrap();
+            )?;
         }
 
         let orphan_covered: Vec<u32> = orphan_covered.into_iter().collect();
@@ -159,33 +391,124 @@ pub fn output_activedata_etl(results: &[ResultTuple], output_file: Option<&Path>
             writer,
             "{}",
             json!({
+                "grcov_version": env!("CARGO_PKG_VERSION"),
+                "schema_version": schema_version,
                 "language": "c/c++",
                 "is_file": true,
                 "file": {
                     "name": rel_path,
-                    "covered": covered,
-                    "uncovered": uncovered,
+                    "covered": encode_lines(&covered),
+                    "uncovered": encode_lines(&uncovered),
                     "total_covered": covered.len(),
                     "total_uncovered": uncovered.len(),
                     "percentage_covered": covered.len() as f32 / (covered.len() + uncovered.len()) as f32,
                 },
                 "method": {
-                    "covered": orphan_covered,
-                    "uncovered": orphan_uncovered,
+                    "covered": encode_lines(&orphan_covered),
+                    "uncovered": encode_lines(&orphan_uncovered),
                     "total_covered": orphan_covered.len(),
                     "total_uncovered": orphan_uncovered.len(),
                     "percentage_covered": orphan_covered.len() as f32 / (orphan_covered.len() + orphan_uncovered.len()) as f32,
                 }
             })
-        ).unwrap();
+        )?;
+    }
+
+    writer.flush()
+}
+
+/// Returns the functions ordered by start line and name, so that the outputs don't depend on the
+/// order of the hash map.
+pub(crate) fn sorted_functions(functions: &FunctionMap) -> Vec<(&String, &Function)> {
+    let mut functions: Vec<_> = functions.iter().collect();
+    functions.sort_by_key(|(name, function)| (function.start, *name));
+    functions
+}
+
+/// Removes the generic parameters of a demangled function name, e.g. `foo::bar::<u32>` and
+/// `ns::f<int>` become `foo::bar` and `ns::f`. The qualified paths, e.g. in
+/// `<foo::Bar<T> as core::fmt::Debug>::fmt`, and the `operator<` functions are kept.
+fn strip_generic_parameters(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut depth = 0;
+    let mut prev = None;
+    for c in name.chars() {
+        if depth > 0 {
+            match c {
+                '<' => depth += 1,
+                // The `->` of the function types.
+                '>' if prev != Some('-') => depth -= 1,
+                _ => {}
+            }
+        } else if c == '<'
+            && prev.is_some_and(|p: char| p.is_alphanumeric() || p == '_' || p == ':')
+            && !stripped.trim_end_matches(['<', '=']).ends_with("operator")
+        {
+            depth = 1;
+            // The `::` of the turbofish.
+            if let Some(without_colons) = stripped.strip_suffix("::") {
+                stripped.truncate(without_colons.len());
+            }
+        } else {
+            stripped.push(c);
+        }
+        prev = Some(c);
+    }
+    stripped
+}
+
+/// Merges the functions whose demangled names (with `demangler`, or the names themselves when
+/// they can't be demangled) are the same once their generic parameters are removed, and which
+/// start on the same line, e.g. the instances of a Rust generic function or of a C++ template
+/// (but not its explicit specializations). A group is kept under the first of its names, and is
+/// executed if any of its functions is.
+pub fn collapse_generic_functions(result: &mut CovResult, demangler: Demangler) {
+    let mut groups: BTreeMap<(String, u32), Vec<String>> = BTreeMap::new();
+    for (name, function) in &result.functions {
+        let demangled = demangler.demangle(name).unwrap_or_else(|| name.clone());
+        groups
+            .entry((strip_generic_parameters(&demangled), function.start))
+            .or_default()
+            .push(name.clone());
+    }
+
+    let mut renamed: FxHashMap<String, String> = FxHashMap::default();
+    for mut names in groups.into_values().filter(|names| names.len() > 1) {
+        names.sort_unstable();
+        let kept = names[0].clone();
+        for name in &names[1..] {
+            let function = result.functions.remove(name).unwrap();
+            result.functions.get_mut(&kept).unwrap().executed |= function.executed;
+            renamed.insert(name.clone(), kept.clone());
+        }
+    }
+    for name in result.line_functions.values_mut() {
+        if let Some(kept) = renamed.get(name) {
+            *name = kept.clone();
+        }
+    }
+}
+
+/// Adds the title and the metadata of the report, when given, to the JSON object of an output.
+fn add_report_metadata(output: &mut Value, report: &ReportMetadata) {
+    if let Some(title) = &report.title {
+        output["title"] = json!(title);
+    }
+    if !report.metadata.is_empty() {
+        output["metadata"] = json!(report.metadata);
     }
 }
 
-pub fn output_covdir(results: &[ResultTuple], output_file: Option<&Path>, precision: usize) {
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    let mut relative: FxHashMap<PathBuf, Rc<RefCell<CDDirStats>>> = FxHashMap::default();
+pub fn output_covdir(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    precision: usize,
+    report: &ReportMetadata,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    // The directories are keyed by their path, with a trailing `/`.
+    let mut relative: FxHashMap<String, Rc<RefCell<CDDirStats>>> = FxHashMap::default();
     let global = Rc::new(RefCell::new(CDDirStats::new("".to_string())));
-    relative.insert(PathBuf::from(""), global.clone());
 
     for (abs_path, rel_path, result) in results {
         let path = if rel_path.is_relative() {
@@ -194,30 +517,20 @@ pub fn output_covdir(results: &[ResultTuple], output_file: Option<&Path>, precis
             abs_path
         };
 
-        let parent = path.parent().unwrap();
-        let mut ancestors = Vec::new();
-        for ancestor in parent.ancestors() {
-            ancestors.push(ancestor);
-            if relative.contains_key(ancestor) {
-                break;
-            }
-        }
-
+        let (dirs, file_name) = split_path(path);
+        let mut dir_path = String::new();
         let mut prev_stats = global.clone();
 
-        while let Some(ancestor) = ancestors.pop() {
-            prev_stats = match relative.entry(ancestor.to_path_buf()) {
+        for dir in dirs {
+            dir_path.push_str(&dir);
+            dir_path.push('/');
+            prev_stats = match relative.entry(dir_path.clone()) {
                 hash_map::Entry::Occupied(s) => s.get().clone(),
                 hash_map::Entry::Vacant(p) => {
                     let mut prev_stats = prev_stats.borrow_mut();
-                    let path_tail = if ancestor == PathBuf::from("/") {
-                        "/".to_string()
-                    } else {
-                        ancestor.file_name().unwrap().to_str().unwrap().to_string()
-                    };
                     prev_stats
                         .dirs
-                        .push(Rc::new(RefCell::new(CDDirStats::new(path_tail))));
+                        .push(Rc::new(RefCell::new(CDDirStats::new(dir))));
                     let last = prev_stats.dirs.last_mut().unwrap();
                     p.insert(last.clone());
                     last.clone()
@@ -226,54 +539,127 @@ pub fn output_covdir(results: &[ResultTuple], output_file: Option<&Path>, precis
         }
 
         prev_stats.borrow_mut().files.push(CDFileStats::new(
-            path.file_name().unwrap().to_str().unwrap().to_string(),
+            file_name,
             result.lines.clone(),
             precision,
+            result.informational,
         ));
     }
 
     let mut global = global.take();
     global.set_stats(precision);
 
-    serde_json::to_writer(&mut writer, &global.into_json()).unwrap();
+    let mut covdir = global.into_json();
+    covdir["grcov_version"] = json!(env!("CARGO_PKG_VERSION"));
+    covdir["schema_version"] = json!(COVDIR_SCHEMA_VERSION);
+    add_report_metadata(&mut covdir, report);
+
+    serde_json::to_writer(&mut writer, &covdir)?;
+    writer.flush()
+}
+
+pub fn output_lcov(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    demangler: Demangler,
+    omit_zero: bool,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    write_lcov(&mut writer, results, demangler, omit_zero)?;
+    writer.flush()
+}
+
+/// Returns the key grouping `rel_path` with the other files sharing its first `depth` directories,
+/// e.g. `src_foo` for `src/foo/bar/a.c` with a depth of 2. The `%` and `_` of the directory names
+/// are percent-encoded, so that distinct groups always have distinct keys: `a_b/c` is `a%5Fb_c`,
+/// and `_root`, used for the files without any directory, can't be the key of another group.
+pub fn get_group_key(rel_path: &Path, depth: usize) -> String {
+    let dirs: Vec<_> = rel_path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .filter_map(|c| match c {
+                    Component::Normal(c) => {
+                        Some(c.to_string_lossy().replace('%', "%25").replace('_', "%5F"))
+                    }
+                    _ => None,
+                })
+                .take(depth)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if dirs.is_empty() {
+        "_root".to_string()
+    } else {
+        dirs.join("_")
+    }
+}
+
+/// Writes one lcov file per group of source files sharing their first `depth` directories into
+/// `output_dir`, named after the group key (see `get_group_key`).
+pub fn output_lcov_split(
+    results: &[ResultTuple],
+    output_dir: &Path,
+    demangler: Demangler,
+    omit_zero: bool,
+    depth: usize,
+) -> io::Result<()> {
+    let mut groups: BTreeMap<String, Vec<&ResultTuple>> = BTreeMap::new();
+    for result in results {
+        groups
+            .entry(get_group_key(&result.1, depth))
+            .or_default()
+            .push(result);
+    }
+
+    for (key, results) in groups {
+        let output_file = output_dir.join(format!("{}.info", key));
+        let mut writer = BufWriter::new(get_target_output_writable(Some(&output_file))?);
+        write_lcov(&mut writer, results, demangler, omit_zero)?;
+        writer.flush()?;
+    }
+
+    Ok(())
 }
 
-pub fn output_lcov(results: &[ResultTuple], output_file: Option<&Path>, demangle: bool) {
-    let demangle_options = DemangleOptions::name_only();
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    writer.write_all(b"TN:\n").unwrap();
+fn write_lcov<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = &'a ResultTuple>,
+    demangler: Demangler,
+    omit_zero: bool,
+) -> io::Result<()> {
+    writer.write_all(b"TN:\n")?;
 
     for (_, rel_path, result) in results {
         // println!("{} {:?}", rel_path, result.lines);
 
-        writeln!(writer, "SF:{}", rel_path.display()).unwrap();
+        writeln!(writer, "SF:{}", rel_path.display())?;
 
-        for (name, function) in &result.functions {
+        for (name, function) in sorted_functions(&result.functions) {
             writeln!(
                 writer,
                 "FN:{},{}",
                 function.start,
-                demangle!(name, demangle, demangle_options)
-            )
-            .unwrap();
+                demangle!(name, demangler)
+            )?;
         }
-        for (name, function) in &result.functions {
+        for (name, function) in sorted_functions(&result.functions) {
             writeln!(
                 writer,
                 "FNDA:{},{}",
                 i32::from(function.executed),
-                demangle!(name, demangle, demangle_options)
-            )
-            .unwrap();
+                demangle!(name, demangler)
+            )?;
         }
         if !result.functions.is_empty() {
-            writeln!(writer, "FNF:{}", result.functions.len()).unwrap();
+            writeln!(writer, "FNF:{}", result.functions.len())?;
             writeln!(
                 writer,
                 "FNH:{}",
                 result.functions.values().filter(|x| x.executed).count()
-            )
-            .unwrap();
+            )?;
         }
 
         // branch coverage information
@@ -281,36 +667,59 @@ pub fn output_lcov(results: &[ResultTuple], output_file: Option<&Path>, demangle
         let mut branch_hit = 0;
         for (line, taken) in &result.branches {
             branch_count += taken.len();
+            let ids = result
+                .branch_ids
+                .get(line)
+                .filter(|ids| ids.len() == taken.len());
             for (n, b_t) in taken.iter().enumerate() {
+                let (block, branch) = ids.map_or((0, n as u32), |ids| ids[n]);
                 writeln!(
                     writer,
-                    "BRDA:{},0,{},{}",
+                    "BRDA:{},{},{},{}",
                     line,
-                    n,
+                    block,
+                    branch,
                     if *b_t { "1" } else { "-" }
-                )
-                .unwrap();
+                )?;
                 if *b_t {
                     branch_hit += 1;
                 }
             }
         }
 
-        writeln!(writer, "BRF:{}", branch_count).unwrap();
-        writeln!(writer, "BRH:{}", branch_hit).unwrap();
+        writeln!(writer, "BRF:{}", branch_count)?;
+        writeln!(writer, "BRH:{}", branch_hit)?;
 
-        for (line, execution_count) in &result.lines {
-            writeln!(writer, "DA:{},{}", line, execution_count).unwrap();
+        // With omit_zero, lines which were never executed are only kept when they have branches or
+        // start a function, as the others may not be executable at all.
+        let function_starts: FxHashSet<u32> = if omit_zero {
+            result.functions.values().map(|f| f.start).collect()
+        } else {
+            FxHashSet::default()
+        };
+        let lines: Vec<(&u32, &u64)> = result
+            .lines
+            .iter()
+            .filter(|(line, execution_count)| {
+                !omit_zero
+                    || **execution_count > 0
+                    || result.branches.contains_key(line)
+                    || function_starts.contains(line)
+            })
+            .collect();
+        for (line, execution_count) in &lines {
+            writeln!(writer, "DA:{},{}", line, execution_count)?;
         }
-        writeln!(writer, "LF:{}", result.lines.len()).unwrap();
+        writeln!(writer, "LF:{}", lines.len())?;
         writeln!(
             writer,
             "LH:{}",
-            result.lines.values().filter(|&v| *v > 0).count()
-        )
-        .unwrap();
-        writer.write_all(b"end_of_record\n").unwrap();
+            lines.iter().filter(|(_, &v)| v > 0).count()
+        )?;
+        writer.write_all(b"end_of_record\n")?;
     }
+
+    Ok(())
 }
 
 fn get_digest(path: PathBuf) -> String {
@@ -344,20 +753,97 @@ where
         .unwrap_or_default()
 }
 
+fn is_commit_sha(s: &str) -> bool {
+    s.len() >= 40 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Reads the hash of the commit HEAD points to from a `.git` directory, following a symbolic
+/// reference to a loose or packed branch.
+fn read_git_head(git_dir: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let Some(reference) = head.strip_prefix("ref: ") else {
+        return is_commit_sha(head).then(|| head.to_string());
+    };
+
+    if let Ok(sha) = std::fs::read_to_string(git_dir.join(reference)) {
+        let sha = sha.trim();
+        return is_commit_sha(sha).then(|| sha.to_string());
+    }
+
+    let packed_refs = std::fs::read_to_string(git_dir.join("packed-refs")).ok()?;
+    packed_refs
+        .lines()
+        .find_map(|line| match line.split_once(' ') {
+            Some((sha, name)) if name == reference && is_commit_sha(sha) => Some(sha.to_string()),
+            _ => None,
+        })
+}
+
+/// Returns the hash of the HEAD commit of the repository in `repo_dir`, reading it from the `.git`
+/// directory or falling back on `git rev-parse HEAD` (e.g. for worktrees).
+pub fn get_git_head_sha(repo_dir: &Path) -> Option<String> {
+    if let Some(sha) = read_git_head(&repo_dir.join(".git")) {
+        return Some(sha);
+    }
+
+    let repo_dir = repo_dir.as_os_str();
+    let sha = get_git_output([
+        "-C".as_ref(),
+        repo_dir,
+        "rev-parse".as_ref(),
+        "HEAD".as_ref(),
+    ]);
+    let sha = sha.trim();
+    is_commit_sha(sha).then(|| sha.to_string())
+}
+
+/// Returns the name of a file in the coveralls output: its path relative to `vcs_root` when it's
+/// given and contains the file, or its relative path otherwise, always with '/' separators.
+fn get_coveralls_file_name(abs_path: &Path, rel_path: &Path, vcs_root: Option<&Path>) -> String {
+    vcs_root
+        .and_then(|vcs_root| abs_path.strip_prefix(vcs_root).ok())
+        .unwrap_or(rel_path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
 /// Returns a JSON object describing the given commit. Coveralls uses that to display commit info.
 ///
 /// \a vcs_branch is what user passed on the command line via `--vcs-branch`. This is included in
 /// the output, but doesn't affect the rest of the info (e.g. this function doesn't check if that
 /// branch actually points to the given commit).
-fn get_coveralls_git_info(commit_sha: &str, vcs_branch: &str) -> Value {
-    let status = Command::new("git")
+///
+/// The author, message and remotes are read from the repository in \a repo_dir (or the current
+/// directory), unless \a with_git_info is false.
+fn get_coveralls_git_info(
+    commit_sha: &str,
+    vcs_branch: &str,
+    repo_dir: Option<&Path>,
+    with_git_info: bool,
+) -> Value {
+    let git = |args: &[&str]| -> String {
+        let mut full_args: Vec<&OsStr> = Vec::new();
+        if let Some(repo_dir) = repo_dir {
+            full_args.push("-C".as_ref());
+            full_args.push(repo_dir.as_os_str());
+        }
+        full_args.extend(args.iter().map(OsStr::new));
+        get_git_output(full_args)
+    };
+
+    let mut status = Command::new("git");
+    if let Some(repo_dir) = repo_dir {
+        status.arg("-C").arg(repo_dir);
+    }
+    let status = status
         .arg("status")
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .status()
         .map(|exit_status| exit_status.success());
-    if let Ok(true) = status {
+    if with_git_info && matches!(status, Ok(true)) {
         // We have a valid Git repo -- the rest of the function will handle this case
     } else {
         return json!({
@@ -371,7 +857,7 @@ fn get_coveralls_git_info(commit_sha: &str, vcs_branch: &str) -> Value {
     // Runs `git log` with a given format, to extract some piece of commit info. On failure,
     // returns empty string.
     let gitlog = |format| -> String {
-        get_git_output([
+        git(&[
             "log",
             "--max-count=1",
             &format!("--pretty=format:{}", format),
@@ -386,7 +872,7 @@ fn get_coveralls_git_info(commit_sha: &str, vcs_branch: &str) -> Value {
     let message = gitlog("%s");
 
     let remotes: Value = {
-        let output = get_git_output(["remote", "--verbose"]);
+        let output = git(&["remote", "--verbose"]);
 
         let mut remotes = Vec::<Value>::new();
         for line in output.lines() {
@@ -427,12 +913,14 @@ pub fn output_coveralls(
     output_file: Option<&Path>,
     vcs_branch: &str,
     parallel: bool,
-    demangle: bool,
-) {
-    let demangle_options = DemangleOptions::name_only();
+    demangler: Demangler,
+    vcs_root: Option<&Path>,
+    with_git_info: bool,
+) -> io::Result<()> {
     let mut source_files = Vec::new();
 
     for (abs_path, rel_path, result) in results {
+        let name = get_coveralls_file_name(abs_path, rel_path, vcs_root);
         let end: u32 = result.lines.keys().last().unwrap_or(&0) + 1;
 
         let mut coverage = Vec::new();
@@ -457,23 +945,23 @@ pub fn output_coveralls(
 
         if !with_function_info {
             source_files.push(json!({
-                "name": rel_path,
+                "name": name,
                 "source_digest": get_digest(abs_path.clone()),
                 "coverage": coverage,
                 "branches": branches,
             }));
         } else {
             let mut functions = Vec::new();
-            for (name, function) in &result.functions {
+            for (name, function) in sorted_functions(&result.functions) {
                 functions.push(json!({
-                    "name": demangle!(name, demangle, demangle_options),
+                    "name": demangle!(name, demangler),
                     "start": function.start,
                     "exec": function.executed,
                 }));
             }
 
             source_files.push(json!({
-                "name": rel_path,
+                "name": name,
                 "source_digest": get_digest(abs_path.clone()),
                 "coverage": coverage,
                 "branches": branches,
@@ -482,7 +970,7 @@ pub fn output_coveralls(
         }
     }
 
-    let git = get_coveralls_git_info(commit_sha, vcs_branch);
+    let git = get_coveralls_git_info(commit_sha, vcs_branch, vcs_root, with_git_info);
 
     let mut result = json!({
         "git": git,
@@ -508,15 +996,117 @@ pub fn output_coveralls(
         obj.insert("service_job_id".to_string(), json!(service_job_id));
     }
 
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    serde_json::to_writer(&mut writer, &result).unwrap();
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &result)?;
+    writer.flush()
+}
+
+fn get_coveralls_done_payload(
+    repo_token: Option<&str>,
+    service_name: Option<&str>,
+    service_number: &str,
+    service_job_id: Option<&str>,
+) -> Value {
+    let mut payload = json!({
+        "payload": {
+            "build_num": service_number,
+            "status": "done",
+        },
+    });
+
+    if let Some(obj) = payload.as_object_mut() {
+        if let Some(repo_token) = repo_token {
+            obj.insert("repo_token".to_string(), json!(repo_token));
+        }
+        if let Some(service_name) = service_name {
+            obj.insert("service_name".to_string(), json!(service_name));
+        }
+        if let Some(service_job_id) = service_job_id {
+            obj.insert("service_job_id".to_string(), json!(service_job_id));
+        }
+    }
+
+    payload
+}
+
+/// Notifies Coveralls that all the jobs of a parallel build have been uploaded, so that it can
+/// merge them. This uses `curl`, the endpoint can be overridden with the `COVERALLS_ENDPOINT`
+/// environment variable (e.g. for Coveralls Enterprise).
+pub fn send_coveralls_done(
+    repo_token: Option<&str>,
+    service_name: Option<&str>,
+    service_number: &str,
+    service_job_id: Option<&str>,
+) -> Result<(), String> {
+    let endpoint =
+        env::var("COVERALLS_ENDPOINT").unwrap_or_else(|_| "https://coveralls.io".to_string());
+    let url = format!("{}/webhook", endpoint.trim_end_matches('/'));
+    let payload =
+        get_coveralls_done_payload(repo_token, service_name, service_number, service_job_id);
+
+    let output = run_with_stdin(
+        "curl",
+        payload.to_string(),
+        &[
+            "--silent".as_ref(),
+            "--show-error".as_ref(),
+            "--fail".as_ref(),
+            "--header".as_ref(),
+            "Content-Type: application/json".as_ref(),
+            "--data".as_ref(),
+            "@-".as_ref(),
+            url.as_ref(),
+        ],
+    )?;
+    info!(
+        "Coveralls webhook response: {}",
+        String::from_utf8_lossy(&output)
+    );
+
+    Ok(())
+}
+
+pub fn output_error_report(
+    parse_errors: &[ParseError],
+    output_file: Option<&Path>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer_pretty(&mut writer, parse_errors)?;
+    writeln!(writer)?;
+    writer.flush()
 }
 
-pub fn output_files(results: &[ResultTuple], output_file: Option<&Path>) {
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    for (_, rel_path, _) in results {
-        writeln!(writer, "{}", rel_path.display()).unwrap();
+pub fn output_files(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    with_stats: bool,
+    precision: usize,
+) -> io::Result<()> {
+    // The coverage of files without any line or branch isn't applicable, shown as '-'.
+    let percent = |percentage: Option<f64>| {
+        percentage.map_or("-".to_string(), |percentage| {
+            format!("{:.precision$}", percentage)
+        })
+    };
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    for (_, rel_path, result) in results {
+        if !with_stats {
+            writeln!(writer, "{}", rel_path.display())?;
+            continue;
+        }
+
+        let totals = CoverageTotals::of(result);
+        writeln!(
+            writer,
+            "{} {} {}",
+            rel_path.display(),
+            percent(totals.line_percentage()),
+            percent(totals.branch_percentage()),
+        )?;
     }
+
+    writer.flush()
 }
 
 pub fn output_html(
@@ -526,8 +1116,15 @@ pub fn output_html(
     branch_enabled: bool,
     output_config_file: Option<&Path>,
     precision: usize,
-) {
+    keep_not_existing: bool,
+    report: &ReportMetadata,
+) -> io::Result<()> {
     let output = if let Some(output_dir) = output_dir {
+        if is_stdout_path(output_dir) {
+            return Err(io::Error::other(
+                "The HTML output is a directory, so it can't be written to the standard output.",
+            ));
+        }
         PathBuf::from(output_dir)
     } else {
         PathBuf::from("./html")
@@ -535,19 +1132,23 @@ pub fn output_html(
 
     if output.exists() {
         if !output.is_dir() {
-            eprintln!("{} is not a directory", output.to_str().unwrap());
-            return;
+            return Err(io::Error::other(format!(
+                "The HTML output {} is not a directory.",
+                output.display()
+            )));
         }
-    } else if std::fs::create_dir_all(&output).is_err() {
-        eprintln!("Cannot create directory {}", output.to_str().unwrap());
-        return;
+    } else if let Err(e) = fs::create_dir_all(&output) {
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Cannot create the directory {}: {}", output.display(), e),
+        ));
     }
 
     let (sender, receiver) = unbounded();
 
     let stats = Arc::new(Mutex::new(HtmlGlobalStats::default()));
     let mut threads = Vec::with_capacity(num_threads);
-    let (tera, config) = html::get_config(output_config_file);
+    let (tera, config) = html::get_config(output_config_file, report);
     for i in 0..num_threads {
         let receiver = receiver.clone();
         let output = output.clone();
@@ -565,7 +1166,8 @@ pub fn output_html(
                     config,
                     branch_enabled,
                     precision,
-                );
+                    keep_not_existing,
+                )
             })
             .unwrap();
 
@@ -586,113 +1188,786 @@ pub fn output_html(
         sender.send(None).unwrap();
     }
 
-    for t in threads {
-        if t.join().is_err() {
-            process::exit(1);
-        }
+    // Join all the threads before returning the first error.
+    let results: Vec<_> = threads.into_iter().map(|t| t.join()).collect();
+    for result in results {
+        result.map_err(|_| io::Error::other("An HTML consumer thread panicked"))??;
     }
 
     let global = Arc::try_unwrap(stats).unwrap().into_inner().unwrap();
 
-    html::gen_index(&tera, &global, &config, &output, branch_enabled, precision);
+    html::gen_index(&tera, &global, &config, &output, branch_enabled, precision)?;
 
     for style in html::BadgeStyle::iter() {
-        html::gen_badge(&tera, &global.stats, &config, &output, style);
+        html::gen_badge(&tera, &global.stats, &config, &output, style)?;
     }
 
-    html::gen_coverage_json(&global.stats, &config, &output, precision);
+    html::gen_coverage_json(&global.stats, &config, &output, precision)
 }
 
-pub fn output_markdown(results: &[ResultTuple], output_file: Option<&Path>, precision: usize) {
-    #[derive(Tabled)]
-    struct LineSummary {
-        file: String,
-        coverage: String,
-        covered: String,
-        missed_lines: String,
+/// Writes the HTML report into the zip archive `output_file` (`./html.zip` by default) instead of
+/// a directory, by generating it in a temporary directory first.
+pub fn output_html_zip(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    num_threads: usize,
+    branch_enabled: bool,
+    output_config_file: Option<&Path>,
+    precision: usize,
+    keep_not_existing: bool,
+    report: &ReportMetadata,
+) -> io::Result<()> {
+    let output_file = match output_file {
+        Some(output_file) if is_stdout_path(output_file) => {
+            return Err(io::Error::other(
+                "The zipped HTML output can't be written to the standard output.",
+            ))
+        }
+        Some(output_file) => output_file,
+        None => Path::new("./html.zip"),
+    };
+
+    let tmp_dir = tempfile::tempdir()?;
+    output_html(
+        results,
+        Some(tmp_dir.path()),
+        num_threads,
+        branch_enabled,
+        output_config_file,
+        precision,
+        keep_not_existing,
+        report,
+    )?;
+
+    zip_directory(tmp_dir.path(), output_file)
+        .map_err(|e| io::Error::other(format!("Cannot write {}: {}", output_file.display(), e)))
+}
+
+/// Packs the content of `dir` into the zip archive `output_file`, with the paths relative to `dir`.
+fn zip_directory(dir: &Path, output_file: &Path) -> zip::result::ZipResult<()> {
+    let mut output = OutputFile::create(output_file)?;
+    match &mut output {
+        OutputFile::Atomic(file, _) => write_zip(dir, file.as_file_mut())?,
+        // The zip writer needs to seek, so the archive is written to a temporary file first.
+        OutputFile::Direct(file) => {
+            let mut zip_file = tempfile::tempfile()?;
+            write_zip(dir, &mut zip_file)?;
+            zip_file.rewind()?;
+            io::copy(&mut zip_file, file)?;
+        }
     }
+    output.persist()?;
+    Ok(())
+}
 
-    fn format_pair(start: u32, end: u32) -> String {
-        if start == end {
-            start.to_string()
+fn write_zip<W: Write + Seek>(dir: &Path, writer: W) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(writer));
+    let options = SimpleFileOptions::default();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::from)?;
+        let rel_path = entry.path().strip_prefix(dir).unwrap();
+        if rel_path.as_os_str().is_empty() {
+            continue;
+        }
+        let name = rel_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if entry.file_type().is_dir() {
+            zip.add_directory(name, options)?;
         } else {
-            format!("{}-{}", start, end)
+            zip.start_file(name, options)?;
+            io::copy(&mut File::open(entry.path())?, &mut zip)?;
         }
     }
+    zip.finish()?.into_inner().map_err(|e| e.into_error())?;
+    Ok(())
+}
 
-    fn format_lines(lines: &BTreeMap<u32, u64>) -> (usize, String) {
-        let mut total_missed = 0;
-        let mut missed = Vec::new();
-        let mut start: u32 = 0;
-        let mut end: u32 = 0;
-        for (&line, &hits) in lines {
-            if hits == 0 {
-                total_missed += 1;
-                if start == 0 {
-                    start = line;
-                }
-                end = line;
-            } else if start != 0 {
-                missed.push(format_pair(start, end));
-                start = 0;
+/// Collapses the uncovered lines into ranges (inclusive), the lines without coverage data between
+/// them don't interrupt a range.
+pub(crate) fn get_missed_ranges(lines: &BTreeMap<u32, u64>) -> Vec<(u32, u32)> {
+    let mut missed = Vec::new();
+    let mut start: u32 = 0;
+    let mut end: u32 = 0;
+    for (&line, &hits) in lines {
+        if hits == 0 {
+            if start == 0 {
+                start = line;
             }
+            end = line;
+        } else if start != 0 {
+            missed.push((start, end));
+            start = 0;
         }
-        if start != 0 {
-            missed.push(format_pair(start, end));
-        }
-        (total_missed, missed.join(", "))
     }
-
-    let mut summary = Vec::new();
-    let mut total_lines: usize = 0;
-    let mut total_covered: usize = 0;
-    for (_, rel_path, result) in results {
-        let (missed, missed_lines) = format_lines(&result.lines);
-        let covered: usize = result.lines.len() - missed;
-        summary.push(LineSummary {
-            file: rel_path.display().to_string(),
-            coverage: format!(
-                "{:.precision$}%",
-                (covered as f32 * 100.0 / result.lines.len() as f32),
-            ),
-            covered: format!("{} / {}", covered, result.lines.len()),
-            missed_lines,
-        });
-        total_lines += result.lines.len();
-        total_covered += covered;
+    if start != 0 {
+        missed.push((start, end));
     }
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    writeln!(writer, "{}", Table::new(summary).with(Style::markdown())).unwrap();
-    writeln!(writer).unwrap();
-    writeln!(
-        writer,
-        "Total coverage: {:.precision$}%",
-        (total_covered as f32 * 100.0 / total_lines as f32),
-    )
-    .unwrap()
+    missed
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::{collections::BTreeMap, path::Path};
+/// Outputs the uncovered regions of each file as JSON, with their source and `context` lines
+/// around them, e.g. for review bots.
+pub fn output_annotated(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    context: u32,
+) -> io::Result<()> {
+    let mut files = Vec::new();
+    for (abs_path, rel_path, result) in results {
+        let ranges = get_missed_ranges(&result.lines);
+        if ranges.is_empty() {
+            continue;
+        }
 
-    fn read_file(path: &Path) -> String {
-        let mut f =
-            File::open(path).unwrap_or_else(|_| panic!("{:?} file not found", path.file_name()));
-        let mut s = String::new();
-        f.read_to_string(&mut s).unwrap();
-        s
+        let source = crate::file_filter::read_source(abs_path);
+        let source_lines: Vec<&str> = source
+            .as_deref()
+            .map_or(Vec::new(), |s| s.lines().collect());
+        // Without the source, there is no context to show.
+        let (context, last_line) = if source.is_some() {
+            (context, source_lines.len() as u32)
+        } else {
+            (0, u32::MAX)
+        };
+
+        // Merge the regions whose context overlaps.
+        let mut windows: Vec<(u32, u32)> = Vec::new();
+        for (start, end) in ranges {
+            let start = start.saturating_sub(context).max(1);
+            let end = end.saturating_add(context).min(last_line.max(end));
+            match windows.last_mut() {
+                Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                _ => windows.push((start, end)),
+            }
+        }
+
+        let regions: Vec<Value> = windows
+            .into_iter()
+            .map(|(start, end)| {
+                let lines: Vec<Value> = (start..=end)
+                    .filter(|line| source.is_some() || result.lines.get(line) == Some(&0))
+                    .map(|line| {
+                        json!({
+                            "line": line,
+                            "source": source_lines.get(line as usize - 1),
+                            "uncovered": result.lines.get(&line) == Some(&0),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "start": start,
+                    "end": end,
+                    "lines": lines,
+                })
+            })
+            .collect();
+
+        files.push(json!({
+            "file": rel_path,
+            "regions": regions,
+        }));
     }
 
-    #[test]
-    fn test_lcov_brf_brh() {
-        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_lcov_brf_brh.info";
-        let file_path = tmp_dir.path().join(file_name);
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &json!({ "files": files }))?;
+    writer.flush()
+}
 
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
+/// Outputs the uncovered lines (collapsed into ranges) and the functions which weren't executed
+/// as SARIF results, so that they can be shown by code scanning tools.
+pub fn output_sarif(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    demangler: Demangler,
+) -> io::Result<()> {
+    let mut sarif_results = Vec::new();
+    for (_, rel_path, result) in results {
+        let uri = rel_path.to_str().unwrap().replace('\\', "/");
+        let location = |start: u32, end: u32| {
+            json!([{
+                "physicalLocation": {
+                    "artifactLocation": {
+                        "uri": uri,
+                        "uriBaseId": "%SRCROOT%",
+                    },
+                    "region": {
+                        "startLine": start,
+                        "endLine": end,
+                    },
+                },
+            }])
+        };
+
+        for (start, end) in get_missed_ranges(&result.lines) {
+            let text = if start == end {
+                format!("Line {} is not covered.", start)
+            } else {
+                format!("Lines {}-{} are not covered.", start, end)
+            };
+            sarif_results.push(json!({
+                "ruleId": "uncovered-line",
+                "level": "note",
+                "message": { "text": text },
+                "locations": location(start, end),
+            }));
+        }
+
+        for (name, function) in sorted_functions(&result.functions) {
+            if function.executed {
+                continue;
+            }
+            sarif_results.push(json!({
+                "ruleId": "uncovered-function",
+                "level": "note",
+                "message": {
+                    "text": format!("Function {} is never executed.", demangle!(name, demangler)),
+                },
+                "locations": location(function.start, function.start),
+            }));
+        }
+    }
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "grcov",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/mozilla/grcov",
+                    "rules": [
+                        {
+                            "id": "uncovered-line",
+                            "shortDescription": { "text": "Line not covered by the tests" },
+                            "defaultConfiguration": { "level": "note" },
+                        },
+                        {
+                            "id": "uncovered-function",
+                            "shortDescription": { "text": "Function not executed by the tests" },
+                            "defaultConfiguration": { "level": "note" },
+                        },
+                    ],
+                },
+            },
+            "results": sarif_results,
+        }],
+    });
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &sarif)?;
+    writer.flush()
+}
+
+/// Returns the number of lines executed by each input archive (see `--per-archive`), and the
+/// number of lines of all the files: the coverage of an archive is the one it would have alone.
+fn archive_line_counts<'a>(results: &[&'a ResultTuple]) -> (BTreeMap<&'a str, usize>, usize) {
+    let mut archives: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut total = 0;
+    for (_, _, result) in results {
+        total += result.lines.len();
+        for (line_no, line_archives) in &result.line_archives {
+            if result.lines.get(line_no).is_some_and(|&hits| hits > 0) {
+                for archive in line_archives {
+                    *archives.entry(archive).or_default() += 1;
+                }
+            }
+        }
+    }
+    (archives, total)
+}
+
+/// Returns the number of missed lines and their ranges, formatted as e.g. `3-5, 8`.
+fn format_missed_lines(lines: &BTreeMap<u32, u64>) -> (usize, String) {
+    let total_missed = lines.values().filter(|&&hits| hits == 0).count();
+    let missed: Vec<String> = get_missed_ranges(lines)
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect();
+    (total_missed, missed.join(", "))
+}
+
+/// Outputs a markdown table of the line coverage of each file, then the total coverage. The
+/// informational files are listed in their own table, and aren't counted in the total.
+pub fn output_markdown(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    precision: usize,
+) -> io::Result<()> {
+    #[derive(Tabled)]
+    struct LineSummary {
+        file: String,
+        coverage: String,
+        covered: String,
+        missed_lines: String,
+    }
+
+    let summarize = |results: &[&ResultTuple]| -> Vec<LineSummary> {
+        results
+            .iter()
+            .map(|(_, rel_path, result)| {
+                let totals = CoverageTotals::of(result);
+                LineSummary {
+                    file: rel_path.display().to_string(),
+                    coverage: format!(
+                        "{:.precision$}%",
+                        totals.line_percentage().unwrap_or(100.0),
+                    ),
+                    covered: format!("{} / {}", totals.covered_lines, totals.total_lines),
+                    missed_lines: format_missed_lines(&result.lines).1,
+                }
+            })
+            .collect()
+    };
+
+    let (results, informational) = split_informational(results);
+    let total = CoverageTotals::counted(results.iter().map(|(_, _, result)| result));
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    writeln!(
+        writer,
+        "{}",
+        Table::new(summarize(&results)).with(Style::markdown())
+    )?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "Total coverage: {:.precision$}%",
+        total.line_percentage().unwrap_or(100.0),
+    )?;
+
+    if !informational.is_empty() {
+        let summary = summarize(&informational);
+        writeln!(writer)?;
+        writeln!(writer, "Informational (not counted in the total coverage):")?;
+        writeln!(writer)?;
+        writeln!(writer, "{}", Table::new(summary).with(Style::markdown()))?;
+    }
+
+    let (archives, total_lines) = archive_line_counts(&results);
+    if !archives.is_empty() {
+        #[derive(Tabled)]
+        struct ArchiveSummary {
+            archive: String,
+            coverage: String,
+            covered: String,
+        }
+
+        let summary: Vec<ArchiveSummary> = archives
+            .into_iter()
+            .map(|(archive, covered)| ArchiveSummary {
+                archive: archive.to_string(),
+                coverage: format!(
+                    "{:.precision$}%",
+                    (covered as f32 * 100.0 / total_lines as f32),
+                ),
+                covered: format!("{} / {}", covered, total_lines),
+            })
+            .collect();
+        writeln!(writer)?;
+        writeln!(writer, "Coverage by archive:")?;
+        writeln!(writer)?;
+        writeln!(writer, "{}", Table::new(summary).with(Style::markdown()))?;
+    }
+
+    writer.flush()
+}
+
+/// Outputs the functions which were never executed, sorted by file and start line, e.g. to look
+/// for dead code.
+pub fn output_dead_functions(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    demangler: Demangler,
+) -> io::Result<()> {
+    let mut results: Vec<&ResultTuple> = results.iter().collect();
+    results.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+
+    let mut dead_functions = Vec::new();
+    for (_, rel_path, result) in results {
+        for (name, function) in sorted_functions(&result.functions) {
+            if function.executed {
+                continue;
+            }
+            dead_functions.push(json!({
+                "file": rel_path,
+                "line": function.start,
+                "name": demangle!(name, demangler),
+            }));
+        }
+    }
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &dead_functions)?;
+    writer.flush()
+}
+
+/// Outputs the branches of each line, as a JSON object mapping the files to objects mapping
+/// the lines to the list of their branches, telling whether each branch was taken.
+/// Files without branches are left out.
+pub fn output_branches_json(results: &[ResultTuple], output_file: Option<&Path>) -> io::Result<()> {
+    let branches: BTreeMap<String, &BTreeMap<u32, Vec<bool>>> = results
+        .iter()
+        .filter(|(_, _, result)| !result.branches.is_empty())
+        .map(|(_, rel_path, result)| (rel_path.display().to_string(), &result.branches))
+        .collect();
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &branches)?;
+    writer.flush()
+}
+
+/// Outputs the lines executed by each test, by file, as JSON, e.g. for test impact analysis. The
+/// tests are only known for some inputs (e.g. with `--per-test-profraw`).
+pub fn output_tests_json(results: &[ResultTuple], output_file: Option<&Path>) -> io::Result<()> {
+    let mut tests: BTreeMap<&str, BTreeMap<String, Vec<u32>>> = BTreeMap::new();
+    for (_, rel_path, result) in results {
+        for (line_no, line_tests) in &result.line_tests {
+            if !result.lines.contains_key(line_no) {
+                continue;
+            }
+            for test in line_tests {
+                tests
+                    .entry(test)
+                    .or_default()
+                    .entry(rel_path.display().to_string())
+                    .or_default()
+                    .push(*line_no);
+            }
+        }
+    }
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &tests)?;
+    writer.flush()
+}
+
+/// Outputs a JUnit XML report with a test case per file, e.g. for the test reports of CI
+/// services. The files whose line coverage is below `threshold` percent are failures, listing
+/// their missed lines, unless they're informational.
+pub fn output_junit(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    precision: usize,
+    threshold: f64,
+) -> io::Result<()> {
+    let mut results: Vec<&ResultTuple> = results.iter().collect();
+    results.sort_by(|(_, a, _), (_, b, _)| a.cmp(b));
+
+    let mut failures = 0;
+    let mut test_cases = Vec::new();
+    for (_, rel_path, result) in results {
+        let (_, missed_lines) = format_missed_lines(&result.lines);
+        let percentage = CoverageTotals::of(result)
+            .line_percentage()
+            .unwrap_or(100.0);
+        let failure = if percentage < threshold && !result.informational {
+            failures += 1;
+            Some((
+                format!(
+                    "Line coverage {:.precision$}% is below {}%",
+                    percentage, threshold
+                ),
+                format!("Missed lines: {}", missed_lines),
+            ))
+        } else {
+            None
+        };
+        test_cases.push((rel_path.display().to_string(), failure));
+    }
+
+    let mut writer = quick_xml::Writer::new_with_indent(Cursor::new(vec![]), b' ', 2);
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .unwrap();
+    let tests = test_cases.len().to_string();
+    let failures = failures.to_string();
+    for tag in ["testsuites", "testsuite"] {
+        let start = BytesStart::new(tag).with_attributes([
+            ("name", "grcov"),
+            ("tests", tests.as_str()),
+            ("failures", failures.as_str()),
+        ]);
+        writer.write_event(Event::Start(start)).unwrap();
+    }
+    for (path, failure) in test_cases {
+        let test_case = BytesStart::new("testcase")
+            .with_attributes([("name", path.as_str()), ("classname", "grcov")]);
+        match failure {
+            Some((message, missed_lines)) => {
+                writer.write_event(Event::Start(test_case)).unwrap();
+                let failure = BytesStart::new("failure")
+                    .with_attributes([("message", message.as_str()), ("type", "coverage")]);
+                writer.write_event(Event::Start(failure)).unwrap();
+                writer
+                    .write_event(Event::Text(BytesText::new(&missed_lines)))
+                    .unwrap();
+                writer
+                    .write_event(Event::End(BytesEnd::new("failure")))
+                    .unwrap();
+                writer
+                    .write_event(Event::End(BytesEnd::new("testcase")))
+                    .unwrap();
+            }
+            None => writer.write_event(Event::Empty(test_case)).unwrap(),
+        }
+    }
+    for tag in ["testsuite", "testsuites"] {
+        writer.write_event(Event::End(BytesEnd::new(tag))).unwrap();
+    }
+    let mut xml = writer.into_inner().into_inner();
+    xml.push(b'\n');
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    writer.write_all(&xml)?;
+    writer.flush()
+}
+
+/// Outputs only the percentage of covered lines over all the files (except the informational
+/// ones), e.g. to compare it to a threshold in a script.
+pub fn output_total_percentage(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    precision: usize,
+) -> io::Result<()> {
+    let percentage = CoverageTotals::counted(results.iter().map(|(_, _, result)| result))
+        .line_percentage()
+        .unwrap_or(100.0);
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    writeln!(writer, "{:.precision$}", percentage)?;
+    writer.flush()
+}
+
+/// Outputs the totals of the lines, branches and functions of all the files, as JSON, e.g. to
+/// check them against thresholds in CI whatever the output types. The totals of the
+/// informational files are under their own `informational` key.
+pub fn output_summary(
+    results: &[ResultTuple],
+    output_file: Option<&Path>,
+    precision: usize,
+    report: &ReportMetadata,
+) -> io::Result<()> {
+    let to_json = |(covered, total): (usize, usize)| {
+        let stats = CDStats::new(total, covered, precision);
+        json!({
+            "covered": stats.covered,
+            "total": stats.total,
+            "missed": stats.missed,
+            "percentage": stats.percent,
+        })
+    };
+    let totals = |results: &[&ResultTuple]| {
+        let totals = CoverageTotals::sum(results.iter().map(|(_, _, result)| result));
+        json!({
+            "lines": to_json((totals.covered_lines, totals.total_lines)),
+            "branches": to_json((totals.covered_branches, totals.total_branches)),
+            "functions": to_json((totals.covered_functions, totals.total_functions)),
+        })
+    };
+
+    let (results, informational) = split_informational(results);
+    let mut summary = totals(&results);
+    summary["grcov_version"] = json!(env!("CARGO_PKG_VERSION"));
+    summary["schema_version"] = json!(SUMMARY_SCHEMA_VERSION);
+    if !informational.is_empty() {
+        summary["informational"] = totals(&informational);
+    }
+    let (archives, total_lines) = archive_line_counts(&results);
+    if !archives.is_empty() {
+        summary["archives"] = archives
+            .into_iter()
+            .map(|(archive, covered)| {
+                (
+                    archive.to_string(),
+                    json!({ "lines": to_json((covered, total_lines)) }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+
+    add_report_metadata(&mut summary, report);
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file)?);
+    serde_json::to_writer(&mut writer, &summary)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, path::Path};
+
+    fn read_file(path: &Path) -> String {
+        let mut f =
+            File::open(path).unwrap_or_else(|_| panic!("{:?} file not found", path.file_name()));
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_is_stdout_path() {
+        assert!(is_stdout_path(Path::new("-")));
+        assert!(!is_stdout_path(Path::new("./-")));
+        assert!(!is_stdout_path(Path::new("lcov.info")));
+    }
+
+    #[test]
+    fn test_strip_generic_parameters() {
+        assert_eq!(strip_generic_parameters("foo::bar::<u32>"), "foo::bar");
+        assert_eq!(
+            strip_generic_parameters("foo::bar::<alloc::vec::Vec<u8>>"),
+            "foo::bar"
+        );
+        assert_eq!(
+            strip_generic_parameters("<foo::Bar<T> as core::fmt::Debug>::fmt"),
+            "<foo::Bar as core::fmt::Debug>::fmt"
+        );
+        assert_eq!(
+            strip_generic_parameters("std::vector<int, std::allocator<int> >::push_back"),
+            "std::vector::push_back"
+        );
+        assert_eq!(
+            strip_generic_parameters("apply<std::function<int () -> int>>"),
+            "apply"
+        );
+        assert_eq!(strip_generic_parameters("ns::operator<"), "ns::operator<");
+        assert_eq!(strip_generic_parameters("ns::operator<<"), "ns::operator<<");
+        assert_eq!(strip_generic_parameters("ns::f"), "ns::f");
+    }
+
+    #[test]
+    fn test_collapse_generic_functions() {
+        let function = |start, executed| Function { start, executed };
+        let mut result = CovResult {
+            lines: [(1, 1), (2, 0), (5, 0)].iter().cloned().collect(),
+            functions: [
+                ("_RINvCs1234_3foo3barjEB2_".to_string(), function(1, false)),
+                ("_RINvCs1234_3foo3barmEB2_".to_string(), function(1, true)),
+                ("_RNvCs1234_3foo3baz".to_string(), function(5, false)),
+                ("max<int>".to_string(), function(10, false)),
+                ("max<double>".to_string(), function(10, false)),
+                ("max<char>".to_string(), function(20, true)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            line_functions: [
+                (1, "_RINvCs1234_3foo3barmEB2_".to_string()),
+                (5, "_RNvCs1234_3foo3baz".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            ..Default::default()
+        };
+
+        collapse_generic_functions(&mut result, Demangler::Auto);
+
+        let mut functions: Vec<(&str, u32, bool)> = result
+            .functions
+            .iter()
+            .map(|(name, f)| (name.as_str(), f.start, f.executed))
+            .collect();
+        functions.sort_unstable();
+        assert_eq!(
+            functions,
+            vec![
+                ("_RINvCs1234_3foo3barjEB2_", 1, true),
+                ("_RNvCs1234_3foo3baz", 5, false),
+                ("max<char>", 20, true),
+                ("max<double>", 10, false),
+            ]
+        );
+        assert_eq!(result.line_functions[&1], "_RINvCs1234_3foo3barjEB2_");
+        assert_eq!(result.line_functions[&5], "_RNvCs1234_3foo3baz");
+    }
+
+    #[test]
+    fn test_for_each_result() {
+        #[derive(Default)]
+        struct Visitor {
+            files: Vec<(PathBuf, PathBuf, usize)>,
+            finished: bool,
+        }
+
+        impl CovResultVisitor for Visitor {
+            fn visit_file(&mut self, abs_path: &Path, rel_path: &Path, result: &CovResult) {
+                assert!(!self.finished);
+                self.files.push((
+                    abs_path.to_path_buf(),
+                    rel_path.to_path_buf(),
+                    result.lines.len(),
+                ));
+            }
+
+            fn finish(&mut self) {
+                self.finished = true;
+            }
+        }
+
+        let results = vec![
+            (
+                PathBuf::from("/src/foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("/src/foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult {
+                    lines: [(1, 1)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let mut visitor = Visitor::default();
+        for_each_result(&results, &mut visitor);
+
+        assert!(visitor.finished);
+        assert_eq!(
+            visitor.files,
+            vec![
+                (
+                    PathBuf::from("/src/foo/a.cpp"),
+                    PathBuf::from("foo/a.cpp"),
+                    2
+                ),
+                (
+                    PathBuf::from("/src/foo/b.cpp"),
+                    PathBuf::from("foo/b.cpp"),
+                    1
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lcov_brf_brh() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_lcov_brf_brh.info";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
                 lines: [(1, 10), (2, 11)].iter().cloned().collect(),
@@ -704,10 +1979,11 @@ mod tests {
                     map
                 },
                 functions: FxHashMap::default(),
+                ..Default::default()
             },
         )];
 
-        output_lcov(&results, Some(&file_path), false);
+        output_lcov(&results, Some(&file_path), Demangler::None, false).unwrap();
 
         let results = read_file(&file_path);
 
@@ -716,59 +1992,1145 @@ mod tests {
     }
 
     #[test]
-    fn test_lcov_demangle() {
+    fn test_demangler() {
+        let v0 = "_RINvNtC3std3mem8align_ofNtNtC3std3mem12DiscriminantE";
+        let cpp = "_ZN9wikipedia7article6formatEv";
+
+        assert_eq!(
+            Demangler::Auto.demangle(v0).unwrap(),
+            "std::mem::align_of::<std::mem::Discriminant>"
+        );
+        assert_eq!(
+            Demangler::Auto.demangle(cpp).unwrap(),
+            "wikipedia::article::format"
+        );
+        assert_eq!(
+            Demangler::Rust.demangle(v0).unwrap(),
+            "std::mem::align_of::<std::mem::Discriminant>"
+        );
+        assert_eq!(
+            Demangler::RustV0.demangle(v0).unwrap(),
+            "std::mem::align_of::<std::mem::Discriminant>"
+        );
+        assert_eq!(Demangler::RustV0.demangle(cpp), None);
+        assert_eq!(
+            Demangler::Cpp.demangle(cpp).unwrap(),
+            "wikipedia::article::format"
+        );
+        assert_eq!(Demangler::None.demangle(v0), None);
+        assert_eq!(Demangler::None.demangle(cpp), None);
+
+        assert_eq!(Demangler::from_str("rust-v0"), Ok(Demangler::RustV0));
+        assert!(Demangler::from_str("swift").is_err());
+    }
+
+    #[test]
+    fn test_lcov_omit_zero() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_lcov_demangle";
-        let file_path = tmp_dir.path().join(file_name);
+        let file_path = tmp_dir.path().join("test_lcov_omit_zero.info");
 
         let results = vec![(
             PathBuf::from("foo/bar/a.cpp"),
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
-                lines: BTreeMap::new(),
-                branches: BTreeMap::new(),
+                lines: [(1, 0), (2, 5), (3, 0), (4, 0)].iter().cloned().collect(),
+                branches: [(3, vec![false, false])].iter().cloned().collect(),
                 functions: {
                     let mut map = FxHashMap::default();
                     map.insert(
-                        "_RINvNtC3std3mem8align_ofNtNtC3std3mem12DiscriminantE".to_string(),
+                        "f".to_string(),
                         Function {
                             start: 1,
-                            executed: true,
-                        },
-                    );
-                    map.insert(
-                        "_ZN9wikipedia7article6formatEv".to_string(),
-                        Function {
-                            start: 2,
-                            executed: true,
-                        },
-                    );
-                    map.insert(
-                        "hello_world".to_string(),
-                        Function {
-                            start: 3,
-                            executed: true,
+                            executed: false,
                         },
                     );
                     map
                 },
+                ..Default::default()
             },
         )];
 
-        output_lcov(&results, Some(&file_path), true);
+        output_lcov(&results, Some(&file_path), Demangler::None, true).unwrap();
 
         let results = read_file(&file_path);
-
-        assert!(results.contains("FN:1,std::mem::align_of::<std::mem::Discriminant>\n"));
-        assert!(results.contains("FN:2,wikipedia::article::format\n"));
-        assert!(results.contains("FN:3,hello_world\n"));
+        assert!(results.contains("DA:1,0\n"));
+        assert!(results.contains("DA:2,5\n"));
+        assert!(results.contains("DA:3,0\n"));
+        assert!(!results.contains("DA:4,0\n"));
+        assert!(results.contains("LF:3\n"));
+        assert!(results.contains("LH:1\n"));
     }
 
     #[test]
-    fn test_covdir() {
+    fn test_output_files_with_stats() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_output_files_with_stats.txt");
+
+        let results = vec![
+            (
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 11), (3, 0)].iter().cloned().collect(),
+                    branches: [(1, vec![true, false]), (2, vec![true, true])]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/bar/b.cpp"),
+                PathBuf::from("foo/bar/b.cpp"),
+                CovResult {
+                    lines: [(1, 0)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        output_files(&results, Some(&file_path), false, 2).unwrap();
+        assert_eq!(read_file(&file_path), "foo/bar/a.cpp\nfoo/bar/b.cpp\n");
+
+        output_files(&results, Some(&file_path), true, 1).unwrap();
+        assert_eq!(
+            read_file(&file_path),
+            "foo/bar/a.cpp 66.7 75.0\nfoo/bar/b.cpp 0.0 -\n"
+        );
+    }
+
+    #[test]
+    fn test_lcov_functions_order() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_lcov_functions_order.info");
+
+        let mut functions = FxHashMap::default();
+        for (name, start) in [("c", 1), ("b", 7), ("a", 7), ("d", 3)].iter() {
+            functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                },
+            );
+        }
+        let results = vec![(
+            PathBuf::from("a.cpp"),
+            PathBuf::from("a.cpp"),
+            CovResult {
+                lines: [(1, 1)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions,
+                ..Default::default()
+            },
+        )];
+
+        output_lcov(&results, Some(&file_path), Demangler::None, false).unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains("FN:1,c\nFN:3,d\nFN:7,a\nFN:7,b\n"));
+        assert!(results.contains("FNDA:1,c\nFNDA:1,d\nFNDA:1,a\nFNDA:1,b\n"));
+    }
+
+    #[test]
+    fn test_get_group_key() {
+        assert_eq!(get_group_key(Path::new("src/foo/bar/a.c"), 1), "src");
+        assert_eq!(get_group_key(Path::new("src/foo/bar/a.c"), 2), "src_foo");
+        assert_eq!(get_group_key(Path::new("src/a.c"), 2), "src");
+        assert_eq!(get_group_key(Path::new("a.c"), 1), "_root");
+        assert_eq!(get_group_key(Path::new("/usr/include/a.h"), 1), "usr");
+        assert_eq!(get_group_key(Path::new("a_b/c/d.c"), 2), "a%5Fb_c");
+        assert_eq!(get_group_key(Path::new("a/b_c/d.c"), 2), "a_b%5Fc");
+        assert_eq!(get_group_key(Path::new("_root/a.c"), 1), "%5Froot");
+        assert_eq!(get_group_key(Path::new("100%/a.c"), 1), "100%25");
+    }
+
+    #[test]
+    fn test_lcov_split() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        let result = CovResult {
+            lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+            branches: BTreeMap::new(),
+            functions: FxHashMap::default(),
+            ..Default::default()
+        };
+        let results: Vec<ResultTuple> = ["foo/a.cpp", "foo/sub/b.cpp", "bar/c.cpp", "d.cpp"]
+            .iter()
+            .map(|path| (PathBuf::from(path), PathBuf::from(path), result.clone()))
+            .collect();
+
+        output_lcov_split(&results, tmp_dir.path(), Demangler::None, false, 1).unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["_root.info", "bar.info", "foo.info"]);
+
+        let foo = read_file(&tmp_dir.path().join("foo.info"));
+        assert!(foo.contains("SF:foo/a.cpp\n"));
+        assert!(foo.contains("SF:foo/sub/b.cpp\n"));
+        assert!(!foo.contains("SF:bar/c.cpp\n"));
+        assert!(read_file(&tmp_dir.path().join("_root.info")).contains("SF:d.cpp\n"));
+    }
+
+    #[test]
+    fn test_lcov_demangle() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_lcov_demangle";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: BTreeMap::new(),
+                branches: BTreeMap::new(),
+                functions: {
+                    let mut map = FxHashMap::default();
+                    map.insert(
+                        "_RINvNtC3std3mem8align_ofNtNtC3std3mem12DiscriminantE".to_string(),
+                        Function {
+                            start: 1,
+                            executed: true,
+                        },
+                    );
+                    map.insert(
+                        "_ZN9wikipedia7article6formatEv".to_string(),
+                        Function {
+                            start: 2,
+                            executed: true,
+                        },
+                    );
+                    map.insert(
+                        "hello_world".to_string(),
+                        Function {
+                            start: 3,
+                            executed: true,
+                        },
+                    );
+                    map
+                },
+                ..Default::default()
+            },
+        )];
+
+        output_lcov(&results, Some(&file_path), Demangler::Auto, false).unwrap();
+
+        let results = read_file(&file_path);
+
+        assert!(results.contains("FN:1,std::mem::align_of::<std::mem::Discriminant>\n"));
+        assert!(results.contains("FN:2,wikipedia::article::format\n"));
+        assert!(results.contains("FN:3,hello_world\n"));
+    }
+
+    #[test]
+    fn test_activedata_etl_versions() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_activedata_etl_versions.json");
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: {
+                    let mut functions = FxHashMap::default();
+                    functions.insert(
+                        "f".to_string(),
+                        Function {
+                            start: 1,
+                            executed: true,
+                        },
+                    );
+                    functions
+                },
+                ..Default::default()
+            },
+        )];
+
+        output_activedata_etl(&results, Some(&file_path), Demangler::None, false).unwrap();
+
+        let records: Vec<Value> = read_file(&file_path)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        for record in records {
+            assert_eq!(record["grcov_version"], env!("CARGO_PKG_VERSION"));
+            assert_eq!(record["schema_version"], ADE_SCHEMA_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_activedata_etl_line_ranges() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_activedata_etl_line_ranges.json");
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 1), (3, 1), (4, 0), (6, 1), (7, 0)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        output_activedata_etl(&results, Some(&file_path), Demangler::None, true).unwrap();
+
+        let record: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(record["schema_version"], ADE_LINE_RANGES_SCHEMA_VERSION);
+        assert_eq!(record["file"]["covered"], json!([[1, 3], [6, 6]]));
+        assert_eq!(record["file"]["uncovered"], json!([[4, 4], [7, 7]]));
+        assert_eq!(record["file"]["total_covered"], 4);
+        assert_eq!(record["file"]["total_uncovered"], 2);
+    }
+
+    #[test]
+    fn test_covdir() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_covdir.json";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![
+            (
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/bar/b.cpp"),
+                PathBuf::from("foo/bar/b.cpp"),
+                CovResult {
+                    lines: [(1, 0), (2, 10), (4, 0)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/c.cpp"),
+                PathBuf::from("foo/c.cpp"),
+                CovResult {
+                    lines: [(1, 10), (4, 1)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("/foo/d.cpp"),
+                PathBuf::from("/foo/d.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        output_covdir(&results, Some(&file_path), 2, &ReportMetadata::default()).unwrap();
+
+        let mut results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        let results_obj = results.as_object_mut().unwrap();
+        assert_eq!(
+            results_obj.remove("grcov_version").unwrap(),
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(
+            results_obj.remove("schema_version").unwrap(),
+            COVDIR_SCHEMA_VERSION
+        );
+        let expected_path = PathBuf::from("./test/").join(file_name);
+        let expected: Value = serde_json::from_str(&read_file(&expected_path)).unwrap();
+
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_covdir_windows_paths() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_covdir_windows_paths.json");
+
+        let result = |lines: &[(u32, u64)]| CovResult {
+            lines: lines.iter().cloned().collect(),
+            branches: BTreeMap::new(),
+            functions: FxHashMap::default(),
+            ..Default::default()
+        };
+        let results = vec![
+            (
+                PathBuf::from(r"C:\src\foo\bar\a.cpp"),
+                PathBuf::from(r"foo\bar\a.cpp"),
+                result(&[(1, 10), (2, 0)]),
+            ),
+            (
+                PathBuf::from(r"C:\src\foo\b.cpp"),
+                PathBuf::from(r"foo\b.cpp"),
+                result(&[(1, 1)]),
+            ),
+            (
+                PathBuf::from("C:/src/foo/bar/c.cpp"),
+                PathBuf::from("foo/bar/c.cpp"),
+                result(&[(1, 0)]),
+            ),
+        ];
+
+        output_covdir(&results, Some(&file_path), 2, &ReportMetadata::default()).unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        let keys = |dir: &Value| -> Vec<String> {
+            dir["children"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect()
+        };
+        assert_eq!(keys(&results), vec!["foo"]);
+        let foo = &results["children"]["foo"];
+        assert_eq!(foo["name"], "foo");
+        assert_eq!(keys(foo), vec!["b.cpp", "bar"]);
+        let bar = &foo["children"]["bar"];
+        assert_eq!(keys(bar), vec!["a.cpp", "c.cpp"]);
+        assert_eq!(bar["linesTotal"], 3);
+        assert_eq!(bar["linesCovered"], 1);
+    }
+
+    #[test]
+    fn test_coveralls_service_job_id() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_coveralls_service_job_id.json";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        let expected_service_job_id: &str = "100500";
+        let with_function_info: bool = true;
+        let parallel: bool = true;
+        output_coveralls(
+            &results,
+            None,
+            None,
+            "unused",
+            Some(expected_service_job_id),
+            "unused",
+            Some("unused"),
+            "unused",
+            with_function_info,
+            Some(&file_path),
+            "unused",
+            parallel,
+            Demangler::None,
+            None,
+            true,
+        )
+        .unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+
+        assert_eq!(results["service_job_id"], expected_service_job_id);
+    }
+
+    #[test]
+    fn test_coveralls_service_flag_name() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_coveralls_service_job_id.json";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        let expected_service_job_id: &str = "100500";
+        let expected_flag_name: &str = "expected flag name";
+        let with_function_info: bool = true;
+        let parallel: bool = true;
+        output_coveralls(
+            &results,
+            None,
+            None,
+            "unused",
+            Some(expected_service_job_id),
+            "unused",
+            Some(expected_flag_name),
+            "unused",
+            with_function_info,
+            Some(&file_path),
+            "unused",
+            parallel,
+            Demangler::None,
+            None,
+            true,
+        )
+        .unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+
+        assert_eq!(results["flag_name"], expected_flag_name);
+    }
+
+    #[test]
+    fn test_coveralls_token_field_is_absent_if_arg_is_none() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_coveralls_token.json";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        let token = None;
+        let with_function_info: bool = true;
+        let parallel: bool = true;
+        output_coveralls(
+            &results,
+            token,
+            None,
+            "unused",
+            None,
+            "unused",
+            Some("unused"),
+            "unused",
+            with_function_info,
+            Some(&file_path),
+            "unused",
+            parallel,
+            Demangler::None,
+            None,
+            true,
+        )
+        .unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+
+        assert_eq!(results.get("repo_token"), None);
+    }
+
+    #[test]
+    fn test_coveralls_service_fields_are_absent_if_args_are_none() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_coveralls_service_fields.json";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                ..Default::default()
+            },
+        )];
+
+        let service_name = None;
+        let service_job_id = None;
+        let with_function_info: bool = true;
+        let parallel: bool = true;
+        output_coveralls(
+            &results,
+            None,
+            service_name,
+            "unused",
+            service_job_id,
+            "unused",
+            None,
+            "unused",
+            with_function_info,
+            Some(&file_path),
+            "unused",
+            parallel,
+            Demangler::None,
+            None,
+            true,
+        )
+        .unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+
+        assert_eq!(results.get("service_name"), None);
+        assert_eq!(results.get("service_job_id"), None);
+        assert_eq!(results.get("flag_name"), None)
+    }
+
+    #[test]
+    fn test_coveralls_file_name() {
+        let abs_path = Path::new("/repo/project/src/main.rs");
+        let rel_path = Path::new("src/main.rs");
+        assert_eq!(
+            get_coveralls_file_name(abs_path, rel_path, None),
+            "src/main.rs"
+        );
+        assert_eq!(
+            get_coveralls_file_name(abs_path, rel_path, Some(Path::new("/repo"))),
+            "project/src/main.rs"
+        );
+        assert_eq!(
+            get_coveralls_file_name(abs_path, rel_path, Some(Path::new("/elsewhere"))),
+            "src/main.rs"
+        );
+        assert_eq!(
+            get_coveralls_file_name(abs_path, Path::new("src\\main.rs"), None),
+            "src/main.rs"
+        );
+    }
+
+    #[test]
+    fn test_coveralls_git_info() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let repo = tmp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args([
+                    "-c",
+                    "user.name=Alice",
+                    "-c",
+                    "user.email=alice@example.com",
+                ])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init"]);
+        git(&["remote", "add", "origin", "https://example.com/repo.git"]);
+        git(&["commit", "--allow-empty", "-m", "First commit"]);
+        let sha = get_git_output(["-C", repo.to_str().unwrap(), "rev-parse", "HEAD"]);
+        let sha = sha.trim();
+
+        let info = get_coveralls_git_info(sha, "main", Some(repo), true);
+        assert_eq!(info["head"]["id"], sha);
+        assert_eq!(info["head"]["author_name"], "Alice");
+        assert_eq!(info["head"]["committer_email"], "alice@example.com");
+        assert_eq!(info["head"]["message"], "First commit");
+        assert_eq!(info["branch"], "main");
+        assert_eq!(
+            info["remotes"],
+            json!([{"name": "origin", "url": "https://example.com/repo.git"}])
+        );
+
+        let info = get_coveralls_git_info(sha, "main", Some(repo), false);
+        assert_eq!(info, json!({"head": {"id": sha}, "branch": "main"}));
+    }
+
+    #[test]
+    fn test_read_git_head() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let git_dir = tmp_dir.path();
+
+        std::fs::write(git_dir.join("HEAD"), format!("{}\n", sha)).unwrap();
+        assert_eq!(read_git_head(git_dir).unwrap(), sha);
+
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(read_git_head(git_dir), None);
+
+        std::fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{} refs/heads/main\n", sha),
+        )
+        .unwrap();
+        assert_eq!(read_git_head(git_dir).unwrap(), sha);
+
+        let other_sha = "89abcdef0123456789abcdef0123456789abcdef";
+        std::fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        std::fs::write(git_dir.join("refs/heads/main"), other_sha).unwrap();
+        assert_eq!(read_git_head(git_dir).unwrap(), other_sha);
+    }
+
+    #[test]
+    fn test_coveralls_done_payload() {
+        let payload = get_coveralls_done_payload(Some("123"), Some("travis-ci"), "42", None);
+
+        assert_eq!(payload["repo_token"], "123");
+        assert_eq!(payload["service_name"], "travis-ci");
+        assert_eq!(payload["payload"]["build_num"], "42");
+        assert_eq!(payload["payload"]["status"], "done");
+        assert_eq!(payload.get("service_job_id"), None);
+    }
+
+    #[test]
+    fn test_error_report() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("errors.json");
+
+        let parse_errors = vec![ParseError {
+            file: "test/invalid_DA_record.info".to_string(),
+            format: "info".to_string(),
+            error: "Invalid record".to_string(),
+        }];
+        output_error_report(&parse_errors, Some(&file_path)).unwrap();
+
+        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            results,
+            json!([{
+                "file": "test/invalid_DA_record.info",
+                "format": "info",
+                "error": "Invalid record",
+            }])
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_markdown";
+        let file_path = tmp_dir.path().join(file_name);
+
+        let results = vec![
+            (
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/bar/b.cpp"),
+                PathBuf::from("foo/bar/b.cpp"),
+                CovResult {
+                    lines: [(1, 0), (2, 10), (4, 10), (5, 0), (7, 0)]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        output_markdown(&results, Some(&file_path), 2).unwrap();
+
+        let informational_path = tmp_dir.path().join("test_markdown_informational");
+        let mut informational = results.clone();
+        informational[1].2.informational = true;
+        output_markdown(&informational, Some(&informational_path), 2).unwrap();
+        let output = read_file(&informational_path);
+        assert!(output.contains("Total coverage: 100.00%"));
+        assert!(output.ends_with(
+            "Informational (not counted in the total coverage):
+
+| file          | coverage | covered | missed_lines |
+|---------------|----------|---------|--------------|
+| foo/bar/b.cpp | 40.00%   | 2 / 5   | 1, 5-7       |
+"
+        ));
+
+        let archives =
+            |names: &[&str]| -> BTreeSet<String> { names.iter().map(|n| n.to_string()).collect() };
+        let mut per_archive = results.clone();
+        per_archive[0].2.line_archives = [
+            (1, archives(&["shard1.zip", "shard2.zip"])),
+            (2, archives(&["shard1.zip"])),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        per_archive[1].2.line_archives = [
+            (2, archives(&["shard2.zip"])),
+            (4, archives(&["shard2.zip"])),
+            // Lines without hits (e.g. after excluding some tests) aren't counted.
+            (5, archives(&["shard2.zip"])),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let per_archive_path = tmp_dir.path().join("test_markdown_per_archive");
+        output_markdown(&per_archive, Some(&per_archive_path), 2).unwrap();
+        assert!(read_file(&per_archive_path).ends_with(
+            "Total coverage: 57.14%
+
+Coverage by archive:
+
+| archive    | coverage | covered |
+|------------|----------|---------|
+| shard1.zip | 28.57%   | 2 / 7   |
+| shard2.zip | 42.86%   | 3 / 7   |
+"
+        ));
+
+        let results = &read_file(&file_path);
+        let expected = "| file          | coverage | covered | missed_lines |
+|---------------|----------|---------|--------------|
+| foo/bar/a.cpp | 100.00%  | 2 / 2   |              |
+| foo/bar/b.cpp | 40.00%   | 2 / 5   | 1, 5-7       |
+
+Total coverage: 57.14%
+";
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_tests_json() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("tests.json");
+
+        let test_names =
+            |names: &[&str]| -> BTreeSet<String> { names.iter().map(|n| n.to_string()).collect() };
+        let results = vec![(
+            PathBuf::from("/src/foo.rs"),
+            PathBuf::from("foo.rs"),
+            CovResult {
+                lines: [(1, 2), (2, 1), (3, 0)].iter().cloned().collect(),
+                branches: BTreeMap::new(),
+                functions: FxHashMap::default(),
+                line_tests: [
+                    (1, test_names(&["test_a", "test_b"])),
+                    (2, test_names(&["test_b"])),
+                    // Lines which were filtered out aren't listed.
+                    (4, test_names(&["test_a"])),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        )];
+
+        output_tests_json(&results, Some(&file_path)).unwrap();
+        let tests: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            tests,
+            json!({
+                "test_a": {"foo.rs": [1]},
+                "test_b": {"foo.rs": [1, 2]},
+            })
+        );
+    }
+
+    #[test]
+    fn test_output_errors() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        let output_dir = tmp_dir.path().join("a").join("b");
+        prepare_output_dir(&output_dir).unwrap();
+        assert!(output_dir.is_dir());
+
+        // A directory can't be an output file, and the parent directory of the file must exist.
+        assert!(output_branches_json(&[], Some(&output_dir)).is_err());
+        let err = output_branches_json(&[], Some(&tmp_dir.path().join("c").join("out.json")))
+            .unwrap_err();
+        assert!(err.to_string().contains("doesn't exist"));
+
+        let file = tmp_dir.path().join("file");
+        output_branches_json(&[], Some(&file)).unwrap();
+        assert!(prepare_output_dir(&file).is_err());
+    }
+
+    #[test]
+    fn test_atomic_output() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file = tmp_dir.path().join("out.txt");
+        let dir_entries = || {
+            let mut entries: Vec<_> = fs::read_dir(tmp_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            entries.sort();
+            entries
+        };
+        fs::write(&file, "old").unwrap();
+
+        // The output is only replaced when the writer is flushed.
+        let mut writer = get_target_output_writable(Some(&file)).unwrap();
+        writer.write_all(b"new").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old");
+        assert_eq!(dir_entries().len(), 2);
+        writer.flush().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(dir_entries(), vec!["out.txt"]);
+        assert!(writer.write_all(b"more").is_err());
+        drop(writer);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+
+        // An output which isn't completed leaves the previous file and no temporary file.
+        let mut writer = get_target_output_writable(Some(&file)).unwrap();
+        writer.write_all(b"partial").unwrap();
+        drop(writer);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(dir_entries(), vec!["out.txt"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_ne!(mode & 0o044, 0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_output_special_files() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        // The target of a symlink is replaced, the symlink is kept.
+        let target = tmp_dir.path().join("target.txt");
+        let link = tmp_dir.path().join("link.txt");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+        let mut writer = get_target_output_writable(Some(&link)).unwrap();
+        writer.write_all(b"new").unwrap();
+        writer.flush().unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+
+        // A dangling symlink creates its target.
+        fs::remove_file(&target).unwrap();
+        let mut writer = get_target_output_writable(Some(&link)).unwrap();
+        writer.write_all(b"created").unwrap();
+        writer.flush().unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "created");
+
+        // A device is written directly, even through a symlink.
+        let null_link = tmp_dir.path().join("null");
+        std::os::unix::fs::symlink("/dev/null", &null_link).unwrap();
+        for output in [Path::new("/dev/null"), &null_link] {
+            let mut writer = get_target_output_writable(Some(output)).unwrap();
+            writer.write_all(b"discarded").unwrap();
+            writer.flush().unwrap();
+            zip_directory(tmp_dir.path(), output).unwrap();
+        }
+        assert!(std::os::unix::fs::FileTypeExt::is_char_device(
+            &fs::metadata("/dev/null").unwrap().file_type()
+        ));
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_junit() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("junit.xml");
+
+        let result = |lines: &[(u32, u64)]| CovResult {
+            lines: lines.iter().cloned().collect(),
+            branches: BTreeMap::new(),
+            functions: FxHashMap::default(),
+            ..Default::default()
+        };
+        let results = vec![
+            (
+                PathBuf::from("foo/b&c.cpp"),
+                PathBuf::from("foo/b&c.cpp"),
+                result(&[(1, 0), (2, 10), (4, 10), (5, 0), (7, 0)]),
+            ),
+            (
+                PathBuf::from("foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                result(&[(1, 10), (2, 0), (3, 11), (4, 12)]),
+            ),
+        ];
+
+        output_junit(&results, Some(&file_path), 2, 50.0).unwrap();
+
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="grcov" tests="2" failures="1">
+  <testsuite name="grcov" tests="2" failures="1">
+    <testcase name="foo/a.cpp" classname="grcov"/>
+    <testcase name="foo/b&amp;c.cpp" classname="grcov">
+      <failure message="Line coverage 40.00% is below 50%" type="coverage">Missed lines: 1, 5-7</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+        assert_eq!(read_file(&file_path), expected);
+
+        output_junit(&results, Some(&file_path), 2, 40.0).unwrap();
+        assert!(read_file(&file_path).contains(r#"failures="0""#));
+
+        // The informational files never fail.
+        let mut results = results;
+        results[0].2.informational = true;
+        output_junit(&results, Some(&file_path), 2, 50.0).unwrap();
+        assert!(read_file(&file_path).contains(r#"tests="2" failures="0""#));
+    }
+
+    #[test]
+    fn test_annotated() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_path = tmp_dir.path().join("a.c");
+        std::fs::write(&source_path, "l1\nl2\nl3\nl4\nl5\nl6\nl7\nl8\nl9\nl10\n").unwrap();
+        let file_path = tmp_dir.path().join("annotated.json");
+
+        let results = vec![
+            (
+                source_path,
+                PathBuf::from("a.c"),
+                CovResult {
+                    lines: [(2, 0), (3, 1), (5, 0), (7, 1), (9, 0)]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("missing.c"),
+                PathBuf::from("missing.c"),
+                CovResult {
+                    lines: [(1, 0), (2, 1)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("covered.c"),
+                PathBuf::from("covered.c"),
+                CovResult {
+                    lines: [(1, 1)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        output_annotated(&results, Some(&file_path), 1).unwrap();
+
+        let annotated: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        let files = annotated["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+
+        assert_eq!(files[0]["file"], "a.c");
+        let regions = files[0]["regions"].as_array().unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            (&regions[0]["start"], &regions[0]["end"]),
+            (&json!(1), &json!(6))
+        );
+        assert_eq!(
+            regions[0]["lines"][1],
+            json!({"line": 2, "source": "l2", "uncovered": true})
+        );
+        assert_eq!(
+            regions[0]["lines"][2],
+            json!({"line": 3, "source": "l3", "uncovered": false})
+        );
+        assert_eq!(
+            (&regions[1]["start"], &regions[1]["end"]),
+            (&json!(8), &json!(10))
+        );
+
+        assert_eq!(
+            files[1],
+            json!({
+                "file": "missing.c",
+                "regions": [{
+                    "start": 1,
+                    "end": 1,
+                    "lines": [{"line": 1, "source": null, "uncovered": true}],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_total_percentage() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_total_percentage");
+
+        let results = vec![
+            (
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/bar/b.cpp"),
+                PathBuf::from("foo/bar/b.cpp"),
+                CovResult {
+                    lines: [(1, 0), (2, 10), (4, 0)].iter().cloned().collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        output_total_percentage(&results, Some(&file_path), 2).unwrap();
+        assert_eq!(read_file(&file_path), "60.00\n");
+
+        output_total_percentage(&results, Some(&file_path), 0).unwrap();
+        assert_eq!(read_file(&file_path), "60\n");
+
+        let mut informational = results.clone();
+        informational[1].2.informational = true;
+        output_total_percentage(&informational, Some(&file_path), 2).unwrap();
+        assert_eq!(read_file(&file_path), "100.00\n");
+
+        // Without any line, the coverage isn't applicable and counts as full.
+        output_total_percentage(&[], Some(&file_path), 1).unwrap();
+        assert_eq!(read_file(&file_path), "100.0\n");
+    }
+
+    #[test]
+    fn test_summary() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_covdir.json";
-        let file_path = tmp_dir.path().join(file_name);
+        let file_path = tmp_dir.path().join("test_summary.json");
 
         let results = vec![
             (
@@ -776,8 +3138,18 @@ mod tests {
                 PathBuf::from("foo/bar/a.cpp"),
                 CovResult {
                     lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    branches: [(1, vec![true, false, false])].iter().cloned().collect(),
+                    functions: [(
+                        "f".to_string(),
+                        Function {
+                            start: 1,
+                            executed: true,
+                        },
+                    )]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                    ..Default::default()
                 },
             ),
             (
@@ -786,242 +3158,319 @@ mod tests {
                 CovResult {
                     lines: [(1, 0), (2, 10), (4, 0)].iter().cloned().collect(),
                     branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    functions: [(
+                        "g".to_string(),
+                        Function {
+                            start: 1,
+                            executed: false,
+                        },
+                    )]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                    ..Default::default()
                 },
             ),
+        ];
+
+        output_summary(&results, Some(&file_path), 2, &ReportMetadata::default()).unwrap();
+        let summary: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            summary,
+            json!({
+                "lines": {"covered": 3, "total": 5, "missed": 2, "percentage": 60.0},
+                "branches": {"covered": 1, "total": 3, "missed": 2, "percentage": 33.33},
+                "functions": {"covered": 1, "total": 2, "missed": 1, "percentage": 50.0},
+                "grcov_version": env!("CARGO_PKG_VERSION"),
+                "schema_version": SUMMARY_SCHEMA_VERSION,
+            })
+        );
+
+        let report = ReportMetadata {
+            title: Some("Nightly".to_string()),
+            metadata: [("build".to_string(), "1234".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        output_summary(&results, Some(&file_path), 2, &report).unwrap();
+        let summary: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(summary["title"], "Nightly");
+        assert_eq!(summary["metadata"], json!({"build": "1234"}));
+
+        let mut informational = results.clone();
+        informational[1].2.informational = true;
+        output_summary(
+            &informational,
+            Some(&file_path),
+            2,
+            &ReportMetadata::default(),
+        )
+        .unwrap();
+        let summary: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            summary["lines"],
+            json!({"covered": 2, "total": 2, "missed": 0, "percentage": 100.0})
+        );
+        assert_eq!(
+            summary["informational"]["lines"],
+            json!({"covered": 1, "total": 3, "missed": 2, "percentage": 33.33})
+        );
+        assert!(summary.get("archives").is_none());
+
+        let mut per_archive = results.clone();
+        per_archive[0].2.line_archives =
+            [(1, ["shard1.zip".to_string()].iter().cloned().collect())]
+                .iter()
+                .cloned()
+                .collect();
+        output_summary(
+            &per_archive,
+            Some(&file_path),
+            2,
+            &ReportMetadata::default(),
+        )
+        .unwrap();
+        let summary: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            summary["archives"],
+            json!({
+                "shard1.zip": {
+                    "lines": {"covered": 1, "total": 5, "missed": 4, "percentage": 20.0},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_branches_json() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_branches.json");
+
+        let results = vec![
             (
-                PathBuf::from("foo/c.cpp"),
-                PathBuf::from("foo/c.cpp"),
+                PathBuf::from("/src/foo/b.rs"),
+                PathBuf::from("foo/b.rs"),
                 CovResult {
-                    lines: [(1, 10), (4, 1)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
+                    lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+                    branches: [(1, vec![true, false]), (2, vec![false, false])]
+                        .iter()
+                        .cloned()
+                        .collect(),
                     functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
             (
-                PathBuf::from("/foo/d.cpp"),
-                PathBuf::from("/foo/d.cpp"),
+                PathBuf::from("/src/foo/a.rs"),
+                PathBuf::from("foo/a.rs"),
                 CovResult {
-                    lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                    lines: [(1, 1)].iter().cloned().collect(),
                     branches: BTreeMap::new(),
                     functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
         ];
 
-        output_covdir(&results, Some(&file_path), 2);
-
-        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
-        let expected_path = PathBuf::from("./test/").join(file_name);
-        let expected: Value = serde_json::from_str(&read_file(&expected_path)).unwrap();
-
-        assert_eq!(results, expected);
-    }
-
-    #[test]
-    fn test_coveralls_service_job_id() {
-        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_coveralls_service_job_id.json";
-        let file_path = tmp_dir.path().join(file_name);
-
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
-            PathBuf::from("foo/bar/a.cpp"),
-            CovResult {
-                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
-            },
-        )];
-
-        let expected_service_job_id: &str = "100500";
-        let with_function_info: bool = true;
-        let parallel: bool = true;
-        output_coveralls(
-            &results,
-            None,
-            None,
-            "unused",
-            Some(expected_service_job_id),
-            "unused",
-            Some("unused"),
-            "unused",
-            with_function_info,
-            Some(&file_path),
-            "unused",
-            parallel,
-            false,
+        output_branches_json(&results, Some(&file_path)).unwrap();
+        let branches: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            branches,
+            json!({
+                "foo/b.rs": {
+                    "1": [true, false],
+                    "2": [false, false],
+                },
+            })
         );
-
-        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
-
-        assert_eq!(results["service_job_id"], expected_service_job_id);
     }
 
     #[test]
-    fn test_coveralls_service_flag_name() {
+    fn test_output_html_errors() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_coveralls_service_job_id.json";
-        let file_path = tmp_dir.path().join(file_name);
-
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
-            PathBuf::from("foo/bar/a.cpp"),
-            CovResult {
-                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
-            },
-        )];
-
-        let expected_service_job_id: &str = "100500";
-        let expected_flag_name: &str = "expected flag name";
-        let with_function_info: bool = true;
-        let parallel: bool = true;
-        output_coveralls(
-            &results,
-            None,
-            None,
-            "unused",
-            Some(expected_service_job_id),
-            "unused",
-            Some(expected_flag_name),
-            "unused",
-            with_function_info,
-            Some(&file_path),
-            "unused",
-            parallel,
+        let file = tmp_dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let report = ReportMetadata::default();
+
+        let stdout = Path::new("-");
+        assert!(output_html(&[], Some(stdout), 1, false, None, 2, false, &report).is_err());
+        assert!(output_html_zip(&[], Some(stdout), 1, false, None, 2, false, &report).is_err());
+
+        let err = output_html(&[], Some(&file), 1, false, None, 2, false, &report).unwrap_err();
+        assert!(err.to_string().contains("is not a directory"));
+        assert!(output_html(
+            &[],
+            Some(&file.join("html")),
+            1,
             false,
-        );
-
-        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
-
-        assert_eq!(results["flag_name"], expected_flag_name);
-    }
-
-    #[test]
-    fn test_coveralls_token_field_is_absent_if_arg_is_none() {
-        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_coveralls_token.json";
-        let file_path = tmp_dir.path().join(file_name);
-
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
-            PathBuf::from("foo/bar/a.cpp"),
-            CovResult {
-                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
-            },
-        )];
-
-        let token = None;
-        let with_function_info: bool = true;
-        let parallel: bool = true;
-        output_coveralls(
-            &results,
-            token,
             None,
-            "unused",
+            2,
+            false,
+            &report
+        )
+        .is_err());
+        assert!(output_html_zip(
+            &[],
+            Some(&tmp_dir.path().join("missing").join("html.zip")),
+            1,
+            false,
             None,
-            "unused",
-            Some("unused"),
-            "unused",
-            with_function_info,
-            Some(&file_path),
-            "unused",
-            parallel,
+            2,
             false,
-        );
-
-        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
-
-        assert_eq!(results.get("repo_token"), None);
+            &report
+        )
+        .is_err());
+
+        let output_dir = tmp_dir.path().join("html");
+        output_html(&[], Some(&output_dir), 1, false, None, 2, false, &report).unwrap();
+        assert!(output_dir.join("index.html").is_file());
+        assert!(output_dir.join("coverage.json").is_file());
+        // The files are written to temporary files renamed into place.
+        assert!(WalkDir::new(&output_dir).into_iter().all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".grcov")));
     }
 
     #[test]
-    fn test_coveralls_service_fields_are_absent_if_args_are_none() {
+    fn test_zip_directory() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_coveralls_service_fields.json";
-        let file_path = tmp_dir.path().join(file_name);
-
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
-            PathBuf::from("foo/bar/a.cpp"),
-            CovResult {
-                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
-            },
-        )];
-
-        let service_name = None;
-        let service_job_id = None;
-        let with_function_info: bool = true;
-        let parallel: bool = true;
-        output_coveralls(
-            &results,
-            None,
-            service_name,
-            "unused",
-            service_job_id,
-            "unused",
-            None,
-            "unused",
-            with_function_info,
-            Some(&file_path),
-            "unused",
-            parallel,
-            false,
-        );
-
-        let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
-
-        assert_eq!(results.get("service_name"), None);
-        assert_eq!(results.get("service_job_id"), None);
-        assert_eq!(results.get("flag_name"), None)
+        let dir = tmp_dir.path().join("html");
+        std::fs::create_dir_all(dir.join("badges")).unwrap();
+        std::fs::write(dir.join("index.html"), "index").unwrap();
+        std::fs::write(dir.join("badges").join("flat.svg"), "badge").unwrap();
+        let zip_path = tmp_dir.path().join("html.zip");
+
+        zip_directory(&dir, &zip_path).unwrap();
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 2);
+
+        let mut archive = zip::ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["badges/", "badges/flat.svg", "index.html"]);
+        let mut content = String::new();
+        archive
+            .by_name("badges/flat.svg")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "badge");
     }
 
     #[test]
-    fn test_markdown() {
+    fn test_dead_functions() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_markdown";
-        let file_path = tmp_dir.path().join(file_name);
+        let file_path = tmp_dir.path().join("test_dead_functions.json");
 
+        let functions = |functions: &[(&str, u32, bool)]| -> FunctionMap {
+            functions
+                .iter()
+                .map(|&(name, start, executed)| (name.to_string(), Function { start, executed }))
+                .collect()
+        };
         let results = vec![
             (
-                PathBuf::from("foo/bar/a.cpp"),
-                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("/src/foo/b.rs"),
+                PathBuf::from("foo/b.rs"),
                 CovResult {
-                    lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                    lines: BTreeMap::new(),
                     branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    functions: functions(&[("_ZN3foo3bar17h0123456789abcdefE", 3, false)]),
+                    ..Default::default()
                 },
             ),
             (
-                PathBuf::from("foo/bar/b.cpp"),
-                PathBuf::from("foo/bar/b.cpp"),
+                PathBuf::from("/src/foo/a.rs"),
+                PathBuf::from("foo/a.rs"),
                 CovResult {
-                    lines: [(1, 0), (2, 10), (4, 10), (5, 0), (7, 0)]
-                        .iter()
-                        .cloned()
-                        .collect(),
+                    lines: BTreeMap::new(),
                     branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    functions: functions(&[
+                        ("late", 20, false),
+                        ("used", 1, true),
+                        ("early", 5, false),
+                    ]),
+                    ..Default::default()
                 },
             ),
         ];
 
-        output_markdown(&results, Some(&file_path), 2);
+        output_dead_functions(&results, Some(&file_path), Demangler::None).unwrap();
+        let dead_functions: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            dead_functions,
+            json!([
+                {"file": "foo/a.rs", "line": 5, "name": "early"},
+                {"file": "foo/a.rs", "line": 20, "name": "late"},
+                {"file": "foo/b.rs", "line": 3, "name": "_ZN3foo3bar17h0123456789abcdefE"},
+            ])
+        );
 
-        let results = &read_file(&file_path);
-        let expected = "| file          | coverage | covered | missed_lines |
-|---------------|----------|---------|--------------|
-| foo/bar/a.cpp | 100.00%  | 2 / 2   |              |
-| foo/bar/b.cpp | 40.00%   | 2 / 5   | 1, 5-7       |
+        output_dead_functions(&results, Some(&file_path), Demangler::Auto).unwrap();
+        let dead_functions: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(dead_functions[2]["name"], "foo::bar");
+    }
 
-Total coverage: 57.14%
-";
-        assert_eq!(results, expected);
+    #[test]
+    fn test_sarif() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("coverage.sarif");
+
+        let mut functions = FxHashMap::default();
+        functions.insert(
+            "covered".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+            },
+        );
+        functions.insert(
+            "uncovered".to_string(),
+            Function {
+                start: 5,
+                executed: false,
+            },
+        );
+        let results = vec![(
+            PathBuf::from("/src/foo/a.c"),
+            PathBuf::from("foo/a.c"),
+            CovResult {
+                lines: [(1, 1), (2, 0), (3, 1), (5, 0), (7, 0)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                branches: BTreeMap::new(),
+                functions,
+                ..Default::default()
+            },
+        )];
+
+        output_sarif(&results, Some(&file_path), Demangler::None).unwrap();
+
+        let sarif: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "grcov");
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0]["ruleId"], "uncovered-line");
+        assert_eq!(results[0]["level"], "note");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "foo/a.c");
+        assert_eq!(location["region"], json!({"startLine": 2, "endLine": 2}));
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            json!({"startLine": 5, "endLine": 7})
+        );
+        assert_eq!(results[1]["message"]["text"], "Lines 5-7 are not covered.");
+
+        assert_eq!(results[2]["ruleId"], "uncovered-function");
+        assert_eq!(
+            results[2]["message"]["text"],
+            "Function uncovered is never executed."
+        );
     }
 }
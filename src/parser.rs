@@ -1,7 +1,8 @@
-use flate2::read::GzDecoder;
+use flate2::read::MultiGzDecoder;
 use serde::{Deserialize, Deserializer};
 use std::cmp::Ordering;
-use std::collections::{btree_map, hash_map, BTreeMap};
+use std::collections::{btree_map, BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read};
@@ -10,13 +11,15 @@ use std::path::Path;
 use std::str;
 use std::sync::Arc;
 
-use log::error;
+use log::{error, warn};
 
 use quick_xml::encoding::Decoder;
 use quick_xml::events::attributes::AttrError;
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
 
+use regex::Regex;
+
 use rustc_hash::FxHashMap;
 
 use crate::defs::*;
@@ -110,6 +113,26 @@ fn remove_newline(l: &mut Vec<u8>) {
     }
 }
 
+/// Adds a branch identified by its block and branch numbers, merging it with the branch having
+/// the same numbers if there is one.
+fn add_identified_branch(
+    branches: &mut BTreeMap<u32, Vec<bool>>,
+    branch_ids: &mut BTreeMap<u32, Vec<(u32, u32)>>,
+    line_no: u32,
+    id: (u32, u32),
+    taken: bool,
+) {
+    let ids = branch_ids.entry(line_no).or_default();
+    let taken_branches = branches.entry(line_no).or_default();
+    match ids.iter().position(|&other_id| other_id == id) {
+        Some(i) => taken_branches[i] |= taken,
+        None => {
+            ids.push(id);
+            taken_branches.push(taken);
+        }
+    }
+}
+
 pub fn add_branch(branches: &mut BTreeMap<u32, Vec<bool>>, line_no: u32, no: u32, taken: bool) {
     match branches.entry(line_no) {
         btree_map::Entry::Occupied(c) => {
@@ -132,19 +155,120 @@ pub fn add_branch(branches: &mut BTreeMap<u32, Vec<bool>>, line_no: u32, no: u32
     };
 }
 
+/// Parses a lcov report. Records which aren't known are skipped, with a warning unless `lenient`
+/// is set. When `keep_branch_ids` is set, the branches of the different blocks of a line are kept
+/// apart, with their (block, branch) numbers in `branch_ids`, instead of being merged by their
+/// branch number.
 pub fn parse_lcov(
     buffer: Vec<u8>,
     branch_enabled: bool,
+    lenient: bool,
+    keep_branch_ids: bool,
 ) -> Result<Vec<(String, CovResult)>, ParserError> {
+    parse_lcov_records(buffer, branch_enabled, lenient, keep_branch_ids, None)
+        .map(|(results, _)| results)
+}
+
+/// Parses a lcov report like `parse_lcov`, but without the coverage of the tests (given by the
+/// `TN` records) matching `excluded_tests`: their execution counts are subtracted, and the lines
+/// which were only executed by them are dropped.
+pub fn parse_lcov_excluding_tests(
+    buffer: Vec<u8>,
+    branch_enabled: bool,
+    lenient: bool,
+    keep_branch_ids: bool,
+    excluded_tests: &Regex,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let (mut results, excluded_records) = parse_lcov_records(
+        buffer,
+        branch_enabled,
+        lenient,
+        keep_branch_ids,
+        Some(excluded_tests),
+    )?;
+
+    // The lines executed by the excluded tests and by the other ones, for each file.
+    let mut test_lines: FxHashMap<String, BTreeSet<u32>> = FxHashMap::default();
+    let mut product_lines: FxHashMap<String, BTreeSet<u32>> = FxHashMap::default();
+    for (i, (file, result)) in results.iter().enumerate() {
+        let lines = if excluded_records.binary_search(&i).is_ok() {
+            &mut test_lines
+        } else {
+            &mut product_lines
+        };
+        lines.entry(file.clone()).or_default().extend(
+            result
+                .lines
+                .iter()
+                .filter(|(_, &count)| count > 0)
+                .map(|(&line_no, _)| line_no),
+        );
+    }
+
+    for (i, (file, result)) in results.iter_mut().enumerate() {
+        if excluded_records.binary_search(&i).is_ok() {
+            result.lines.values_mut().for_each(|count| *count = 0);
+            result
+                .branches
+                .values_mut()
+                .for_each(|taken| taken.iter_mut().for_each(|taken| *taken = false));
+            result
+                .functions
+                .values_mut()
+                .for_each(|function| function.executed = false);
+        }
+        if let Some(test_lines) = test_lines.get(file.as_str()) {
+            let product_lines = product_lines.get(file.as_str());
+            result.lines.retain(|line_no, _| {
+                !test_lines.contains(line_no)
+                    || product_lines.is_some_and(|lines| lines.contains(line_no))
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+/// Parses the number starting with the digits `init` and the ones at the start of `iter`, consuming
+/// the character which follows them. Returns `None` if it doesn't fit in `T` (the remaining digits
+/// aren't consumed then).
+fn parse_lcov_number<'a, T: TryFrom<u64>>(
+    iter: &mut impl Iterator<Item = &'a u8>,
+    init: u64,
+) -> Option<T> {
+    iter.take_while(|&&c| c.is_ascii_digit())
+        .try_fold(init, |r, &x| {
+            r.checked_mul(10)?.checked_add(u64::from(x - b'0'))
+        })
+        .and_then(|number| T::try_from(number).ok())
+}
+
+/// The results of a lcov report, with the (sorted) indices of the records of the excluded tests.
+type LcovRecords = (Vec<(String, CovResult)>, Vec<usize>);
+
+/// Parses a lcov report, returning the indices of the records of the tests matching
+/// `excluded_tests` too.
+fn parse_lcov_records(
+    buffer: Vec<u8>,
+    branch_enabled: bool,
+    lenient: bool,
+    keep_branch_ids: bool,
+    excluded_tests: Option<&Regex>,
+) -> Result<LcovRecords, ParserError> {
     let mut cur_file = None;
     let mut cur_lines = BTreeMap::new();
     let mut cur_branches = BTreeMap::new();
+    let mut cur_branch_ids = BTreeMap::new();
     let mut cur_functions = FxHashMap::default();
 
     // We only log the duplicated FN error once per parse_lcov call.
     let mut duplicated_error_logged = false;
+    // Same for each unknown record type.
+    let mut unknown_keys_logged: Vec<Option<u32>> = Vec::new();
 
     let mut results = Vec::new();
+    let mut excluded_records = Vec::new();
+    let mut cur_test_excluded = false;
     let iter = &mut buffer.iter().peekable();
 
     const SF: u32 = (b'S' as u32) * (1 << 8) + (b'F' as u32);
@@ -158,26 +282,50 @@ pub fn parse_lcov(
         + (b'R' as u32) * (1 << 16)
         + (b'D' as u32) * (1 << 8)
         + (b'A' as u32);
+    const TN: u32 = (b'T' as u32) * (1 << 8) + (b'N' as u32);
+    // Records we know about but don't need.
+    const TNF: u32 = (b'T' as u32) * (1 << 16) + (b'N' as u32) * (1 << 8) + (b'F' as u32);
+    const VER: u32 = (b'V' as u32) * (1 << 16) + (b'E' as u32) * (1 << 8) + (b'R' as u32);
+    const LF: u32 = (b'L' as u32) * (1 << 8) + (b'F' as u32);
+    const LH: u32 = (b'L' as u32) * (1 << 8) + (b'H' as u32);
+    const FNF: u32 = (b'F' as u32) * (1 << 16) + (b'N' as u32) * (1 << 8) + (b'F' as u32);
+    const FNH: u32 = (b'F' as u32) * (1 << 16) + (b'N' as u32) * (1 << 8) + (b'H' as u32);
+    const BRF: u32 = (b'B' as u32) * (1 << 16) + (b'R' as u32) * (1 << 8) + (b'F' as u32);
+    const BRH: u32 = (b'B' as u32) * (1 << 16) + (b'R' as u32) * (1 << 8) + (b'H' as u32);
 
     let mut line = 0;
+    let invalid_record = |record: &str, line: usize| {
+        ParserError::InvalidRecord(format!("{} at line {}", record, line))
+    };
 
     while let Some(c) = iter.next() {
         line += 1;
         match *c {
             b'e' => {
                 // we've a end_of_record
+                if cur_test_excluded {
+                    excluded_records.push(results.len());
+                }
+                let Some(file) = cur_file.take() else {
+                    return Err(invalid_record("end_of_record", line));
+                };
                 results.push((
-                    cur_file.unwrap(),
+                    file,
                     CovResult {
                         lines: cur_lines,
                         branches: cur_branches,
                         functions: cur_functions,
+                        line_functions: BTreeMap::new(),
+                        branch_ids: cur_branch_ids,
+                        line_tests: BTreeMap::new(),
+                        line_archives: BTreeMap::new(),
+                        informational: false,
                     },
                 ));
 
-                cur_file = None;
                 cur_lines = BTreeMap::new();
                 cur_branches = BTreeMap::new();
+                cur_branch_ids = BTreeMap::new();
                 cur_functions = FxHashMap::default();
                 iter.take_while(|&&c| c != b'\n').last();
             }
@@ -185,25 +333,22 @@ pub fn parse_lcov(
                 continue;
             }
             _ => {
-                if *c != b'S' && *c != b'D' && *c != b'F' && *c != b'B' {
+                if !c.is_ascii_uppercase() {
                     iter.take_while(|&&c| c != b'\n').last();
                     continue;
                 }
 
-                let key = iter
-                    .take_while(|&&c| c.is_ascii_uppercase())
-                    .try_fold(*c as u32, |r, &x| {
-                        r.checked_mul(1 << 8)?.checked_add(u32::from(x))
-                    });
-
-                if key.is_none() {
-                    return Err(ParserError::InvalidRecord(format!(
-                        "Invalid key at line {}",
-                        line
-                    )));
+                // Keys of more than 4 letters don't fit, but they aren't known records anyway.
+                let mut key = Some(*c as u32);
+                while let Some(&&c) = iter.peek().filter(|c| c.is_ascii_uppercase()) {
+                    key = key.and_then(|r| r.checked_mul(1 << 8)?.checked_add(u32::from(c)));
+                    iter.next();
+                }
+                if iter.peek() == Some(&&b':') {
+                    iter.next();
                 }
 
-                match key.unwrap() {
+                match key.unwrap_or(0) {
                     SF => {
                         // SF:string
                         cur_file = Some(
@@ -223,27 +368,24 @@ pub fn parse_lcov(
                             }
                         }
 
-                        let line_no = iter
-                            .take_while(|&&c| c.is_ascii_digit())
-                            .fold(0, |r, &x| r * 10 + u32::from(x - b'0'));
+                        let line_no: u32 =
+                            parse_lcov_number(iter, 0).ok_or_else(|| invalid_record("DA", line))?;
 
-                        if iter.peek().is_none() {
-                            return Err(ParserError::InvalidRecord(format!("DA at line {}", line)));
-                        }
-                        let execution_count = if let Some(c) = iter.next() {
-                            if *c == b'-' {
+                        let execution_count = match iter.next() {
+                            Some(b'-') => {
                                 iter.take_while(|&&c| c != b'\n').last();
                                 0
-                            } else {
-                                iter.take_while(|&&c| c.is_ascii_digit())
-                                    .fold(u64::from(*c - b'0'), |r, &x| {
-                                        r * 10 + u64::from(x - b'0')
-                                    })
                             }
-                        } else {
-                            0
+                            Some(&c) if c.is_ascii_digit() => {
+                                parse_lcov_number(iter, u64::from(c - b'0'))
+                                    .ok_or_else(|| invalid_record("DA", line))?
+                            }
+                            _ => return Err(invalid_record("DA", line)),
                         };
-                        *cur_lines.entry(line_no).or_insert(0) += execution_count;
+                        let count = cur_lines.entry(line_no).or_insert(0);
+                        *count = count
+                            .checked_add(execution_count)
+                            .ok_or_else(|| invalid_record("DA", line))?;
                     }
                     FN => {
                         // FN:int,string
@@ -255,9 +397,8 @@ pub fn parse_lcov(
                                 )));
                             }
                         }
-                        let start = iter
-                            .take_while(|&&c| c.is_ascii_digit())
-                            .fold(0, |r, &x| r * 10 + u32::from(x - b'0'));
+                        let start: u32 =
+                            parse_lcov_number(iter, 0).ok_or_else(|| invalid_record("FN", line))?;
                         if iter.peek().is_none() {
                             return Err(ParserError::InvalidRecord(format!("FN at line {}", line)));
                         }
@@ -269,7 +410,7 @@ pub fn parse_lcov(
                             error!(
                                 "FN '{}' duplicated for '{}' in a lcov file",
                                 f_name,
-                                cur_file.as_ref().unwrap()
+                                cur_file.as_deref().unwrap_or_default()
                             );
                             duplicated_error_logged = true;
                         }
@@ -291,9 +432,8 @@ pub fn parse_lcov(
                                 )));
                             }
                         }
-                        let executed = iter
-                            .take_while(|&&c| c.is_ascii_digit())
-                            .fold(0, |r, &x| r * 10 + u64::from(x - b'0'));
+                        let executed: u64 = parse_lcov_number(iter, 0)
+                            .ok_or_else(|| invalid_record("FNDA", line))?;
                         if iter.peek().is_none() {
                             return Err(ParserError::InvalidRecord(format!(
                                 "FNDA at line {}",
@@ -324,27 +464,24 @@ pub fn parse_lcov(
                                     )));
                                 }
                             }
-                            let line_no = iter
-                                .take_while(|&&c| c.is_ascii_digit())
-                                .fold(0, |r, &x| r * 10 + u32::from(x - b'0'));
+                            let line_no: u32 = parse_lcov_number(iter, 0)
+                                .ok_or_else(|| invalid_record("BRDA", line))?;
                             if iter.peek().is_none() {
                                 return Err(ParserError::InvalidRecord(format!(
                                     "BRDA at line {}",
                                     line
                                 )));
                             }
-                            let _block_number = iter
-                                .take_while(|&&c| c.is_ascii_digit())
-                                .fold(0, |r, &x| r * 10 + u64::from(x - b'0'));
+                            let block_number: u32 = parse_lcov_number(iter, 0)
+                                .ok_or_else(|| invalid_record("BRDA", line))?;
                             if iter.peek().is_none() {
                                 return Err(ParserError::InvalidRecord(format!(
                                     "BRDA at line {}",
                                     line
                                 )));
                             }
-                            let branch_number = iter
-                                .take_while(|&&c| c.is_ascii_digit())
-                                .fold(0, |r, &x| r * 10 + u32::from(x - b'0'));
+                            let branch_number: u32 = parse_lcov_number(iter, 0)
+                                .ok_or_else(|| invalid_record("BRDA", line))?;
                             if iter.peek().is_none() {
                                 return Err(ParserError::InvalidRecord(format!(
                                     "BRDA at line {}",
@@ -354,12 +491,47 @@ pub fn parse_lcov(
                             let taken = iter
                                 .take_while(|&&c| c != b'\n' && c != b'\r')
                                 .any(|&x| x != b'-');
-                            add_branch(&mut cur_branches, line_no, branch_number, taken);
+                            if keep_branch_ids {
+                                add_identified_branch(
+                                    &mut cur_branches,
+                                    &mut cur_branch_ids,
+                                    line_no,
+                                    (block_number, branch_number),
+                                    taken,
+                                );
+                            } else {
+                                add_branch(&mut cur_branches, line_no, branch_number, taken);
+                            }
                         } else {
                             iter.take_while(|&&c| c != b'\n').last();
                         }
                     }
+                    TN => {
+                        // TN:string
+                        let test_name: String = iter
+                            .take_while(|&&c| c != b'\n' && c != b'\r')
+                            .map(|&c| c as char)
+                            .collect();
+                        cur_test_excluded =
+                            excluded_tests.is_some_and(|excluded| excluded.is_match(&test_name));
+                    }
+                    TNF | VER | LF | LH | FNF | FNH | BRF | BRH => {
+                        iter.take_while(|&&c| c != b'\n').last();
+                    }
                     _ => {
+                        if !lenient && !unknown_keys_logged.contains(&key) {
+                            let key_name: String = match key {
+                                Some(key) => key
+                                    .to_be_bytes()
+                                    .iter()
+                                    .filter(|&&c| c != 0)
+                                    .map(|&c| c as char)
+                                    .collect(),
+                                None => "(too long)".to_string(),
+                            };
+                            warn!("Unknown lcov record '{}' at line {}", key_name, line);
+                            unknown_keys_logged.push(key);
+                        }
                         iter.take_while(|&&c| c != b'\n').last();
                     }
                 }
@@ -367,7 +539,7 @@ pub fn parse_lcov(
         }
     }
 
-    Ok(results)
+    Ok((results, excluded_records))
 }
 
 #[derive(Debug, Deserialize)]
@@ -407,6 +579,11 @@ struct GcovBr {
     count: u64,
     throw: bool,
     fallthrough: bool,
+    // Only in the reports of gcc >= 14.
+    #[serde(default)]
+    source_block_id: Option<u32>,
+    #[serde(default)]
+    destination_block_id: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -447,66 +624,210 @@ where
     }
 }
 
-pub fn parse_gcov_gz(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserError> {
+/// Parses a gzipped gcov JSON report (`gcov --json-format`). When `exclude_branch_throw` is set,
+/// the exception edges of the branches (e.g. the ones of C++ calls which can throw) are dropped.
+/// When `keep_branch_ids` is set, the (source block, destination block) numbers of the branches,
+/// which gcc >= 14 reports, are kept in `branch_ids`. Concatenated gzip members, e.g. of several
+/// reports, are all parsed.
+pub fn parse_gcov_gz(
+    gcov_path: &Path,
+    exclude_branch_throw: bool,
+    keep_branch_ids: bool,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
     let f = File::open(gcov_path)
         .unwrap_or_else(|_| panic!("Failed to open gcov file {}", gcov_path.display()));
 
-    let file = BufReader::new(&f);
-    let gz = GzDecoder::new(file);
-    let mut gcov: GcovJson = serde_json::from_reader(gz).unwrap();
-    let mut results = Vec::new();
+    parse_gcov_json(
+        MultiGzDecoder::new(BufReader::new(&f)),
+        exclude_branch_throw,
+        keep_branch_ids,
+    )
+}
 
-    if gcov.format_version != "1" {
-        error!(
-            "Format version {} is not expected, please file a bug on https://github.com/mozilla/grcov",
-            gcov.format_version
-        );
-    }
+/// Parses a gzipped gcov JSON report from memory, like `parse_gcov_gz`.
+pub fn parse_gcov_gz_buf(
+    buffer: &[u8],
+    exclude_branch_throw: bool,
+    keep_branch_ids: bool,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
+    parse_gcov_json(
+        MultiGzDecoder::new(buffer),
+        exclude_branch_throw,
+        keep_branch_ids,
+    )
+}
+
+fn parse_gcov_json<T: Read>(
+    reader: T,
+    exclude_branch_throw: bool,
+    keep_branch_ids: bool,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let mut results = Vec::new();
+    let mut documents = 0;
+
+    // Concatenated reports are a stream of JSON documents.
+    for gcov in serde_json::Deserializer::from_reader(reader).into_iter::<GcovJson>() {
+        let mut gcov =
+            gcov.map_err(|e| ParserError::Parse(format!("Invalid gcov JSON report: {}", e)))?;
+        documents += 1;
+
+        if gcov.format_version != "1" {
+            error!(
+                "Format version {} is not expected, please file a bug on https://github.com/mozilla/grcov",
+                gcov.format_version
+            );
+        }
 
-    for mut file in gcov.files.drain(..) {
-        let mut lines = BTreeMap::new();
-        let mut branches = BTreeMap::new();
-        for mut line in file.lines.drain(..) {
-            lines.insert(line.line_number, line.count);
-            if !line.branches.is_empty() {
+        for mut file in gcov.files.drain(..) {
+            // Lines refer to the mangled names of the functions, while we use the demangled ones.
+            let demangled_names: FxHashMap<&str, &str> = file
+                .functions
+                .iter()
+                .map(|fun| (fun.name.as_str(), fun.demangled_name.as_str()))
+                .collect();
+
+            let mut lines = BTreeMap::new();
+            let mut branches = BTreeMap::new();
+            let mut branch_ids = BTreeMap::new();
+            let mut line_functions = BTreeMap::new();
+            for mut line in file.lines.drain(..) {
+                lines.insert(line.line_number, line.count);
+                if let Some(function_name) = line.function_name.take() {
+                    let function_name = match demangled_names.get(function_name.as_str()) {
+                        Some(demangled_name) => demangled_name.to_string(),
+                        None => function_name,
+                    };
+                    line_functions.insert(line.line_number, function_name);
+                }
+                line.branches.retain(|b| !(exclude_branch_throw && b.throw));
+                if line.branches.is_empty() {
+                    continue;
+                }
+                if keep_branch_ids {
+                    // The ids are only usable when all the branches of the line have them.
+                    let line_branch_ids: Option<Vec<(u32, u32)>> = line
+                        .branches
+                        .iter()
+                        .map(|b| Some((b.source_block_id?, b.destination_block_id?)))
+                        .collect();
+                    match line_branch_ids {
+                        Some(ids) => branch_ids.insert(line.line_number, ids),
+                        None => branch_ids.remove(&line.line_number),
+                    };
+                }
                 branches.insert(
                     line.line_number,
-                    line.branches.drain(..).map(|b| b.count > 0).collect(),
+                    line.branches.iter().map(|b| b.count > 0).collect(),
                 );
             }
-        }
-        if lines.is_empty() {
-            continue;
-        }
-        let mut functions = FxHashMap::default();
-        for fun in file.functions.drain(..) {
-            functions.insert(
-                fun.demangled_name,
-                Function {
-                    start: fun.start_line,
-                    executed: fun.execution_count > 0,
+            if lines.is_empty() {
+                continue;
+            }
+            let mut functions = FxHashMap::default();
+            for fun in file.functions.drain(..) {
+                functions.insert(
+                    fun.demangled_name,
+                    Function {
+                        start: fun.start_line,
+                        executed: fun.execution_count > 0,
+                    },
+                );
+            }
+            results.push((
+                file.file,
+                CovResult {
+                    lines,
+                    branches,
+                    functions,
+                    line_functions,
+                    branch_ids,
+                    line_tests: BTreeMap::new(),
+                    line_archives: BTreeMap::new(),
+                    informational: false,
                 },
-            );
+            ));
+        }
+    }
+
+    if documents == 0 {
+        return Err(ParserError::Parse("Empty gcov JSON report".to_string()));
+    }
+
+    Ok(results)
+}
+
+/// Removes the ANSI color escape sequences added by `gcov --use-colors`.
+fn strip_ansi_colors(l: &str) -> String {
+    let mut stripped = String::with_capacity(l.len());
+    let mut chars = l.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip "ESC [ parameters final-byte".
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
         }
+    }
+    stripped
+}
+
+/// Parses the execution count of a line of the gcov text format, e.g. "10", "9*" (line with an
+/// unexecuted block), "#####" or "=====" (not executed).
+fn parse_gcov_text_count(count: &str) -> Option<u64> {
+    match count {
+        "#####" | "=====" => Some(0),
+        _ => count.strip_suffix('*').unwrap_or(count).parse().ok(),
+    }
+}
+
+fn push_gcov_result(
+    results: &mut Vec<(String, CovResult)>,
+    file: Option<String>,
+    lines: BTreeMap<u32, u64>,
+    branches: BTreeMap<u32, Vec<bool>>,
+    functions: FxHashMap<String, Function>,
+) {
+    if let Some(file) = file.filter(|_| !lines.is_empty()) {
         results.push((
-            file.file,
+            file,
             CovResult {
                 lines,
                 branches,
                 functions,
+                line_functions: BTreeMap::new(),
+                branch_ids: BTreeMap::new(),
+                line_tests: BTreeMap::new(),
+                line_archives: BTreeMap::new(),
+                informational: false,
             },
         ));
     }
-
-    Ok(results)
 }
 
-pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserError> {
+/// Parses both the intermediate format (`gcov -i`) and the text format (`gcov -b`, optionally
+/// with `-a`, `-c` or `-k`).
+///
+/// When `exclude_branch_throw` is set, the exception edges of the text format, which gcov marks
+/// with "(throw)", are dropped. gcov only marks them when their block was executed, so the edges
+/// of the blocks which were never executed are kept. The intermediate format doesn't tell them
+/// apart.
+pub fn parse_gcov(
+    gcov_path: &Path,
+    exclude_branch_throw: bool,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
     let mut cur_file = None;
     let mut cur_lines = BTreeMap::new();
     let mut cur_branches = BTreeMap::new();
     let mut cur_functions = FxHashMap::default();
     let mut results = Vec::new();
+    // In the text format, the branches follow their line and the functions precede their first
+    // line.
+    let mut cur_line_no = 0;
+    let mut pending_function: Option<(String, bool)> = None;
 
     let f = File::open(gcov_path)
         .unwrap_or_else(|_| panic!("Failed to open gcov file {}", gcov_path.display()));
@@ -524,6 +845,46 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
         remove_newline(&mut l);
 
         let l = unsafe { str::from_utf8_unchecked(&l) };
+        let stripped;
+        let l = if l.contains('\x1b') {
+            stripped = strip_ansi_colors(l);
+            stripped.as_str()
+        } else {
+            l
+        };
+
+        if let Some(value) = l.strip_prefix("branch ") {
+            // "branch  0 taken 9", "branch  0 taken 0% (fallthrough)" or "branch  1 never executed".
+            let mut values = value.split_whitespace();
+            let _: u32 = try_parse_next!(values, l);
+            let taken = match try_next!(values, l) {
+                "taken" => !matches!(try_next!(values, l), "0" | "0%"),
+                "never" => false,
+                _ => return Err(ParserError::InvalidRecord(l.to_string())),
+            };
+            if exclude_branch_throw && values.next() == Some("(throw)") {
+                continue;
+            }
+            cur_branches
+                .entry(cur_line_no)
+                .or_insert_with(Vec::new)
+                .push(taken);
+            continue;
+        }
+        if let Some(value) = l.strip_prefix("function ") {
+            // "function main called 1 returned 100% blocks executed 90%".
+            let (name, stats) = match value.rsplit_once(" called ") {
+                Some(v) => v,
+                None => return Err(ParserError::InvalidRecord(l.to_string())),
+            };
+            let called: u64 = try_parse_next!(stats.split(' '), l);
+            pending_function = Some((name.to_owned(), called > 0));
+            continue;
+        }
+
+        if l.starts_with("call ") || l.starts_with("unconditional ") {
+            continue;
+        }
 
         let mut key_value = l.splitn(2, ':');
         let key = try_next!(key_value, l);
@@ -531,22 +892,14 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
 
         match key {
             "file" => {
-                if let Some(cur_file) = cur_file.filter(|_: &String| !cur_lines.is_empty()) {
-                    // println!("{} {} {:?}", gcov_path.display(), cur_file, cur_lines);
-                    results.push((
-                        cur_file,
-                        CovResult {
-                            lines: cur_lines,
-                            branches: cur_branches,
-                            functions: cur_functions,
-                        },
-                    ));
-                }
-
+                push_gcov_result(
+                    &mut results,
+                    cur_file.take(),
+                    std::mem::take(&mut cur_lines),
+                    std::mem::take(&mut cur_branches),
+                    std::mem::take(&mut cur_functions),
+                );
                 cur_file = Some(value.to_owned());
-                cur_lines = BTreeMap::new();
-                cur_branches = BTreeMap::new();
-                cur_functions = FxHashMap::default();
             }
             "function" => {
                 let mut f_splits = value.splitn(3, ',');
@@ -579,20 +932,57 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
                     }
                 }
             }
-            _ => {}
+            _ => {
+                // A line of the text format: "<count>:<line number>:<source>", where the count
+                // is "-" for lines without code, or a block: "<count>:<line number>-block <n>".
+                let count = key.trim();
+                let count = match parse_gcov_text_count(count) {
+                    Some(count) => Some(count),
+                    None if count == "-" => None,
+                    None => continue,
+                };
+                let mut values = value.splitn(2, ':');
+                let line_no = try_next!(values, l).trim();
+                if line_no == "0" {
+                    if let Some(source) = values.next().and_then(|v| v.strip_prefix("Source:")) {
+                        push_gcov_result(
+                            &mut results,
+                            cur_file.take(),
+                            std::mem::take(&mut cur_lines),
+                            std::mem::take(&mut cur_branches),
+                            std::mem::take(&mut cur_functions),
+                        );
+                        cur_file = Some(source.to_owned());
+                    }
+                    continue;
+                }
+                if line_no.contains("-block") {
+                    continue;
+                }
+                cur_line_no = try_parse!(line_no, l);
+                if let Some((name, executed)) = pending_function.take() {
+                    cur_functions.insert(
+                        name,
+                        Function {
+                            start: cur_line_no,
+                            executed,
+                        },
+                    );
+                }
+                if let Some(count) = count {
+                    cur_lines.insert(cur_line_no, count);
+                }
+            }
         }
     }
 
-    if !cur_lines.is_empty() {
-        results.push((
-            cur_file.unwrap(),
-            CovResult {
-                lines: cur_lines,
-                branches: cur_branches,
-                functions: cur_functions,
-            },
-        ));
-    }
+    push_gcov_result(
+        &mut results,
+        cur_file,
+        cur_lines,
+        cur_branches,
+        cur_functions,
+    );
 
     Ok(results)
 }
@@ -728,7 +1118,11 @@ fn parse_jacoco_report_package<T: BufRead>(
     buf: &mut Vec<u8>,
     package: &str,
 ) -> Result<Vec<(String, CovResult)>, ParserError> {
+    // The results keyed by source file name, e.g. "Person.kt".
     let mut results_map: FxHashMap<String, CovResult> = FxHashMap::default();
+    // The functions of the classes without a `sourcefilename`, keyed by top class name, until a
+    // <sourcefile /> tells the name of their file.
+    let mut pending_functions: FxHashMap<String, FunctionMap> = FxHashMap::default();
 
     loop {
         match parser.read_event_into(buf) {
@@ -740,50 +1134,43 @@ fn parse_jacoco_report_package<T: BufRead>(
                         // Class name: "Person$Age"
                         let class = fq_class
                             .split('/')
-                            .last()
+                            .next_back()
                             .expect("Failed to parse class name");
                         // Class name "Person"
                         let top_class = class
                             .split('$')
                             .next()
                             .expect("Failed to parse top class name");
+                        // Source file name: "Person.kt", only written by recent JaCoCo versions.
+                        let source_file = get_xml_attribute(parser, e, "sourcefilename").ok();
 
                         // Process all <method /> and <counter /> for this class
                         let functions = parse_jacoco_report_class(parser, buf, class)?;
 
-                        match results_map.entry(top_class.to_string()) {
-                            hash_map::Entry::Occupied(obj) => {
-                                obj.into_mut().functions.extend(functions);
+                        match source_file {
+                            Some(source_file) => {
+                                results_map
+                                    .entry(source_file)
+                                    .or_default()
+                                    .functions
+                                    .extend(functions);
                             }
-                            hash_map::Entry::Vacant(v) => {
-                                v.insert(CovResult {
-                                    functions,
-                                    lines: BTreeMap::new(),
-                                    branches: BTreeMap::new(),
-                                });
+                            None => {
+                                pending_functions
+                                    .entry(top_class.to_string())
+                                    .or_default()
+                                    .extend(functions);
                             }
-                        };
+                        }
                     }
                     b"sourcefile" => {
-                        let sourcefile = get_xml_attribute(parser, e, "name")?;
-                        let class = sourcefile.trim_end_matches(".java");
+                        let source_file = get_xml_attribute(parser, e, "name")?;
                         let JacocoReport { lines, branches } =
                             parse_jacoco_report_sourcefile(parser, buf)?;
 
-                        match results_map.entry(class.to_string()) {
-                            hash_map::Entry::Occupied(obj) => {
-                                let obj = obj.into_mut();
-                                obj.lines = lines;
-                                obj.branches = branches;
-                            }
-                            hash_map::Entry::Vacant(v) => {
-                                v.insert(CovResult {
-                                    functions: FxHashMap::default(),
-                                    lines,
-                                    branches,
-                                });
-                            }
-                        };
+                        let result = results_map.entry(source_file).or_default();
+                        result.lines = lines;
+                        result.branches = branches;
                     }
                     &_ => {}
                 }
@@ -794,23 +1181,43 @@ fn parse_jacoco_report_package<T: BufRead>(
         }
     }
 
-    for (class, result) in &results_map {
+    // Without a `sourcefilename`, a class can only be matched to the file named after it, whatever
+    // its extension (e.g. "Person.java" or "Person.kt").
+    for (top_class, functions) in pending_functions {
+        let result = results_map.iter_mut().find_map(|(source_file, result)| {
+            let stem = source_file
+                .rsplit_once('.')
+                .map_or(source_file.as_str(), |(stem, _)| stem);
+            (stem == top_class).then_some(result)
+        });
+        match result {
+            Some(result) => result.functions.extend(functions),
+            None => {
+                return Err(ParserError::InvalidData(format!(
+                    "Class {}/{} is not the top class in its file.",
+                    package, top_class
+                )))
+            }
+        }
+    }
+
+    for (source_file, result) in &results_map {
         if result.lines.is_empty() && result.branches.is_empty() {
             return Err(ParserError::InvalidData(format!(
-                "Class {}/{} is not the top class in its file.",
-                package, class
+                "Source file {}/{} has no coverage data.",
+                package, source_file
             )));
         }
     }
 
-    // Change all keys from the class name to the file name and turn the result into a Vec.
+    // Change all keys from the file name to the path and turn the result into a Vec.
     // If package is the empty string, we have to trim the leading '/' in order to obtain a
     // relative path.
     Ok(results_map
         .into_iter()
-        .map(|(class, result)| {
+        .map(|(source_file, result)| {
             (
-                format!("{}/{}.java", package, class)
+                format!("{}/{}", package, source_file)
                     .trim_start_matches('/')
                     .to_string(),
                 result,
@@ -819,6 +1226,7 @@ fn parse_jacoco_report_package<T: BufRead>(
         .collect())
 }
 
+/// Parses a JaCoCo XML report.
 pub fn parse_jacoco_xml_report<T: Read>(
     xml_reader: BufReader<T>,
 ) -> Result<Vec<(String, CovResult)>, ParserError> {
@@ -880,7 +1288,7 @@ mod tests {
         let mut f = File::open("./test/prova.info").expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, false).unwrap();
+        let results = parse_lcov(buf, false, false, false).unwrap();
 
         assert_eq!(results.len(), 603);
 
@@ -966,7 +1374,7 @@ mod tests {
         let mut f = File::open("./test/prova.info").expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, true).unwrap();
+        let results = parse_lcov(buf, true, false, false).unwrap();
 
         assert_eq!(results.len(), 603);
 
@@ -1049,6 +1457,7 @@ mod tests {
             .cloned()
             .collect()
         );
+        assert!(result.branch_ids.is_empty());
         assert!(result.functions.contains_key("MainProcessSingleton"));
         let func = result.functions.get("MainProcessSingleton").unwrap();
         assert_eq!(func.start, 15);
@@ -1059,13 +1468,28 @@ mod tests {
         assert!(!func.executed);
     }
 
+    #[test]
+    fn test_lcov_parser_with_branch_ids() {
+        let mut f = File::open("./test/prova.info").expect("Failed to open lcov file");
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf).unwrap();
+        let results = parse_lcov(buf, true, false, true).unwrap();
+
+        // The branches of the different blocks of a line are kept apart (matching BRF and BRH).
+        let (_, ref result) = results[0];
+        assert_eq!(result.branches[&34], vec![false; 6]);
+        assert_eq!(result.branches[&44], vec![false; 4]);
+        assert_eq!(result.branches[&68], vec![true, true, true, false]);
+        assert_eq!(result.branch_ids[&44], vec![(4, 0), (4, 1), (5, 0), (5, 1)]);
+    }
+
     #[test]
     fn test_lcov_parser_fn_with_commas() {
         let mut f =
             File::open("./test/prova_fn_with_commas.info").expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, true).unwrap();
+        let results = parse_lcov(buf, true, false, false).unwrap();
 
         assert_eq!(results.len(), 1);
 
@@ -1156,7 +1580,7 @@ mod tests {
         let mut f = File::open("./test/empty_line.info").expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let results = parse_lcov(buf, true).unwrap();
+        let results = parse_lcov(buf, true, false, false).unwrap();
 
         assert_eq!(results.len(), 1);
 
@@ -1242,13 +1666,107 @@ mod tests {
         assert!(func.executed);
     }
 
+    #[test]
+    fn test_lcov_parser_unknown_records() {
+        let buf = "VER:2.0
+TN:test
+SF:foo.c
+FNL:0,1,3
+FNA:0,1,foo
+FN:1,foo
+FNDA:1,foo
+FNF:1
+FNH:1
+DA:1,1
+DA:2,0
+BRDA:2,0,0,1
+BRDA:2,0,1,-
+BRF:2
+BRH:1
+MCDCX:2,2,t,0,1,a
+BR
+LF:2
+LH:1
+end_of_record
+"
+        .as_bytes()
+        .to_vec();
+        for lenient in [false, true].iter() {
+            let results = parse_lcov(buf.clone(), true, *lenient, false).unwrap();
+            assert_eq!(results.len(), 1);
+            let (ref source_name, ref result) = results[0];
+            assert_eq!(source_name, "foo.c");
+            assert_eq!(result.lines, [(1, 1), (2, 0)].iter().cloned().collect());
+            assert_eq!(
+                result.branches,
+                [(2, vec![true, false])].iter().cloned().collect()
+            );
+            assert_eq!(result.functions.len(), 1);
+            assert!(result.functions["foo"].executed);
+        }
+    }
+
+    #[test]
+    fn test_lcov_parser_excluding_tests() {
+        let buf = "TN:product
+SF:foo.c
+FN:1,foo
+FNDA:1,foo
+DA:1,1
+DA:2,0
+DA:3,0
+BRDA:3,0,0,-
+BRDA:3,0,1,-
+end_of_record
+TN:unit_tests
+SF:foo.c
+FN:1,foo
+FNDA:2,foo
+DA:1,2
+DA:2,3
+DA:3,0
+BRDA:3,0,0,1
+BRDA:3,0,1,-
+end_of_record
+TN:unit_tests
+SF:bar.c
+DA:1,1
+end_of_record
+"
+        .as_bytes()
+        .to_vec();
+        let results =
+            parse_lcov_excluding_tests(buf, true, false, false, &Regex::new("^unit_").unwrap())
+                .unwrap();
+        assert_eq!(results.len(), 3);
+
+        let (ref source_name, ref result) = results[0];
+        assert_eq!(source_name, "foo.c");
+        assert_eq!(result.lines, [(1, 1), (3, 0)].iter().cloned().collect());
+        assert!(result.functions["foo"].executed);
+
+        // The counts of the excluded tests are subtracted.
+        let (ref source_name, ref result) = results[1];
+        assert_eq!(source_name, "foo.c");
+        assert_eq!(result.lines, [(1, 0), (3, 0)].iter().cloned().collect());
+        assert_eq!(
+            result.branches,
+            [(3, vec![false, false])].iter().cloned().collect()
+        );
+        assert!(!result.functions["foo"].executed);
+
+        let (ref source_name, ref result) = results[2];
+        assert_eq!(source_name, "bar.c");
+        assert!(result.lines.is_empty());
+    }
+
     #[allow(non_snake_case)]
     #[test]
     fn test_lcov_parser_invalid_DA_record() {
         let mut f = File::open("./test/invalid_DA_record.info").expect("Failed to open lcov file");
         let mut buf = Vec::new();
         f.read_to_end(&mut buf).unwrap();
-        let result = parse_lcov(buf, true);
+        let result = parse_lcov(buf, true, false, false);
         assert!(result.is_err());
     }
 
@@ -1262,15 +1780,53 @@ DA:156,12
 DA
 TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_2dinherit_2emeta_2funset_2fiframe_2dtag_2ehttp_2ehtml_2c_20about_3ablank"
         .as_bytes().to_vec();
-        let result = parse_lcov(buf, true);
+        let result = parse_lcov(buf, true, false, false);
         assert!(result.is_err());
         let error = result.unwrap_err();
         assert_eq!(error.to_string(), "Invalid record: 'DA at line 5'");
     }
 
+    #[test]
+    fn test_lcov_parser_invalid_numbers() {
+        let parse_error = |buf: &str| {
+            parse_lcov(buf.as_bytes().to_vec(), true, false, false)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            parse_error("SF:a.c\nDA:4294967296,1\nend_of_record\n"),
+            "Invalid record: 'DA at line 2'"
+        );
+        assert_eq!(
+            parse_error("SF:a.c\nDA:1,18446744073709551616\nend_of_record\n"),
+            "Invalid record: 'DA at line 2'"
+        );
+        assert_eq!(
+            parse_error("SF:a.c\nDA:1,18446744073709551615\nDA:1,1\nend_of_record\n"),
+            "Invalid record: 'DA at line 3'"
+        );
+        assert_eq!(
+            parse_error("SF:a.c\nDA:1,x\nend_of_record\n"),
+            "Invalid record: 'DA at line 2'"
+        );
+        assert_eq!(
+            parse_error("SF:a.c\nFN:99999999999,f\nend_of_record\n"),
+            "Invalid record: 'FN at line 2'"
+        );
+        assert_eq!(
+            parse_error("SF:a.c\nBRDA:1,0,99999999999,1\nend_of_record\n"),
+            "Invalid record: 'BRDA at line 2'"
+        );
+        assert_eq!(
+            parse_error("DA:1,1\nend_of_record\n"),
+            "Invalid record: 'end_of_record at line 2'"
+        );
+    }
+
     #[test]
     fn test_parser() {
-        let results = parse_gcov(Path::new("./test/prova.gcov")).unwrap();
+        let results = parse_gcov(Path::new("./test/prova.gcov"), false).unwrap();
 
         assert_eq!(results.len(), 10);
 
@@ -1485,7 +2041,7 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
 
     #[test]
     fn test_parser_gcov_with_negative_counts() {
-        let results = parse_gcov(Path::new("./test/negative_counts.gcov")).unwrap();
+        let results = parse_gcov(Path::new("./test/negative_counts.gcov"), false).unwrap();
         assert_eq!(results.len(), 118);
         let (ref source_name, ref result) = results[14];
         assert_eq!(source_name, "/home/marco/Documenti/FD/mozilla-central/build-cov-gcc/dist/include/mozilla/Assertions.h");
@@ -1494,7 +2050,7 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
 
     #[test]
     fn test_parser_gcov_with_64bit_counts() {
-        let results = parse_gcov(Path::new("./test/64bit_count.gcov")).unwrap();
+        let results = parse_gcov(Path::new("./test/64bit_count.gcov"), false).unwrap();
         assert_eq!(results.len(), 46);
         let (ref source_name, ref result) = results[8];
         assert_eq!(
@@ -1736,7 +2292,8 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
 
     #[test]
     fn test_parser_gcov_with_branches() {
-        let results = parse_gcov(Path::new("./test/intermediate_with_branches.gcov")).unwrap();
+        let results =
+            parse_gcov(Path::new("./test/intermediate_with_branches.gcov"), false).unwrap();
         assert_eq!(results.len(), 1);
         let (ref source_name, ref result) = results[0];
 
@@ -1772,11 +2329,68 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
         assert!(!func.executed);
     }
 
+    #[test]
+    fn test_parser_gcov_text() {
+        let expected_lines: BTreeMap<u32, u64> = [
+            (3, 9),
+            (4, 9),
+            (5, 0),
+            (7, 9),
+            (10, 0),
+            (11, 0),
+            (12, 0),
+            (14, 0),
+            (17, 1),
+            (19, 10),
+            (20, 9),
+            (21, 3),
+            (23, 9),
+            (24, 0),
+            (27, 1),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let expected_branches: BTreeMap<u32, Vec<bool>> = [
+            (4, vec![false, true]),
+            (11, vec![false, false]),
+            (19, vec![true, true]),
+            (20, vec![true, true]),
+            (23, vec![false, true]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        // The branches are given as counts in one and as percentages, with colors and blocks, in
+        // the other. They were produced by gcov 12, gcov 11 and 13 print the lines, blocks and
+        // branches of the text format, and their colors with `-k`, in the same way.
+        for path in &[
+            "./test/gcc12_text_counts.gcov",
+            "./test/gcc12_text_blocks_colors.gcov",
+        ] {
+            let results = parse_gcov(Path::new(path), false).unwrap();
+            assert_eq!(results.len(), 1);
+            let (ref source_name, ref result) = results[0];
+            assert_eq!(source_name, "main.c");
+            assert_eq!(result.lines, expected_lines);
+            assert_eq!(result.branches, expected_branches);
+            assert_eq!(result.functions.len(), 3);
+            let func = result.functions.get("never").unwrap();
+            assert_eq!(func.start, 10);
+            assert!(!func.executed);
+            let func = result.functions.get("main").unwrap();
+            assert_eq!(func.start, 17);
+            assert!(func.executed);
+        }
+    }
+
     #[test]
     fn test_parser_gcov_rust_generics_with_two_parameters() {
-        let results = parse_gcov(Path::new(
-            "./test/rust/generics_with_two_parameters_intermediate.gcov",
-        ))
+        let results = parse_gcov(
+            Path::new("./test/rust/generics_with_two_parameters_intermediate.gcov"),
+            false,
+        )
         .unwrap();
         assert_eq!(results.len(), 1);
         let (ref source_name, ref result) = results[0];
@@ -1814,11 +2428,221 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
         assert!(func.executed);
     }
 
+    #[test]
+    fn test_parser_gcov_gz_exclude_branch_throw() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let gcov_path = tmp_dir.path().join("throw.gcno.gcov.json.gz");
+        let json = r#"{
+            "format_version": "1",
+            "gcc_version": "11.2.0",
+            "current_working_directory": "/build",
+            "data_file": "throw.gcda",
+            "files": [{
+                "file": "throw.cpp",
+                "functions": [],
+                "lines": [
+                    {"line_number": 1, "count": 1, "unexecuted_block": false, "branches": [
+                        {"count": 1, "throw": false, "fallthrough": true},
+                        {"count": 0, "throw": false, "fallthrough": false}
+                    ]},
+                    {"line_number": 2, "count": 1, "unexecuted_block": false, "branches": [
+                        {"count": 1, "throw": false, "fallthrough": true},
+                        {"count": 0, "throw": true, "fallthrough": false}
+                    ]},
+                    {"line_number": 3, "count": 1, "unexecuted_block": false, "branches": [
+                        {"count": 0, "throw": true, "fallthrough": false}
+                    ]}
+                ]
+            }]
+        }"#;
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&gcov_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        std::io::Write::write_all(&mut encoder, json.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let results = parse_gcov_gz(&gcov_path, false, false).unwrap();
+        assert_eq!(
+            results[0].1.branches,
+            [
+                (1, vec![true, false]),
+                (2, vec![true, false]),
+                (3, vec![false])
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+
+        let results = parse_gcov_gz(&gcov_path, true, false).unwrap();
+        assert_eq!(
+            results[0].1.branches,
+            [(1, vec![true, false]), (2, vec![true])]
+                .iter()
+                .cloned()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_parser_gcov_gz_branch_ids() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let gcov_path = tmp_dir.path().join("ids.gcno.gcov.json.gz");
+        // gcc >= 14 reports the blocks of the branches, older versions don't.
+        let json = r#"{
+            "format_version": "2",
+            "gcc_version": "14.1.0",
+            "current_working_directory": "/build",
+            "data_file": "ids.gcda",
+            "files": [{
+                "file": "ids.cpp",
+                "functions": [],
+                "lines": [
+                    {"line_number": 1, "count": 1, "unexecuted_block": false, "branches": [
+                        {"count": 1, "throw": false, "fallthrough": true,
+                         "source_block_id": 2, "destination_block_id": 3},
+                        {"count": 0, "throw": false, "fallthrough": false,
+                         "source_block_id": 2, "destination_block_id": 4}
+                    ]},
+                    {"line_number": 2, "count": 1, "unexecuted_block": false, "branches": [
+                        {"count": 1, "throw": false, "fallthrough": true}
+                    ]}
+                ]
+            }]
+        }"#;
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&gcov_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        std::io::Write::write_all(&mut encoder, json.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let results = parse_gcov_gz(&gcov_path, false, false).unwrap();
+        assert!(results[0].1.branch_ids.is_empty());
+
+        let results = parse_gcov_gz(&gcov_path, false, true).unwrap();
+        assert_eq!(
+            results[0].1.branches,
+            [(1, vec![true, false]), (2, vec![true])]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert_eq!(
+            results[0].1.branch_ids,
+            [(1, vec![(2, 3), (2, 4)])].iter().cloned().collect()
+        );
+    }
+
+    #[test]
+    fn test_parser_gcov_text_exclude_branch_throw() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let gcov_path = tmp_dir.path().join("throw.cpp.gcov");
+        std::fs::write(
+            &gcov_path,
+            "        -:    0:Source:throw.cpp
+function _Z3fooi called 1 returned 100% blocks executed 80%
+        1:    1:int foo(int x) {
+        1:    2:  if (x > 0) {
+branch  0 taken 100% (fallthrough)
+branch  1 taken 0%
+        1:    3:    bar(x);
+call    0 returned 100%
+branch  1 taken 100% (fallthrough)
+branch  2 taken 0% (throw)
+        -:    4:  }
+        1:    5:  return 0;
+        -:    6:}
+function _Z3bazv called 0 returned 0% blocks executed 0%
+    #####:    7:void baz() {
+    #####:    8:  bar(0);
+call    0 never executed
+branch  1 never executed
+branch  2 never executed
+    #####:    9:  if (qux()) {
+call    0 never executed
+branch  1 never executed
+branch  2 never executed
+branch  3 never executed
+branch  4 never executed
+        -:   10:  }
+        -:   11:}
+",
+        )
+        .unwrap();
+
+        let results = parse_gcov(&gcov_path, false).unwrap();
+        assert_eq!(
+            results[0].1.branches,
+            [
+                (2, vec![true, false]),
+                (3, vec![true, false]),
+                (8, vec![false, false]),
+                (9, vec![false, false, false, false])
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+
+        let results = parse_gcov(&gcov_path, true).unwrap();
+        assert_eq!(
+            results[0].1.branches,
+            [
+                (2, vec![true, false]),
+                (3, vec![true]),
+                (8, vec![false, false]),
+                (9, vec![false, false, false, false])
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_parser_gcov_gz_buf() {
+        let path = Path::new("./test/mozillavpn_serverconnection.gcno.gcov.json.gz");
+        let buffer = std::fs::read(path).unwrap();
+        assert_eq!(
+            parse_gcov_gz_buf(&buffer, false, false).unwrap(),
+            parse_gcov_gz(path, false, false).unwrap()
+        );
+
+        assert!(matches!(
+            parse_gcov_gz_buf(b"not gzipped", false, false),
+            Err(ParserError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parser_gcov_gz_concatenated() {
+        let results =
+            parse_gcov_gz(Path::new("./test/concatenated.gcov.json.gz"), false, false).unwrap();
+        let files: Vec<&str> = results.iter().map(|(file, _)| file.as_str()).collect();
+        assert_eq!(files, vec!["a.c", "b.c"]);
+        assert_eq!(
+            results[0].1.lines,
+            [(2, 1), (3, 0)].iter().cloned().collect::<BTreeMap<_, _>>()
+        );
+        assert!(!results[1].1.functions["helper"].executed);
+
+        let mut buffer = std::fs::read("./test/concatenated.gcov.json.gz").unwrap();
+        buffer.extend_from_slice(b"trailing garbage");
+        assert!(matches!(
+            parse_gcov_gz_buf(&buffer, false, false),
+            Err(ParserError::Parse(_))
+        ));
+    }
+
     #[test]
     fn test_parser_gcov_gz() {
-        let results = parse_gcov_gz(Path::new(
-            "./test/mozillavpn_serverconnection.gcno.gcov.json.gz",
-        ))
+        let results = parse_gcov_gz(
+            Path::new("./test/mozillavpn_serverconnection.gcno.gcov.json.gz"),
+            false,
+            false,
+        )
         .unwrap();
         assert_eq!(results.len(), 37);
         let (ref source_name, ref result) = results[0];
@@ -1993,6 +2817,16 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
             .unwrap();
         assert_eq!(func.start, 188);
         assert!(!func.executed);
+
+        assert_eq!(result.line_functions.len(), result.lines.len());
+        assert_eq!(
+            result.line_functions[&32],
+            "(anonymous namespace)::serializeServerCountry(ServerCountryModel*, QJsonObject&)"
+        );
+        assert!(result
+            .line_functions
+            .values()
+            .all(|name| result.functions.contains_key(name)));
     }
 
     #[test]
@@ -2024,6 +2858,7 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
@@ -2071,6 +2906,7 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
@@ -2081,6 +2917,41 @@ TN:http_3a_2f_2fweb_2dplatform_2etest_3a8000_2freferrer_2dpolicy_2fgen_2fsrcdoc_
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn test_parser_jacoco_xml_kotlin() {
+        let f = File::open("./test/jacoco/kotlin.xml").expect("Failed to open xml file");
+        let file = BufReader::new(&f);
+        let mut results = parse_jacoco_xml_report(file).unwrap();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(results.len(), 2);
+        let (name, result) = &results[0];
+        assert_eq!(name, "org/example/Greeter.kt");
+        assert_eq!(
+            result.lines,
+            [(3, 1), (5, 1), (9, 0)].iter().cloned().collect()
+        );
+        assert_eq!(
+            result.branches,
+            [(4, vec![true, false])].iter().cloned().collect()
+        );
+        let mut functions: Vec<&str> = result.functions.keys().map(String::as_str).collect();
+        functions.sort_unstable();
+        assert_eq!(
+            functions,
+            [
+                "Greeter#<init>",
+                "Greeter#greet",
+                "Greeter$Companion#create"
+            ]
+        );
+
+        let (name, result) = &results[1];
+        assert_eq!(name, "org/example/Strings.kt");
+        assert_eq!(result.lines, [(3, 1)].iter().cloned().collect());
+        assert!(result.functions.contains_key("StringsKt#shout"));
+    }
+
     #[test]
     #[should_panic]
     fn test_parser_jacoco_xml_non_top_level_classes_panics() {
@@ -1,8 +1,13 @@
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use log::{debug, warn};
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
 use serde_json::Value;
 use std::collections::hash_map;
+use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::io;
 use std::path::{Component, Path, PathBuf};
@@ -35,6 +40,50 @@ pub fn canonicalize_path<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
     Ok(path)
 }
 
+/// Replaces the `${VAR}` references of `s` with the value of the environment variable `VAR`.
+/// Returns an error if a variable isn't set.
+pub fn expand_env_vars(s: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated variable reference in '{}'", s))?;
+        let name = &rest[start + 2..start + end];
+        let value = env::var(name).map_err(|_| {
+            format!(
+                "The environment variable '{}' used in '{}' isn't set",
+                name, s
+            )
+        })?;
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands the `${VAR}` references of the keys and the values of a path mapping, see
+/// `expand_env_vars`.
+pub fn expand_mapping_env_vars(mapping: Value) -> Result<Value, String> {
+    Ok(match mapping {
+        Value::String(s) => Value::String(expand_env_vars(&s)?),
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(expand_mapping_env_vars)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| Ok((expand_env_vars(&key)?, expand_mapping_env_vars(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        value => value,
+    })
+}
+
 pub fn has_no_parent(path: &str) -> bool {
     PathBuf::from(path).parent() == Some(&PathBuf::from(""))
 }
@@ -58,8 +107,8 @@ pub fn normalize_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
             Component::CurDir => {}
             Component::ParentDir => {
                 if !ret.pop() {
-                    eprintln!(
-                        "Warning: {:?} cannot be normalized because of \"..\", so skip it.",
+                    debug!(
+                        "{:?} cannot be normalized because of \"..\", so skip it.",
                         path.as_ref()
                     );
                     return None;
@@ -73,6 +122,82 @@ pub fn normalize_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
     Some(ret)
 }
 
+#[derive(Deserialize)]
+struct CompileCommand {
+    directory: String,
+    file: String,
+}
+
+/// Normalizes the relative path `path` (with '/' separators) as a key of the path mapping,
+/// removing its '.' components and the '..' ones which follow a directory, e.g. `./gen/../lib.h`
+/// becomes `lib.h` while `../src/main.c` is left untouched.
+fn normalize_mapping_key(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+/// Adds the source files of the compilation database `database` (the content of a
+/// `compile_commands.json` file) to the path mapping: the relative paths of the files, as given to
+/// the compiler (and so as found in the coverage data, once normalized), are mapped to their paths
+/// in the `directory` of their entries. When a path is relative to several directories, it is
+/// mapped to the only one of them in which the file exists, and left out if there's none or more
+/// than one. The entries already in the mapping take precedence.
+pub fn add_compilation_database_mapping(
+    path_mapping: &mut Option<Value>,
+    database: &str,
+) -> Result<(), String> {
+    let commands: Vec<CompileCommand> = serde_json::from_str(database)
+        .map_err(|e| format!("Invalid compilation database: {}", e))?;
+
+    let mut files: FxHashMap<String, Vec<PathBuf>> = FxHashMap::default();
+    for command in commands {
+        if Path::new(&command.file).is_absolute() {
+            continue;
+        }
+        let file = normalize_mapping_key(&command.file.replace('\\', "/"));
+        let Some(abs_path) = normalize_path(Path::new(&command.directory).join(&command.file))
+        else {
+            continue;
+        };
+        let paths = files.entry(file).or_default();
+        if !paths.contains(&abs_path) {
+            paths.push(abs_path);
+        }
+    }
+
+    let mapping = path_mapping.get_or_insert_with(|| Value::Object(Default::default()));
+    let Some(mapping) = mapping.as_object_mut() else {
+        return Err("The path mapping isn't a JSON object".to_string());
+    };
+    for (file, mut paths) in files {
+        if paths.len() > 1 {
+            paths.retain(|path| path.is_file());
+            if paths.len() != 1 {
+                warn!(
+                    "{} is relative to different directories in the compilation database, and {} of them contain it, so it isn't mapped.",
+                    file,
+                    if paths.is_empty() { "none" } else { "several" }
+                );
+                continue;
+            }
+        }
+        mapping
+            .entry(file)
+            .or_insert_with(|| Value::String(paths[0].to_string_lossy().into_owned()));
+    }
+
+    Ok(())
+}
+
 // Search the source file's path in the mapping.
 fn apply_mapping(mapping: &Option<Value>, path: &str) -> PathBuf {
     if let Some(mapping) = mapping {
@@ -81,6 +206,13 @@ fn apply_mapping(mapping: &Option<Value>, path: &str) -> PathBuf {
         } else if let Some(p) = mapping.get(to_uppercase_first(path)) {
             return PathBuf::from(p.as_str().unwrap());
         }
+        // The relative paths of the compilation database are normalized.
+        let normalized = normalize_mapping_key(path);
+        if !Path::new(path).is_absolute() && normalized != path {
+            if let Some(p) = mapping.get(&normalized) {
+                return PathBuf::from(p.as_str().unwrap());
+            }
+        }
     }
 
     PathBuf::from(path)
@@ -126,7 +258,11 @@ fn fixup_rel_path(source_dir: Option<&Path>, abs_path: &Path, rel_path: PathBuf)
 }
 
 // Get the absolute path for the source file's path, resolving symlinks.
-fn get_abs_path(source_dir: Option<&Path>, rel_path: PathBuf) -> Option<(PathBuf, PathBuf)> {
+fn get_abs_path(
+    source_dir: Option<&Path>,
+    rel_path: PathBuf,
+    canonicalize: bool,
+) -> Option<(PathBuf, PathBuf)> {
     let mut abs_path = if !rel_path.is_relative() {
         rel_path.to_owned()
     } else if let Some(source_dir) = source_dir {
@@ -142,9 +278,11 @@ fn get_abs_path(source_dir: Option<&Path>, rel_path: PathBuf) -> Option<(PathBuf
         rel_path.to_owned()
     };
 
-    // Canonicalize, if possible.
-    if let Ok(p) = canonicalize_path(&abs_path) {
-        abs_path = p;
+    // Canonicalize, if possible, unless the symlinks must be kept.
+    if canonicalize {
+        if let Ok(p) = canonicalize_path(&abs_path) {
+            abs_path = p;
+        }
     }
 
     // Fixup the relative path, in case the absolute path was a symlink.
@@ -157,10 +295,54 @@ fn get_abs_path(source_dir: Option<&Path>, rel_path: PathBuf) -> Option<(PathBuf
     abs_path.zip(rel_path)
 }
 
-fn check_extension(path: &Path, e: &str) -> bool {
+/// The extensions of the files whose partial paths (e.g. relative to their package, as in JaCoCo
+/// reports) are mapped to full paths by default.
+pub const DEFAULT_PARTIAL_PATH_EXTENSIONS: &[&str] = &["java", "kt", "scala", "groovy"];
+
+/// The options of `rewrite_paths` besides the paths and the filters of the files to keep.
+#[derive(Clone, Debug)]
+pub struct RewriteOptions {
+    /// Skips the files and directories ignored by `.gitignore` files when walking the source
+    /// directory.
+    pub respect_gitignore: bool,
+    /// Merges the results of the files whose paths only differ by case.
+    pub ignore_path_case: bool,
+    /// The maximum depth of the walk of the source directory, unlimited if `None`.
+    pub source_walk_max_depth: Option<usize>,
+    /// Doesn't walk the directories of the source directory matching an ignore glob at all.
+    pub prune_ignored_dirs: bool,
+    /// How the results of the files merged together are combined.
+    pub merge_strategy: MergeStrategy,
+    /// The extensions of the files whose partial paths are mapped to full paths.
+    pub partial_path_extensions: Vec<String>,
+    /// A directory whose files are dropped, and which isn't walked.
+    pub ignored_dir: Option<PathBuf>,
+    /// Resolves the symbolic links of the absolute paths of the files.
+    pub canonicalize: bool,
+}
+
+impl Default for RewriteOptions {
+    fn default() -> Self {
+        Self {
+            respect_gitignore: false,
+            ignore_path_case: false,
+            source_walk_max_depth: None,
+            prune_ignored_dirs: false,
+            merge_strategy: MergeStrategy::default(),
+            partial_path_extensions: DEFAULT_PARTIAL_PATH_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            ignored_dir: None,
+            canonicalize: true,
+        }
+    }
+}
+
+fn check_extension(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
     if let Some(ext) = &path.extension() {
         if let Some(ext) = ext.to_str() {
-            ext == e
+            extensions.iter().any(|e| e.as_ref() == ext)
         } else {
             false
         }
@@ -215,16 +397,57 @@ fn is_symbolic_link(entry: &DirEntry) -> bool {
     entry.path_is_symlink()
 }
 
+// With `prune_ignored_dirs`, the directories matching an ignore glob aren't traversed at all,
+// which is much faster for big vendored trees.
+fn is_ignored_dir(
+    path: &Path,
+    is_dir: bool,
+    source_dir: &Path,
+    to_prune_globset: Option<&GlobSet>,
+) -> bool {
+    let Some(to_ignore_globset) = to_prune_globset else {
+        return false;
+    };
+    is_dir
+        && path.strip_prefix(source_dir).is_ok_and(|rel_path| {
+            !rel_path.as_os_str().is_empty() && to_ignore_globset.is_match(rel_path)
+        })
+}
+
+// The paths are matched with '/' separators, so Windows-style separators in the globs are
+// normalized too (which means backslashes can't be used to escape special characters).
 fn to_globset(dirs: &[impl AsRef<str>]) -> GlobSet {
     let mut glob_builder = GlobSetBuilder::new();
 
     for dir in dirs {
-        glob_builder.add(Glob::new(dir.as_ref()).unwrap());
+        glob_builder.add(Glob::new(&dir.as_ref().replace('\\', "/")).unwrap());
     }
 
     glob_builder.build().unwrap()
 }
 
+/// Marks the results whose relative path matches one of the `informational` globs as
+/// informational: the outputs list them, but don't count them in their totals.
+pub fn mark_informational<'a>(
+    results: impl IntoIterator<Item = (&'a Path, &'a mut CovResult)>,
+    informational: &[impl AsRef<str>],
+) {
+    if informational.is_empty() {
+        return;
+    }
+    let informational_globset = to_globset(informational);
+    for (rel_path, result) in results {
+        result.informational = informational_globset.is_match(rel_path);
+    }
+}
+
+/// Splits the results in the ones counted in the totals and the informational ones.
+pub fn split_informational(results: &[ResultTuple]) -> (Vec<&ResultTuple>, Vec<&ResultTuple>) {
+    results
+        .iter()
+        .partition(|(_, _, result)| !result.informational)
+}
+
 pub fn rewrite_paths(
     result_map: CovResultMap,
     path_mapping: Option<Value>,
@@ -235,9 +458,17 @@ pub fn rewrite_paths(
     to_keep_dirs: &[impl AsRef<str>],
     filter_option: Option<bool>,
     file_filter: crate::FileFilter,
+    options: &RewriteOptions,
 ) -> Vec<ResultTuple> {
+    let source_walk_max_depth = options.source_walk_max_depth;
+    let partial_path_extensions = &options.partial_path_extensions;
+    let ignored_dir = options.ignored_dir.as_deref();
+    let canonicalize = options.canonicalize;
     let to_ignore_globset = to_globset(to_ignore_dirs);
     let to_keep_globset = to_globset(to_keep_dirs);
+    let to_prune_globset = options
+        .prune_ignored_dirs
+        .then(|| to_ignore_globset.clone());
 
     if let Some(p) = &source_dir {
         assert!(p.is_absolute());
@@ -246,30 +477,73 @@ pub fn rewrite_paths(
     // Traverse source dir and store all paths, reversed.
     let mut file_to_paths: FxHashMap<String, Vec<PathBuf>> = FxHashMap::default();
     if let Some(ref source_dir) = source_dir {
-        for entry in WalkDir::new(source_dir)
-            .into_iter()
-            .filter_entry(|e| !is_hidden(e) && !is_symbolic_link(e))
-        {
-            let entry = entry
-                .unwrap_or_else(|_| panic!("Failed to open directory '{}'.", source_dir.display()));
-
-            let full_path = entry.path();
+        let mut add_file = |full_path: &Path, name: &OsStr| {
             if !full_path.is_file() {
-                continue;
+                return;
             }
 
             let path = full_path.strip_prefix(source_dir).unwrap().to_path_buf();
             if to_ignore_globset.is_match(&path) {
-                continue;
+                return;
             }
 
-            let name = entry.file_name().to_str().unwrap().to_string();
+            let name = name.to_str().unwrap().to_string();
             match file_to_paths.entry(name) {
                 hash_map::Entry::Occupied(f) => f.into_mut().push(path),
                 hash_map::Entry::Vacant(v) => {
                     v.insert(vec![path]);
                 }
             };
+        };
+
+        if options.respect_gitignore {
+            // The filter of WalkBuilder must be 'static.
+            let walk_root = source_dir.to_path_buf();
+            let walk_prune_globset = to_prune_globset.clone();
+            let walk_ignored_dir = ignored_dir.map(Path::to_path_buf);
+            for entry in WalkBuilder::new(source_dir)
+                .hidden(true)
+                .require_git(false)
+                .max_depth(source_walk_max_depth)
+                .filter_entry(move |e| {
+                    let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+                    !e.path_is_symlink()
+                        && !is_ignored_dir(
+                            e.path(),
+                            is_dir,
+                            &walk_root,
+                            walk_prune_globset.as_ref(),
+                        )
+                        && walk_ignored_dir.as_deref() != Some(e.path())
+                })
+                .build()
+            {
+                let entry = entry.unwrap_or_else(|_| {
+                    panic!("Failed to open directory '{}'.", source_dir.display())
+                });
+                add_file(entry.path(), entry.file_name());
+            }
+        } else {
+            for entry in WalkDir::new(source_dir)
+                .max_depth(source_walk_max_depth.unwrap_or(usize::MAX))
+                .into_iter()
+                .filter_entry(|e| {
+                    !is_hidden(e)
+                        && !is_symbolic_link(e)
+                        && !is_ignored_dir(
+                            e.path(),
+                            e.file_type().is_dir(),
+                            source_dir,
+                            to_prune_globset.as_ref(),
+                        )
+                        && ignored_dir != Some(e.path())
+                })
+            {
+                let entry = entry.unwrap_or_else(|_| {
+                    panic!("Failed to open directory '{}'.", source_dir.display())
+                });
+                add_file(entry.path(), entry.file_name());
+            }
         }
     }
 
@@ -285,14 +559,18 @@ pub fn rewrite_paths(
             let rel_path = remove_prefix(prefix_dir, rel_path);
 
             // Try mapping a partial path to a full path.
-            let rel_path = if check_extension(&rel_path, "java") {
+            let rel_path = if check_extension(&rel_path, partial_path_extensions) {
                 map_partial_path(&file_to_paths, rel_path)
             } else {
                 rel_path
             };
 
             // Get absolute path to the source file.
-            let (abs_path, rel_path) = get_abs_path(source_dir, rel_path)?;
+            let (abs_path, rel_path) = get_abs_path(source_dir, rel_path, canonicalize)?;
+
+            if ignored_dir.is_some_and(|ignored_dir| abs_path.starts_with(ignored_dir)) {
+                return None;
+            }
 
             if to_ignore_globset.is_match(&rel_path) {
                 return None;
@@ -306,13 +584,29 @@ pub fn rewrite_paths(
                 return None;
             }
 
+            // The source is read once for both the hashes and the markers.
+            let source = if file_filter.needs_source() {
+                fs::read(&abs_path).ok()
+            } else {
+                None
+            };
+            if source
+                .as_deref()
+                .is_some_and(|source| file_filter.is_excluded(source))
+            {
+                return None;
+            }
+
             // Always return results with '/'.
             let rel_path = PathBuf::from(rel_path.to_str().unwrap().replace('\\', "/"));
 
-            for filter in file_filter.create(&abs_path) {
+            let filters =
+                source.map_or_else(Vec::new, |source| file_filter.create(&abs_path, source));
+            for filter in filters {
                 match filter {
                     crate::FilterType::Both(number) => {
                         result.branches.remove(&number);
+                        result.branch_ids.remove(&number);
                         result.lines.remove(&number);
                     }
                     crate::FilterType::Line(number) => {
@@ -320,28 +614,144 @@ pub fn rewrite_paths(
                     }
                     crate::FilterType::Branch(number) => {
                         result.branches.remove(&number);
+                        result.branch_ids.remove(&number);
                     }
                 }
             }
 
-            match filter_option {
-                Some(true) => {
-                    if !is_covered(&result) {
-                        return None;
-                    }
-                }
-                Some(false) => {
-                    if is_covered(&result) {
-                        return None;
-                    }
-                }
-                None => (),
-            };
+            for number in file_filter.excluded_lines(&rel_path, &abs_path) {
+                result.branches.remove(&number);
+                result.branch_ids.remove(&number);
+                result.lines.remove(&number);
+            }
 
             Some((abs_path, rel_path, result))
         });
 
-    results.collect()
+    let mut results: Vec<ResultTuple> = results.collect();
+    if options.ignore_path_case {
+        results = merge_case_variants(results, options.merge_strategy);
+    }
+
+    if let Some(covered) = filter_option {
+        results.retain(|(_, _, result)| is_covered(result) == covered);
+    }
+
+    results
+}
+
+/// Merges the results of files whose paths only differ by their case (e.g. `Foo.rs` and `foo.rs`,
+/// which are the same file on case-insensitive file systems). The first path in lexicographic
+/// order is kept.
+fn merge_case_variants(
+    mut results: Vec<ResultTuple>,
+    merge_strategy: MergeStrategy,
+) -> Vec<ResultTuple> {
+    results.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut merged: Vec<ResultTuple> = Vec::with_capacity(results.len());
+    let mut indices: FxHashMap<String, usize> = FxHashMap::default();
+    let mut warn_overflow = false;
+    for (abs_path, rel_path, result) in results {
+        match indices.entry(rel_path.to_str().unwrap().to_lowercase()) {
+            hash_map::Entry::Occupied(i) => {
+                warn_overflow |=
+                    crate::merge_results(&mut merged[*i.get()].2, result, merge_strategy);
+            }
+            hash_map::Entry::Vacant(v) => {
+                v.insert(merged.len());
+                merged.push((abs_path, rel_path, result));
+            }
+        }
+    }
+
+    if warn_overflow {
+        warn!("Execution count overflow detected.");
+    }
+
+    merged
+}
+
+/// Adds a result for each file of the source directory matching `to_include_files` which doesn't
+/// have any coverage data, so that files which were never built still show up. Every non-blank
+/// line of these files is recorded with a zero count, as there is no data telling which lines are
+/// executable.
+pub fn add_missing_sources(
+    results: &mut Vec<ResultTuple>,
+    source_dir: &Path,
+    to_include_files: &[impl AsRef<str>],
+    to_ignore_dirs: &[impl AsRef<str>],
+    to_keep_dirs: &[impl AsRef<str>],
+) {
+    let to_include_globset = to_globset(to_include_files);
+    let to_ignore_globset = to_globset(to_ignore_dirs);
+    let to_keep_globset = to_globset(to_keep_dirs);
+
+    let known: FxHashSet<PathBuf> = results
+        .iter()
+        .map(|(abs_path, _, _)| canonicalize_path(abs_path).unwrap_or_else(|_| abs_path.clone()))
+        .collect();
+
+    for entry in WalkDir::new(source_dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || (!is_hidden(e) && !is_symbolic_link(e)))
+    {
+        let entry = entry
+            .unwrap_or_else(|_| panic!("Failed to open directory '{}'.", source_dir.display()));
+
+        let abs_path = entry.path();
+        if !abs_path.is_file() {
+            continue;
+        }
+
+        let rel_path = abs_path.strip_prefix(source_dir).unwrap();
+        // Always return results with '/'.
+        let rel_path = PathBuf::from(rel_path.to_str().unwrap().replace('\\', "/"));
+        if !to_include_globset.is_match(&rel_path)
+            || to_ignore_globset.is_match(&rel_path)
+            || (!to_keep_globset.is_empty() && !to_keep_globset.is_match(&rel_path))
+            || known
+                .contains(&canonicalize_path(abs_path).unwrap_or_else(|_| abs_path.to_path_buf()))
+        {
+            continue;
+        }
+
+        let lines = fs::read_to_string(abs_path)
+            .map(|source| {
+                source
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| !line.trim().is_empty())
+                    .map(|(i, _)| (i as u32 + 1, 0))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let result = CovResult {
+            lines,
+            ..Default::default()
+        };
+        results.push((abs_path.to_path_buf(), rel_path, result));
+    }
+}
+
+/// Turns the merged results into result tuples without resolving their paths against the
+/// source directory, for when the coverage data only needs to be merged. The `to_ignore_dirs` and
+/// `to_keep_dirs` globs are matched against the original paths.
+pub fn keep_original_paths(
+    result_map: CovResultMap,
+    to_ignore_dirs: &[impl AsRef<str>],
+    to_keep_dirs: &[impl AsRef<str>],
+) -> Vec<ResultTuple> {
+    let to_ignore_globset = to_globset(to_ignore_dirs);
+    let to_keep_globset = to_globset(to_keep_dirs);
+    result_map
+        .into_iter()
+        .filter(|(path, _)| {
+            !to_ignore_globset.is_match(path)
+                && (to_keep_globset.is_empty() || to_keep_globset.is_match(path))
+        })
+        .map(|(path, result)| (PathBuf::from(&path), PathBuf::from(path), result))
+        .collect()
 }
 
 #[cfg(test)]
@@ -350,6 +760,31 @@ mod tests {
     use serde_json::json;
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_expand_env_vars() {
+        env::set_var("GRCOV_TEST_BUILD_ROOT", "/builds/1234");
+        assert_eq!(
+            expand_env_vars("${GRCOV_TEST_BUILD_ROOT}/obj/**").unwrap(),
+            "/builds/1234/obj/**"
+        );
+        assert_eq!(
+            expand_env_vars("a/${GRCOV_TEST_BUILD_ROOT}${GRCOV_TEST_BUILD_ROOT}").unwrap(),
+            "a//builds/1234/builds/1234"
+        );
+        assert_eq!(expand_env_vars("$HOME/{a,b}").unwrap(), "$HOME/{a,b}");
+        assert!(expand_env_vars("${GRCOV_TEST_UNSET_VAR}/a")
+            .unwrap_err()
+            .contains("'GRCOV_TEST_UNSET_VAR'"));
+        assert!(expand_env_vars("${GRCOV_TEST_BUILD_ROOT").is_err());
+
+        let mapping = json!({"${GRCOV_TEST_BUILD_ROOT}/a.rs": "src/a.rs", "b.rs": ["${GRCOV_TEST_BUILD_ROOT}"], "c": 1});
+        assert_eq!(
+            expand_mapping_env_vars(mapping).unwrap(),
+            json!({"/builds/1234/a.rs": "src/a.rs", "b.rs": ["/builds/1234"], "c": 1})
+        );
+        assert!(expand_mapping_env_vars(json!({"a.rs": "${GRCOV_TEST_UNSET_VAR}"})).is_err());
+    }
+
     #[test]
     fn test_to_lowercase_first() {
         assert_eq!(to_lowercase_first("marco"), "marco");
@@ -380,6 +815,7 @@ mod tests {
                 lines: BTreeMap::new(),
                 branches: BTreeMap::new(),
                 functions: FxHashMap::default(),
+                ..Default::default()
             }
         }};
     }
@@ -390,6 +826,7 @@ mod tests {
                 lines: [(42, 1)].iter().cloned().collect(),
                 branches: BTreeMap::new(),
                 functions: FxHashMap::default(),
+                ..Default::default()
             }
         }};
     }
@@ -400,10 +837,18 @@ mod tests {
                 lines: [(42, 0)].iter().cloned().collect(),
                 branches: BTreeMap::new(),
                 functions: FxHashMap::default(),
+                ..Default::default()
             }
         }};
     }
 
+    #[test]
+    fn test_to_globset_backslash_separators() {
+        let globset = to_globset(&["src\\tests\\*"]);
+        assert!(globset.is_match("src/tests/foo.rs"));
+        assert!(!globset.is_match("src/main.rs"));
+    }
+
     macro_rules! skipping_result {
         () => {{
             let mut result = empty_result!();
@@ -429,6 +874,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -440,6 +886,118 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn test_add_missing_sources() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        for file in &[
+            "a.cpp",
+            "sub/b.cpp",
+            "sub/c.txt",
+            "ignored/d.cpp",
+            ".hidden/e.cpp",
+        ] {
+            let path = source_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "int x;\n\n  \nint y;\n").unwrap();
+        }
+
+        // The known results are matched by their absolute path, whatever their relative path.
+        let mut results = vec![(
+            source_dir.join("a.cpp"),
+            PathBuf::from("mapped/a.cpp"),
+            covered_result!(),
+        )];
+        add_missing_sources(
+            &mut results,
+            &source_dir,
+            &["*.cpp"],
+            &["ignored/*"],
+            &[""; 0],
+        );
+
+        assert_eq!(
+            results,
+            vec![
+                (
+                    source_dir.join("a.cpp"),
+                    PathBuf::from("mapped/a.cpp"),
+                    covered_result!()
+                ),
+                (
+                    source_dir.join("sub").join("b.cpp"),
+                    PathBuf::from("sub/b.cpp"),
+                    CovResult {
+                        lines: [(1, 0), (4, 0)].iter().cloned().collect(),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_paths_ignore_path_case() {
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("src/Foo.rs".to_string(), covered_result!());
+        result_map.insert("src/foo.rs".to_string(), uncovered_result!());
+        result_map.insert("src/bar.rs".to_string(), uncovered_result!());
+        let results = rewrite_paths(
+            result_map,
+            None,
+            None,
+            None,
+            false,
+            &[""; 0],
+            &[""; 0],
+            Some(true),
+            Default::default(),
+            &RewriteOptions {
+                ignore_path_case: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            results,
+            vec![(
+                PathBuf::from("src/Foo.rs"),
+                PathBuf::from("src/Foo.rs"),
+                covered_result!()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_keep_original_paths() {
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("/home/worker/src/main.cpp".to_string(), empty_result!());
+        result_map.insert("../non/existing.cpp".to_string(), empty_result!());
+        let mut results = keep_original_paths(result_map.clone(), &[""; 0], &[""; 0]);
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            results,
+            vec![
+                (
+                    PathBuf::from("/home/worker/src/main.cpp"),
+                    PathBuf::from("/home/worker/src/main.cpp"),
+                    empty_result!()
+                ),
+                (
+                    PathBuf::from("../non/existing.cpp"),
+                    PathBuf::from("../non/existing.cpp"),
+                    empty_result!()
+                ),
+            ]
+        );
+
+        let results = keep_original_paths(result_map.clone(), &["../**"], &[""; 0]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, PathBuf::from("/home/worker/src/main.cpp"));
+        let results = keep_original_paths(result_map, &[""; 0], &["../**"]);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, PathBuf::from("../non/existing.cpp"));
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_rewrite_paths_remove_prefix() {
@@ -458,6 +1016,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -487,6 +1046,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -516,6 +1076,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -545,6 +1106,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -572,6 +1134,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -604,6 +1167,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -632,6 +1196,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -659,6 +1224,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -690,6 +1256,7 @@ mod tests {
                 &[""; 0],
                 None,
                 Default::default(),
+                &RewriteOptions::default(),
             );
             let mut count = 0;
             for (abs_path, rel_path, result) in results {
@@ -723,6 +1290,7 @@ mod tests {
                 &[""; 0],
                 None,
                 Default::default(),
+                &RewriteOptions::default(),
             );
             let mut count = 0;
             for (abs_path, rel_path, result) in results {
@@ -752,6 +1320,7 @@ mod tests {
             &["mydir/*"],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -779,6 +1348,7 @@ mod tests {
             &["mydir/*"],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -810,6 +1380,7 @@ mod tests {
                 &keep_only_dirs,
                 None,
                 Default::default(),
+                &RewriteOptions::default(),
             );
             let mut count = 0;
             for (abs_path, rel_path, result) in results {
@@ -843,6 +1414,7 @@ mod tests {
                 &keep_only_dirs,
                 None,
                 Default::default(),
+                &RewriteOptions::default(),
             );
             let mut count = 0;
             for (abs_path, rel_path, result) in results {
@@ -874,6 +1446,7 @@ mod tests {
             &["foo/*.rs"],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -903,6 +1476,7 @@ mod tests {
             &["foo/*.rs"],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -928,6 +1502,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         )
         .iter()
         .any(|_| false);
@@ -949,6 +1524,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -977,6 +1553,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1004,6 +1581,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1031,6 +1609,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1043,6 +1622,45 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_rewrite_partial_path_for_kotlin() {
+        let rewrite = |partial_path_extensions: &[&str]| {
+            let mut result_map: CovResultMap = FxHashMap::default();
+            result_map.insert("kotlin/Main.kt".to_string(), empty_result!());
+            rewrite_paths(
+                result_map,
+                None,
+                Some(&canonicalize_path(".").unwrap()),
+                None,
+                true,
+                &[""; 0],
+                &[""; 0],
+                None,
+                Default::default(),
+                &RewriteOptions {
+                    partial_path_extensions: partial_path_extensions
+                        .iter()
+                        .map(|ext| ext.to_string())
+                        .collect(),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let mut results = rewrite(DEFAULT_PARTIAL_PATH_EXTENSIONS);
+        assert!(results.len() == 1);
+
+        let (abs_path, rel_path, result) = results.remove(0);
+        assert!(abs_path.is_absolute());
+        assert!(abs_path.ends_with("test/kotlin/Main.kt"));
+        assert_eq!(rel_path, PathBuf::from("test/kotlin/Main.kt"));
+        assert_eq!(result, empty_result!());
+
+        // Without the kt extension, the partial path doesn't exist and is ignored.
+        assert!(rewrite(&["java"]).is_empty());
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_rewrite_paths_rewrite_path_for_java_and_rust() {
@@ -1059,6 +1677,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         assert!(results.len() == 1);
 
@@ -1085,6 +1704,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         assert!(results.len() == 1);
 
@@ -1110,6 +1730,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1122,6 +1743,83 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_partial_path_respect_gitignore() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        for dir in &["src/org", "build/org"] {
+            fs::create_dir_all(source_dir.join(dir)).unwrap();
+            fs::write(source_dir.join(dir).join("Foo.java"), "class Foo {}").unwrap();
+        }
+        fs::write(source_dir.join(".gitignore"), "build/\n").unwrap();
+
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("org/Foo.java".to_string(), empty_result!());
+        let results = rewrite_paths(
+            result_map,
+            None,
+            Some(&source_dir),
+            None,
+            true,
+            &[""; 0],
+            &[""; 0],
+            None,
+            Default::default(),
+            &RewriteOptions {
+                respect_gitignore: true,
+                ..Default::default()
+            },
+        );
+        let mut count = 0;
+        for (abs_path, rel_path, result) in results {
+            count += 1;
+            assert_eq!(abs_path, source_dir.join("src/org/Foo.java"));
+            assert_eq!(rel_path, PathBuf::from("src/org/Foo.java"));
+            assert_eq!(result, empty_result!());
+        }
+        assert_eq!(count, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_partial_path_source_walk_pruning() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        // The temporary directory is hidden, so use a child as the source directory.
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap().join("project");
+        for dir in &["src/org", "vendor/org", "deep/a/b/org"] {
+            fs::create_dir_all(source_dir.join(dir)).unwrap();
+            fs::write(source_dir.join(dir).join("Foo.java"), "class Foo {}").unwrap();
+        }
+
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("org/Foo.java".to_string(), empty_result!());
+        let results = rewrite_paths(
+            result_map,
+            None,
+            Some(&source_dir),
+            None,
+            true,
+            &["vendor"],
+            &[""; 0],
+            None,
+            Default::default(),
+            &RewriteOptions {
+                source_walk_max_depth: Some(3),
+                prune_ignored_dirs: true,
+                ..Default::default()
+            },
+        );
+        let mut count = 0;
+        for (abs_path, rel_path, result) in results {
+            count += 1;
+            assert_eq!(abs_path, source_dir.join("src/org/Foo.java"));
+            assert_eq!(rel_path, PathBuf::from("src/org/Foo.java"));
+            assert_eq!(result, empty_result!());
+        }
+        assert_eq!(count, 1);
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_rewrite_paths_rewrite_path_using_absolute_source_directory_and_partial_path() {
@@ -1137,6 +1835,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1167,6 +1866,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1198,6 +1898,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1225,6 +1926,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1251,6 +1953,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1280,6 +1983,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1310,6 +2014,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1340,6 +2045,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1373,6 +2079,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1402,6 +2109,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1431,6 +2139,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1460,6 +2169,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1490,6 +2200,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1520,6 +2231,7 @@ mod tests {
             &[""; 0],
             None,
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1547,6 +2259,7 @@ mod tests {
             &[""; 0],
             Some(true),
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1573,6 +2286,7 @@ mod tests {
             &[""; 0],
             Some(false),
             Default::default(),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (abs_path, rel_path, result) in results {
@@ -1640,7 +2354,11 @@ mod tests {
                 Some(regex::Regex::new("excluded branch").unwrap()),
                 Some(regex::Regex::new("skip branch start").unwrap()),
                 Some(regex::Regex::new("skip branch end").unwrap()),
+                Default::default(),
+                Default::default(),
+                None,
             ),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (_, _, result) in results {
@@ -1662,6 +2380,214 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn test_rewrite_paths_exclude_lines_listing() {
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("test/java/skip.java".to_string(), skipping_result!());
+        result_map.insert("main.cpp".to_string(), skipping_result!());
+        let abs_main = canonicalize_path(".").unwrap().join("main.cpp");
+        let listing = format!(
+            "# From the linter\n./test/java/skip.java:2\ntest/java/skip.java:5\n{}:3\nmain.cpp:x\n",
+            abs_main.display()
+        );
+        let results = rewrite_paths(
+            result_map,
+            None,
+            Some(&canonicalize_path(".").unwrap()),
+            None,
+            false,
+            &[""; 0],
+            &[""; 0],
+            None,
+            crate::FileFilter::new(
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Default::default(),
+                crate::parse_lines_listing(&listing),
+                None,
+            ),
+            &RewriteOptions::default(),
+        );
+        assert_eq!(results.len(), 2);
+        for (_, rel_path, result) in results {
+            let excluded: &[u32] = if rel_path == Path::new("main.cpp") {
+                &[3]
+            } else {
+                &[2, 5]
+            };
+            for number in 1..20 {
+                let is_excluded = excluded.contains(&number);
+                assert_eq!(result.lines.contains_key(&number), !is_excluded);
+                assert_eq!(result.branches.contains_key(&number), !is_excluded);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_compilation_database_mapping() {
+        let database = r#"[
+            {"directory": "/build/app", "file": "../src/main.c", "command": "cc -c ../src/main.c"},
+            {"directory": "/build/app", "file": "util.c", "command": "cc -c util.c"},
+            {"directory": "/build/lib", "file": "util.c", "command": "cc -c util.c"},
+            {"directory": "/build/lib", "file": "/src/lib.c", "command": "cc -c /src/lib.c"},
+            {"directory": "/build/lib", "file": "./gen/lib.h", "command": "cc -c ./gen/lib.h"},
+            {"directory": "/build/lib", "file": "gen/../io.c", "command": "cc -c gen/../io.c"}
+        ]"#;
+
+        let mut path_mapping = Some(json!({"gen/lib.h": "/custom/lib.h"}));
+        add_compilation_database_mapping(&mut path_mapping, database).unwrap();
+        assert_eq!(
+            path_mapping,
+            Some(json!({
+                "../src/main.c": "/build/src/main.c",
+                "gen/lib.h": "/custom/lib.h",
+                "io.c": "/build/lib/io.c",
+            }))
+        );
+        assert_eq!(
+            apply_mapping(&path_mapping, "../src/main.c"),
+            PathBuf::from("/build/src/main.c")
+        );
+        assert_eq!(
+            apply_mapping(&path_mapping, "./gen/../io.c"),
+            PathBuf::from("/build/lib/io.c")
+        );
+
+        // A path relative to several directories is mapped to the one containing the file.
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path();
+        fs::create_dir_all(tmp_path.join("app")).unwrap();
+        fs::create_dir_all(tmp_path.join("lib")).unwrap();
+        fs::write(tmp_path.join("lib").join("util.c"), "").unwrap();
+        let database = json!([
+            {"directory": tmp_path.join("app"), "file": "util.c"},
+            {"directory": tmp_path.join("lib"), "file": "./util.c"},
+        ])
+        .to_string();
+        let mut path_mapping = None;
+        add_compilation_database_mapping(&mut path_mapping, &database).unwrap();
+        assert_eq!(
+            apply_mapping(&path_mapping, "util.c"),
+            tmp_path.join("lib").join("util.c")
+        );
+
+        let mut path_mapping = None;
+        assert!(add_compilation_database_mapping(&mut path_mapping, "{}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_no_canonicalize() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        fs::create_dir_all(source_dir.join("real")).unwrap();
+        fs::create_dir_all(source_dir.join("farm")).unwrap();
+        fs::write(source_dir.join("real").join("main.cpp"), "int main() {}\n").unwrap();
+        std::os::unix::fs::symlink(
+            source_dir.join("real").join("main.cpp"),
+            source_dir.join("farm").join("main.cpp"),
+        )
+        .unwrap();
+
+        for (canonicalize, expected) in &[(true, "real/main.cpp"), (false, "farm/main.cpp")] {
+            let mut result_map: CovResultMap = FxHashMap::default();
+            result_map.insert("farm/./main.cpp".to_string(), empty_result!());
+            let results = rewrite_paths(
+                result_map,
+                None,
+                Some(&source_dir),
+                None,
+                true,
+                &[""; 0],
+                &[""; 0],
+                None,
+                Default::default(),
+                &RewriteOptions {
+                    canonicalize: *canonicalize,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(results.len(), 1);
+            let (abs_path, rel_path, _) = &results[0];
+            assert_eq!(rel_path, Path::new(expected));
+            assert_eq!(abs_path, &source_dir.join(expected));
+        }
+    }
+
+    #[test]
+    fn test_rewrite_paths_ignored_dir() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        let scratch_dir = source_dir.join("scratch");
+        for dir in &["src", "scratch"] {
+            fs::create_dir_all(source_dir.join(dir)).unwrap();
+            fs::write(source_dir.join(dir).join("main.cpp"), "int main() {}\n").unwrap();
+        }
+
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("src/main.cpp".to_string(), empty_result!());
+        result_map.insert("scratch/main.cpp".to_string(), empty_result!());
+        result_map.insert(
+            scratch_dir.join("other.cpp").to_string_lossy().to_string(),
+            empty_result!(),
+        );
+        let results = rewrite_paths(
+            result_map,
+            None,
+            Some(&source_dir),
+            None,
+            false,
+            &[""; 0],
+            &[""; 0],
+            None,
+            Default::default(),
+            &RewriteOptions {
+                ignored_dir: Some(scratch_dir.clone()),
+                ..Default::default()
+            },
+        );
+        let rel_paths: Vec<&Path> = results
+            .iter()
+            .map(|(_, rel_path, _)| rel_path.as_path())
+            .collect();
+        assert_eq!(rel_paths, vec![Path::new("src/main.cpp")]);
+    }
+
+    #[test]
+    fn test_split_informational() {
+        let mut results: Vec<ResultTuple> = ["src/main.rs", "generated/bindings.rs", "src/gen.rs"]
+            .iter()
+            .map(|path| (PathBuf::from(path), PathBuf::from(path), empty_result!()))
+            .collect();
+        mark_informational(
+            results
+                .iter_mut()
+                .map(|(_, rel_path, result)| (rel_path.as_path(), result)),
+            &["generated/**"],
+        );
+        assert!(results[1].2.informational);
+        let (counted, informational) = split_informational(&results);
+        let paths = |results: &[&ResultTuple]| -> Vec<PathBuf> {
+            results
+                .iter()
+                .map(|(_, rel_path, _)| rel_path.clone())
+                .collect()
+        };
+        assert_eq!(
+            paths(&counted),
+            vec![PathBuf::from("src/main.rs"), PathBuf::from("src/gen.rs")]
+        );
+        assert_eq!(
+            paths(&informational),
+            vec![PathBuf::from("generated/bindings.rs")]
+        );
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_rewrite_paths_filter_lines_and_branches() {
@@ -1683,7 +2609,11 @@ mod tests {
                 Some(regex::Regex::new("excluded branch").unwrap()),
                 Some(regex::Regex::new("skip branch start").unwrap()),
                 Some(regex::Regex::new("skip branch end").unwrap()),
+                Default::default(),
+                Default::default(),
+                None,
             ),
+            &RewriteOptions::default(),
         );
         let mut count = 0;
         for (_, _, result) in results {
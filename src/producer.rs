@@ -1,13 +1,24 @@
+use log::{error, warn};
 use rustc_hash::FxHashMap;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use walkdir::WalkDir;
 use zip::ZipArchive;
 
 use crate::defs::*;
+use crate::{report_error, GrcovConfig};
+
+const GCOV_JSON_GZ_EXT: &str = ".gcov.json.gz";
+/// The name of the archive of the files given as input paths.
+const PLAIN_FILES_NAME: &str = "plain files";
+/// The error of the producer when the inputs don't contain any coverage artifact.
+pub(crate) const NO_INPUT_FILES: &str = "No input files found";
 
 #[derive(Debug)]
 pub enum ArchiveType {
@@ -20,6 +31,89 @@ pub enum ArchiveType {
 pub struct Archive {
     pub name: String,
     pub item: RefCell<ArchiveType>,
+    /// The checksums the files read or extracted from the archive are verified against.
+    pub manifest: Option<Rc<Manifest>>,
+    /// Whether the files of a directory are copied when extracted, rather than symlinked.
+    pub copy_extract: bool,
+}
+
+/// The SHA-256 checksums of the inputs, as lowercase hex strings, keyed by the path of each file
+/// in its archive or directory (or by its path as given for the files given directly).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub checksums: FxHashMap<String, String>,
+    /// Whether the files whose checksum doesn't match are still used, with a warning, rather than
+    /// skipped.
+    pub keep_going: bool,
+}
+
+impl Manifest {
+    /// Parses a manifest made of a `path:sha256` line per file. Empty lines and lines starting
+    /// with `#` are ignored.
+    pub fn parse(content: &str, keep_going: bool) -> Result<Manifest, String> {
+        let mut checksums = FxHashMap::default();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // The checksum is after the last colon, as Windows paths can contain colons.
+            let (path, checksum) = line
+                .rsplit_once(':')
+                .ok_or_else(|| format!("Invalid manifest line {}: '{}'", i + 1, line))?;
+            let checksum = checksum.trim().to_ascii_lowercase();
+            if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "Invalid SHA-256 checksum on manifest line {}: '{}'",
+                    i + 1,
+                    checksum
+                ));
+            }
+            let path = path.trim().replace('\\', "/");
+            let path = path.strip_prefix("./").unwrap_or(&path);
+            checksums.insert(path.to_string(), checksum);
+        }
+        Ok(Manifest {
+            checksums,
+            keep_going,
+        })
+    }
+
+    /// Checks the checksum of the file `name` of `archive`, returning whether it can be used.
+    fn verify(&self, archive: &Archive, name: &str, buf: &[u8]) -> bool {
+        let path = name.replace('\\', "/");
+        let path = if let ArchiveType::Plain(_) = *archive.item.borrow() {
+            env::current_dir()
+                .ok()
+                .and_then(|current_dir| {
+                    Path::new(name)
+                        .strip_prefix(current_dir)
+                        .ok()
+                        .map(|path| path.to_string_lossy().replace('\\', "/"))
+                })
+                .unwrap_or(path)
+        } else {
+            path
+        };
+        let checksum = format!("{:x}", Sha256::digest(buf));
+        let error = match self.checksums.get(&path) {
+            Some(expected) if *expected == checksum => return true,
+            Some(expected) => format!(
+                "The SHA-256 checksum of {} is {}, but {} in the manifest",
+                archive.get_file_name(name),
+                checksum,
+                expected
+            ),
+            None => format!("{} isn't in the manifest", archive.get_file_name(name)),
+        };
+        if self.keep_going {
+            warn!("{}", error);
+            true
+        } else {
+            error!("{}", error);
+            false
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -38,6 +132,41 @@ fn clean_path(path: &Path) -> String {
     path.to_str().unwrap().to_string().replace("\\", "/")
 }
 
+/// Returns true if `file_type` is a FIFO or a character device, i.e. a file that can only be read
+/// once from start to end and whose size isn't known in advance.
+#[cfg(unix)]
+fn is_stream(file_type: fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    file_type.is_fifo() || file_type.is_char_device()
+}
+
+#[cfg(not(unix))]
+fn is_stream(_file_type: fs::FileType) -> bool {
+    false
+}
+
+/// Opens `path` if `file_type` is a regular file, without opening streams (opening a FIFO
+/// blocks until a writer shows up). Returns the file and whether `path` is a stream, or `None`
+/// if `path` is neither a regular file nor a stream.
+fn open_file(path: &Path, file_type: fs::FileType) -> Option<(Option<File>, bool)> {
+    if file_type.is_file() {
+        Some((File::open(path).ok(), false))
+    } else if is_stream(file_type) {
+        Some((None, true))
+    } else {
+        None
+    }
+}
+
+/// Returns the size of `file` if it is a regular file, 0 otherwise.
+fn size_hint(file: &File) -> usize {
+    file.metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map_or(0, |metadata| metadata.len() as usize)
+}
+
 impl Archive {
     fn insert_vec<'a>(
         &'a self,
@@ -59,9 +188,20 @@ impl Archive {
         profraws: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         infos: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        gcovs: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        python_coverages: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         linked_files_maps: &RefCell<FxHashMap<String, &'a Archive>>,
         is_llvm: bool,
+        is_stream: bool,
     ) {
+        if cfg!(feature = "python") && Archive::is_python_coverage_name(path) {
+            if is_stream || Archive::check_file(file, &Archive::is_sqlite) {
+                let filename = clean_path(path);
+                self.insert_vec(filename, python_coverages);
+            }
+            return;
+        }
+
         if let Some(ext) = path.extension() {
             match ext.to_str().unwrap() {
                 "gcno" => {
@@ -84,17 +224,28 @@ impl Archive {
                     self.insert_vec(filename, profraws);
                 }
                 "info" => {
-                    if Archive::check_file(file, &Archive::is_info) {
+                    // A stream can only be read once, so it is routed by extension only.
+                    if is_stream || Archive::check_file(file, &Archive::is_info) {
                         let filename = clean_path(path);
                         self.insert_vec(filename, infos);
                     }
                 }
                 "xml" => {
-                    if Archive::check_file(file, &Archive::is_jacoco) {
+                    if is_stream || Archive::check_file(file, &Archive::is_jacoco) {
                         let filename = clean_path(path);
                         self.insert_vec(filename, xmls);
                     }
                 }
+                "gcov" => {
+                    let filename = clean_path(path);
+                    self.insert_vec(filename, gcovs);
+                }
+                "gz" => {
+                    let filename = clean_path(path);
+                    if filename.ends_with(GCOV_JSON_GZ_EXT) {
+                        self.insert_vec(filename, gcovs);
+                    }
+                }
                 "json" => {
                     let filename = path.file_name().unwrap();
                     if filename == "linked-files-map.json" {
@@ -127,18 +278,44 @@ impl Archive {
 
     fn is_info(reader: &mut dyn Read) -> bool {
         let mut bytes: [u8; 3] = [0; 3];
-        reader.read_exact(&mut bytes).is_ok()
-            && (bytes == [b'T', b'N', b':'] || bytes == [b'S', b'F', b':'])
+        reader.read_exact(&mut bytes).is_ok() && (&bytes == b"TN:" || &bytes == b"SF:")
+    }
+
+    /// Returns whether the file name is the one of the data files of coverage.py, either
+    /// `.coverage` or `.coverage.SUFFIX` when running in parallel mode.
+    fn is_python_coverage_name(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name == ".coverage" || name.starts_with(".coverage."))
+    }
+
+    fn is_sqlite(reader: &mut dyn Read) -> bool {
+        let mut bytes: [u8; 16] = [0; 16];
+        reader.read_exact(&mut bytes).is_ok() && &bytes == b"SQLite format 3\0"
     }
 
     fn check_file(file: Option<&mut impl Read>, checker: &dyn Fn(&mut dyn Read) -> bool) -> bool {
-        file.map_or(false, |f| checker(f))
+        file.is_some_and(|f| checker(f))
     }
 
     pub fn get_name(&self) -> &String {
         &self.name
     }
 
+    /// Returns the name the items of the archive are tagged with, with `--per-archive`.
+    fn get_tag(&self, per_archive: bool) -> Option<String> {
+        per_archive.then(|| self.name.clone())
+    }
+
+    /// Returns a name identifying the file `name` of the archive, for error messages.
+    fn get_file_name(&self, name: &str) -> String {
+        if let ArchiveType::Plain(_) = *self.item.borrow() {
+            name.to_string()
+        } else {
+            clean_path(&Path::new(&self.name).join(name))
+        }
+    }
+
     pub fn explore<'a>(
         &'a mut self,
         gcno_stem_archives: &RefCell<FxHashMap<GCNOStem, &'a Archive>>,
@@ -146,6 +323,8 @@ impl Archive {
         profraws: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         infos: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        gcovs: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        python_coverages: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         linked_files_maps: &RefCell<FxHashMap<String, &'a Archive>>,
         is_llvm: bool,
     ) {
@@ -163,8 +342,11 @@ impl Archive {
                         profraws,
                         infos,
                         xmls,
+                        gcovs,
+                        python_coverages,
                         linked_files_maps,
                         is_llvm,
+                        false,
                     );
                 }
             }
@@ -177,8 +359,16 @@ impl Archive {
                         )
                     });
                     let full_path = entry.path();
-                    if full_path.is_file() {
-                        let mut file = File::open(full_path).ok();
+                    // The file type of a walked entry comes for free, only symlinks need a stat.
+                    let file_type = if entry.path_is_symlink() {
+                        match fs::metadata(full_path) {
+                            Ok(metadata) => metadata.file_type(),
+                            Err(_) => continue,
+                        }
+                    } else {
+                        entry.file_type()
+                    };
+                    if let Some((mut file, is_stream)) = open_file(full_path, file_type) {
                         let path = full_path.strip_prefix(dir).unwrap();
                         self.handle_file(
                             file.as_mut(),
@@ -188,8 +378,11 @@ impl Archive {
                             profraws,
                             infos,
                             xmls,
+                            gcovs,
+                            python_coverages,
                             linked_files_maps,
                             is_llvm,
+                            is_stream,
                         );
                     }
                 }
@@ -197,7 +390,10 @@ impl Archive {
             ArchiveType::Plain(ref plain) => {
                 // All the paths are absolutes
                 for full_path in plain {
-                    let mut file = File::open(full_path).ok();
+                    let (mut file, is_stream) = fs::metadata(full_path)
+                        .ok()
+                        .and_then(|metadata| open_file(full_path, metadata.file_type()))
+                        .unwrap_or((None, false));
                     self.handle_file(
                         file.as_mut(),
                         full_path,
@@ -206,16 +402,32 @@ impl Archive {
                         profraws,
                         infos,
                         xmls,
+                        gcovs,
+                        python_coverages,
                         linked_files_maps,
                         is_llvm,
+                        is_stream,
                     );
                 }
             }
         }
     }
 
+    /// Checks the content of the file `name` against the manifest, if any.
+    fn check_manifest(&self, name: &str, buf: &[u8]) -> bool {
+        self.manifest
+            .as_ref()
+            .is_none_or(|manifest| manifest.verify(self, name, buf))
+    }
+
+    /// Checks the file `name` of a plain archive, which is used in place rather than read or
+    /// extracted, against the manifest, if any.
+    fn check_plain_file(&self, name: &str) -> bool {
+        self.manifest.is_none() || fs::read(name).is_ok_and(|buf| self.check_manifest(name, &buf))
+    }
+
     pub fn read(&self, name: &str) -> Option<Vec<u8>> {
-        match *self.item.borrow_mut() {
+        let buf = match *self.item.borrow_mut() {
             ArchiveType::Zip(ref mut zip) => {
                 let mut zip = zip.borrow_mut();
                 let zipfile = zip.by_name(name);
@@ -229,45 +441,34 @@ impl Archive {
                 }
             }
             ArchiveType::Dir(ref dir) => {
-                let path = dir.join(name);
-                if let Ok(metadata) = fs::metadata(&path) {
-                    match File::open(path) {
-                        Ok(mut f) => {
-                            let mut buf = Vec::with_capacity(metadata.len() as usize + 1);
-                            f.read_to_end(&mut buf).expect("Failed to read gcda file");
-                            Some(buf)
-                        }
-                        Err(_) => None,
-                    }
-                } else {
-                    None
-                }
+                let mut f = File::open(dir.join(name)).ok()?;
+                let mut buf = Vec::with_capacity(size_hint(&f) + 1);
+                f.read_to_end(&mut buf).expect("Failed to read gcda file");
+                Some(buf)
             }
             ArchiveType::Plain(_) => {
-                if let Ok(metadata) = fs::metadata(name) {
-                    match File::open(name) {
-                        Ok(mut f) => {
-                            let mut buf = Vec::with_capacity(metadata.len() as usize + 1);
-                            f.read_to_end(&mut buf)
-                                .unwrap_or_else(|_| panic!("Failed to read file: {}.", name));
-                            Some(buf)
-                        }
-                        Err(_) => None,
-                    }
-                } else {
-                    None
-                }
+                let mut f = File::open(name).ok()?;
+                let mut buf = Vec::with_capacity(size_hint(&f) + 1);
+                f.read_to_end(&mut buf)
+                    .unwrap_or_else(|_| panic!("Failed to read file: {}.", name));
+                Some(buf)
             }
+        }?;
+        if self.check_manifest(name, &buf) {
+            Some(buf)
+        } else {
+            None
         }
     }
 
     pub fn extract(&self, name: &str, path: &Path) -> bool {
+        let path = &to_extended_length_path(path);
         let dest_parent = path.parent().unwrap();
         if !dest_parent.exists() {
             fs::create_dir_all(dest_parent).expect("Cannot create parent directory");
         }
 
-        match *self.item.borrow_mut() {
+        let extracted = match *self.item.borrow_mut() {
             ArchiveType::Zip(ref mut zip) => {
                 let mut zip = zip.borrow_mut();
                 let zipfile = zip.by_name(name);
@@ -281,33 +482,92 @@ impl Archive {
             }
             ArchiveType::Dir(ref dir) => {
                 // don't use a hard link here because it can fail when src and dst are not on the same device
-                let src_path = dir.join(name);
+                let src_path = to_extended_length_path(&dir.join(name));
 
-                crate::symlink::symlink_file(&src_path, path).unwrap_or_else(|_| {
-                    panic!("Failed to create a symlink {:?} -> {:?}", src_path, path)
-                });
+                if self.copy_extract {
+                    let mut src = File::open(&src_path)
+                        .unwrap_or_else(|_| panic!("Failed to open file {:?}", src_path));
+                    let mut file = File::create(path).expect("Failed to create file");
+                    io::copy(&mut src, &mut file).unwrap_or_else(|_| {
+                        panic!("Failed to copy file {:?} -> {:?}", src_path, path)
+                    });
+                } else {
+                    crate::symlink::symlink_file(&src_path, path).unwrap_or_else(|_| {
+                        panic!("Failed to create a symlink {:?} -> {:?}", src_path, path)
+                    });
+                }
                 true
             }
             ArchiveType::Plain(_) => {
                 panic!("We shouldn't be there !!");
             }
+        };
+        if !extracted || self.manifest.is_none() {
+            return extracted;
         }
+        let buf = fs::read(path).unwrap_or_else(|_| panic!("Failed to read file: {:?}.", path));
+        self.check_manifest(name, &buf)
     }
 }
 
+/// On Windows, prefixes an absolute path with `\\?\` so that paths longer than MAX_PATH can be
+/// used (this is the reverse of what `canonicalize_path` strips).
+#[cfg(windows)]
+fn to_extended_length_path(path: &Path) -> PathBuf {
+    let path_str = path.to_str().unwrap();
+    if !path.is_absolute() || path_str.starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+
+    // Extended-length paths aren't normalized by Windows, so they must not contain "." or ".."
+    // components and must only use backslashes.
+    let path_str = match crate::path_rewriting::normalize_path(path) {
+        Some(normalized) => normalized.to_str().unwrap().replace('/', "\\"),
+        None => return path.to_path_buf(),
+    };
+    if let Some(unc) = path_str.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", unc))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", path_str))
+    }
+}
+
+#[cfg(not(windows))]
+fn to_extended_length_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+fn report_extraction_error(
+    parse_errors: &SyncParseErrors,
+    name: &str,
+    format: ItemFormat,
+    archive: &Archive,
+) {
+    error!("Failed to extract {} from {}", name, archive.get_name());
+    report_error(
+        parse_errors,
+        name,
+        format,
+        format!("Failed to extract it from {}", archive.get_name()),
+    );
+}
+
 fn gcno_gcda_producer(
     tmp_dir: &Path,
     gcno_stem_archives: &FxHashMap<GCNOStem, &Archive>,
     gcda_stem_archives: &FxHashMap<String, Vec<&Archive>>,
     sender: &JobSender,
     ignore_orphan_gcno: bool,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
 ) {
-    let send_job = |item, name| {
+    let send_job = |item, name, archive| {
         sender
             .send(Some(WorkItem {
                 format: ItemFormat::Gcno,
                 item,
                 name,
+                archive,
             }))
             .unwrap()
     };
@@ -320,24 +580,48 @@ fn gcno_gcda_producer(
             let physical_gcno_path = tmp_dir.join(format!("{}_{}.gcno", stem, 1));
             if gcno_stem.llvm {
                 let mut gcda_buffers: Vec<Vec<u8>> = Vec::with_capacity(gcda_archives.len());
+                let mut gcda_tags: Vec<Option<String>> = Vec::with_capacity(gcda_archives.len());
                 if let Some(gcno_buffer) = gcno_archive.read(&gcno) {
                     for gcda_archive in gcda_archives {
                         let gcda = format!("{}.gcda", stem).to_string();
                         if let Some(gcda_buf) = gcda_archive.read(&gcda) {
                             gcda_buffers.push(gcda_buf);
+                            gcda_tags.push(gcda_archive.get_tag(per_archive));
                         }
                     }
-                    send_job(
-                        ItemType::Buffers(GcnoBuffers {
-                            stem: stem.clone(),
-                            gcno_buf: gcno_buffer,
-                            gcda_buf: gcda_buffers,
-                        }),
-                        "".to_string(),
-                    );
+                    if per_archive && !gcda_buffers.is_empty() {
+                        // The gcda files of each archive are parsed on their own, to tell which
+                        // archive executed each line.
+                        for (gcda_buf, tag) in gcda_buffers.into_iter().zip(gcda_tags) {
+                            send_job(
+                                ItemType::Buffers(GcnoBuffers {
+                                    stem: stem.clone(),
+                                    gcno_buf: gcno_buffer.clone(),
+                                    gcda_buf: vec![gcda_buf],
+                                }),
+                                "".to_string(),
+                                tag,
+                            );
+                        }
+                    } else {
+                        send_job(
+                            ItemType::Buffers(GcnoBuffers {
+                                stem: stem.clone(),
+                                gcno_buf: gcno_buffer,
+                                gcda_buf: gcda_buffers,
+                            }),
+                            "".to_string(),
+                            gcno_archive.get_tag(per_archive),
+                        );
+                    }
+                } else {
+                    report_extraction_error(parse_errors, &gcno, ItemFormat::Gcno, gcno_archive);
                 }
             } else {
-                gcno_archive.extract(&gcno, &physical_gcno_path);
+                if !gcno_archive.extract(&gcno, &physical_gcno_path) {
+                    report_extraction_error(parse_errors, &gcno, ItemFormat::Gcno, gcno_archive);
+                    continue;
+                }
                 for (num, &gcda_archive) in gcda_archives.iter().enumerate() {
                     let gcno_path = tmp_dir.join(format!("{}_{}.gcno", stem, num + 1));
                     let gcda = format!("{}.gcda", stem).to_string();
@@ -355,6 +639,7 @@ fn gcno_gcda_producer(
                         send_job(
                             ItemType::Path((stem.clone(), gcno_path)),
                             gcda_archive.get_name().to_string(),
+                            gcda_archive.get_tag(per_archive),
                         );
                     }
                 }
@@ -371,7 +656,10 @@ fn gcno_gcda_producer(
                             gcda_buf: Vec::new(),
                         }),
                         gcno_archive.get_name().to_string(),
+                        gcno_archive.get_tag(per_archive),
                     );
+                } else {
+                    report_extraction_error(parse_errors, &gcno, ItemFormat::Gcno, gcno_archive);
                 }
             } else {
                 let physical_gcno_path = tmp_dir.join(format!("{}_{}.gcno", stem, 1));
@@ -379,7 +667,10 @@ fn gcno_gcda_producer(
                     send_job(
                         ItemType::Path((stem.clone(), physical_gcno_path)),
                         gcno_archive.get_name().to_string(),
+                        gcno_archive.get_tag(per_archive),
                     );
+                } else {
+                    report_extraction_error(parse_errors, &gcno, ItemFormat::Gcno, gcno_archive);
                 }
             }
         }
@@ -390,12 +681,15 @@ fn profraw_producer(
     tmp_dir: &Path,
     profraws: &FxHashMap<String, Vec<&Archive>>,
     sender: &JobSender,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
 ) {
     if profraws.is_empty() {
         return;
     }
 
-    let mut profraw_paths = Vec::new();
+    // The profraws are all converted together, unless they're tagged with their archive.
+    let mut profraw_paths: BTreeMap<Option<String>, Vec<PathBuf>> = BTreeMap::new();
 
     for (name, archives) in profraws {
         let path = PathBuf::from(name);
@@ -411,30 +705,87 @@ fn profraw_producer(
             };
 
             let profraw_path = if let Some(profraw_path) = profraw_path {
+                if !archive.check_plain_file(name) {
+                    report_extraction_error(parse_errors, name, ItemFormat::Profraw, archive);
+                    continue;
+                }
                 profraw_path
             } else {
                 let tmp_path = tmp_dir.join(format!("{}_{}.profraw", stem, num + 1));
-                archive.extract(name, &tmp_path);
+                if !archive.extract(name, &tmp_path) {
+                    report_extraction_error(parse_errors, name, ItemFormat::Profraw, archive);
+                    continue;
+                }
                 tmp_path
             };
 
-            profraw_paths.push(profraw_path);
+            profraw_paths
+                .entry(archive.get_tag(per_archive))
+                .or_default()
+                .push(profraw_path);
         }
     }
 
-    sender
-        .send(Some(WorkItem {
-            format: ItemFormat::Profraw,
-            item: ItemType::Paths(profraw_paths),
-            name: "profraws".to_string(),
-        }))
-        .unwrap()
+    for (archive, paths) in profraw_paths {
+        sender
+            .send(Some(WorkItem {
+                format: ItemFormat::Profraw,
+                item: ItemType::Paths(paths),
+                name: "profraws".to_string(),
+                archive,
+            }))
+            .unwrap();
+    }
+}
+
+fn gcov_producer(
+    tmp_dir: &Path,
+    gcovs: &FxHashMap<String, Vec<&Archive>>,
+    sender: &JobSender,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
+) {
+    for (name, archives) in gcovs {
+        let (stem, ext) = match name.strip_suffix(GCOV_JSON_GZ_EXT) {
+            Some(stem) => (stem, GCOV_JSON_GZ_EXT),
+            None => (name.strip_suffix(".gcov").unwrap(), ".gcov"),
+        };
+
+        for (num, &archive) in archives.iter().enumerate() {
+            let is_plain = matches!(*archive.item.borrow(), ArchiveType::Plain(_));
+            let gcov_path = if is_plain {
+                if !archive.check_plain_file(name) {
+                    report_extraction_error(parse_errors, name, ItemFormat::Gcov, archive);
+                    continue;
+                }
+                PathBuf::from(name)
+            } else {
+                let tmp_path = tmp_dir.join(format!("{}_{}{}", stem, num + 1, ext));
+                if !archive.extract(name, &tmp_path) {
+                    report_extraction_error(parse_errors, name, ItemFormat::Gcov, archive);
+                    continue;
+                }
+                tmp_path
+            };
+
+            sender
+                .send(Some(WorkItem {
+                    format: ItemFormat::Gcov,
+                    item: ItemType::Path((stem.to_string(), gcov_path)),
+                    name: archive.get_file_name(name),
+                    archive: archive.get_tag(per_archive),
+                }))
+                .unwrap();
+        }
+    }
 }
 
 fn file_content_producer(
     files: &FxHashMap<String, Vec<&Archive>>,
     sender: &JobSender,
     item_format: ItemFormat,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
 ) {
     for (name, archives) in files {
         for archive in archives {
@@ -443,14 +794,108 @@ fn file_content_producer(
                     .send(Some(WorkItem {
                         format: item_format,
                         item: ItemType::Content(buffer),
-                        name: archive.get_name().to_string(),
+                        name: archive.get_file_name(name),
+                        archive: archive.get_tag(per_archive),
                     }))
                     .unwrap();
+            } else {
+                report_extraction_error(parse_errors, name, item_format, archive);
             }
         }
     }
 }
 
+fn python_coverage_producer(
+    tmp_dir: &Path,
+    python_coverages: &FxHashMap<String, Vec<&Archive>>,
+    sender: &JobSender,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
+) {
+    if !python_coverages.is_empty() {
+        warn!("The coverage.py data files only record the executed lines: the lines which were never executed, the functions and the branches of the Python files are missing (use 'coverage lcov' to convert them instead).");
+    }
+
+    for (name, archives) in python_coverages {
+        for (num, &archive) in archives.iter().enumerate() {
+            let is_plain = matches!(*archive.item.borrow(), ArchiveType::Plain(_));
+            // SQLite needs a file on the disk.
+            let path = if is_plain {
+                if !archive.check_plain_file(name) {
+                    report_extraction_error(
+                        parse_errors,
+                        name,
+                        ItemFormat::PythonCoverage,
+                        archive,
+                    );
+                    continue;
+                }
+                PathBuf::from(name)
+            } else {
+                let tmp_path = tmp_dir.join(format!("{}_{}", name, num + 1));
+                if !archive.extract(name, &tmp_path) {
+                    report_extraction_error(
+                        parse_errors,
+                        name,
+                        ItemFormat::PythonCoverage,
+                        archive,
+                    );
+                    continue;
+                }
+                tmp_path
+            };
+
+            sender
+                .send(Some(WorkItem {
+                    format: ItemFormat::PythonCoverage,
+                    item: ItemType::Path((name.to_string(), path)),
+                    name: archive.get_file_name(name),
+                    archive: archive.get_tag(per_archive),
+                }))
+                .unwrap();
+        }
+    }
+}
+
+/// Sends the plain files given as inputs with the format forced by `--input-format`, whatever
+/// their extension or content.
+fn forced_format_producer(
+    files: &[PathBuf],
+    format: ItemFormat,
+    sender: &JobSender,
+    parse_errors: &SyncParseErrors,
+    per_archive: bool,
+) {
+    for path in files {
+        let name = clean_path(path);
+        let item = match format {
+            ItemFormat::Info | ItemFormat::JacocoXml => match fs::read(path) {
+                Ok(buffer) => ItemType::Content(buffer),
+                Err(e) => {
+                    error!("Failed to read {}: {}", name, e);
+                    report_error(parse_errors, &name, format, e.to_string());
+                    continue;
+                }
+            },
+            ItemFormat::Gcov => {
+                ItemType::Path((clean_path(&path.with_extension("")), path.clone()))
+            }
+            ItemFormat::Gcno | ItemFormat::Profraw | ItemFormat::PythonCoverage => {
+                panic!("The {} format can't be forced", format)
+            }
+        };
+
+        sender
+            .send(Some(WorkItem {
+                format,
+                item,
+                name,
+                archive: per_archive.then(|| PLAIN_FILES_NAME.to_string()),
+            }))
+            .unwrap();
+    }
+}
+
 pub fn get_mapping(linked_files_maps: &FxHashMap<String, &Archive>) -> Option<Vec<u8>> {
     if let Some((name, archive)) = linked_files_maps.iter().next() {
         archive.read(name)
@@ -465,25 +910,80 @@ fn open_archive(path: &str) -> ZipArchive<BufReader<File>> {
     ZipArchive::new(reader).unwrap_or_else(|_| panic!("Failed to parse ZIP file: {}", path))
 }
 
+/// Unpacks the ZIP file `path` (e.g. an archive of the source files) to the directory `dest`.
+pub fn extract_zip(path: &Path, dest: &Path) -> Result<(), String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open ZIP file '{}': {}", path.display(), e))?;
+    ZipArchive::new(BufReader::new(file))
+        .and_then(|mut archive| archive.extract(dest))
+        .map_err(|e| format!("Failed to extract ZIP file '{}': {}", path.display(), e))
+}
+
+/// Unpacks the TAR file `path` to `dest`, returning a directory archive for its content and a ZIP
+/// archive for each ZIP file it contains. Only one level of nesting is supported: ZIP files
+/// contained in those ZIP files aren't opened.
+fn open_tar(path: &str, dest: &Path) -> Vec<Archive> {
+    let file = File::open(path).unwrap_or_else(|_| panic!("Failed to open TAR file '{}'.", path));
+    tar::Archive::new(BufReader::new(file))
+        .unpack(dest)
+        .unwrap_or_else(|e| panic!("Failed to unpack TAR file '{}': {}", path, e));
+
+    let mut archives = vec![Archive {
+        name: path.to_string(),
+        item: RefCell::new(ArchiveType::Dir(dest.to_path_buf())),
+        manifest: None,
+        copy_extract: false,
+    }];
+    for entry in WalkDir::new(dest).sort_by_file_name() {
+        let entry = entry.unwrap_or_else(|e| panic!("Failed to read TAR file '{}': {}", path, e));
+        let zip_path = entry.path();
+        if entry.file_type().is_file() && zip_path.extension().is_some_and(|ext| ext == "zip") {
+            let name = clean_path(&Path::new(path).join(zip_path.strip_prefix(dest).unwrap()));
+            archives.push(Archive {
+                name,
+                item: RefCell::new(ArchiveType::Zip(RefCell::new(open_archive(
+                    zip_path.to_str().unwrap(),
+                )))),
+                manifest: None,
+                copy_extract: false,
+            });
+        }
+    }
+    archives
+}
+
 pub fn producer(
     tmp_dir: &Path,
-    paths: &[String],
     sender: &JobSender,
-    ignore_orphan_gcno: bool,
-    is_llvm: bool,
-) -> Option<Vec<u8>> {
+    parse_errors: &SyncParseErrors,
+    config: &GrcovConfig,
+) -> Result<Option<Vec<u8>>, String> {
+    let is_llvm = config.is_llvm;
+    let input_format = config.input_format;
+    let per_archive = config.per_archive;
+
     let mut archives: Vec<Archive> = Vec::new();
     let mut plain_files: Vec<PathBuf> = Vec::new();
+    let mut forced_files: Vec<PathBuf> = Vec::new();
 
     let current_dir = env::current_dir().unwrap();
 
-    for path in paths {
+    let paths = &config.paths;
+    #[cfg(feature = "net")]
+    let paths = &crate::download::download_inputs(paths, tmp_dir)?;
+
+    for (i, path) in paths.iter().enumerate() {
         if path.ends_with(".zip") {
             let archive = open_archive(path);
             archives.push(Archive {
                 name: path.to_string(),
                 item: RefCell::new(ArchiveType::Zip(RefCell::new(archive))),
+                manifest: None,
+                copy_extract: false,
             });
+        } else if path.ends_with(".tar") {
+            let dest = tmp_dir.join("tars").join(i.to_string());
+            archives.append(&mut open_tar(path, &dest));
         } else {
             let path_dir = PathBuf::from(path);
             let full_path = if path_dir.is_relative() {
@@ -495,30 +995,50 @@ pub fn producer(
                 archives.push(Archive {
                     name: path.to_string(),
                     item: RefCell::new(ArchiveType::Dir(full_path)),
+                    manifest: None,
+                    copy_extract: false,
                 });
+            } else if input_format.is_some() {
+                forced_files.push(full_path);
+            } else if cfg!(feature = "python") && Archive::is_python_coverage_name(&full_path) {
+                plain_files.push(full_path);
             } else if let Some(ext) = full_path.clone().extension() {
                 let ext = ext.to_str().unwrap();
-                if ext == "info" || ext == "json" || ext == "xml" || ext == "profraw" {
+                if ext == "info"
+                    || ext == "json"
+                    || ext == "xml"
+                    || ext == "profraw"
+                    || ext == "gcov"
+                    || path.ends_with(GCOV_JSON_GZ_EXT)
+                {
                     plain_files.push(full_path);
                 } else {
                     panic!(
-                        "Cannot load file '{:?}': it isn't a .info, a .json or a .xml file.",
+                        "Cannot load file '{:?}': it isn't a .info, a .json, a .xml, a .gcov or a .gcov.json.gz file.",
                         full_path
                     );
                 }
             } else {
-                panic!("Cannot load file '{:?}': it isn't a directory, a .info, a .json or a .xml file.", full_path);
+                panic!("Cannot load file '{:?}': it isn't a directory, a .info, a .json, a .xml, a .gcov or a .gcov.json.gz file.", full_path);
             }
         }
     }
 
     if !plain_files.is_empty() {
         archives.push(Archive {
-            name: "plain files".to_string(),
+            name: PLAIN_FILES_NAME.to_string(),
             item: RefCell::new(ArchiveType::Plain(plain_files)),
+            manifest: None,
+            copy_extract: false,
         });
     }
 
+    let manifest = config.manifest.clone().map(Rc::new);
+    for archive in &mut archives {
+        archive.manifest = manifest.clone();
+        archive.copy_extract = config.copy_extract;
+    }
+
     let gcno_stems_archives: RefCell<FxHashMap<GCNOStem, &Archive>> =
         RefCell::new(FxHashMap::default());
     let gcda_stems_archives: RefCell<FxHashMap<String, Vec<&Archive>>> =
@@ -526,6 +1046,9 @@ pub fn producer(
     let profraws: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
     let infos: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
     let xmls: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
+    let gcovs: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
+    let python_coverages: RefCell<FxHashMap<String, Vec<&Archive>>> =
+        RefCell::new(FxHashMap::default());
     let linked_files_maps: RefCell<FxHashMap<String, &Archive>> =
         RefCell::new(FxHashMap::default());
 
@@ -536,31 +1059,87 @@ pub fn producer(
             &profraws,
             &infos,
             &xmls,
+            &gcovs,
+            &python_coverages,
             &linked_files_maps,
             is_llvm,
         );
     }
 
-    assert!(
-        !(gcno_stems_archives.borrow().is_empty()
-            && profraws.borrow().is_empty()
-            && infos.borrow().is_empty()
-            && xmls.borrow().is_empty()),
-        "No input files found"
-    );
+    if gcno_stems_archives.borrow().is_empty()
+        && profraws.borrow().is_empty()
+        && infos.borrow().is_empty()
+        && xmls.borrow().is_empty()
+        && gcovs.borrow().is_empty()
+        && python_coverages.borrow().is_empty()
+        && forced_files.is_empty()
+    {
+        return Err(NO_INPUT_FILES.to_string());
+    }
+
+    if let Some(input_format) = input_format {
+        forced_format_producer(
+            &forced_files,
+            input_format,
+            sender,
+            parse_errors,
+            per_archive,
+        );
+    }
 
-    file_content_producer(&infos.into_inner(), sender, ItemFormat::Info);
-    file_content_producer(&xmls.into_inner(), sender, ItemFormat::JacocoXml);
-    profraw_producer(tmp_dir, &profraws.into_inner(), sender);
+    file_content_producer(
+        &infos.into_inner(),
+        sender,
+        ItemFormat::Info,
+        parse_errors,
+        per_archive,
+    );
+    file_content_producer(
+        &xmls.into_inner(),
+        sender,
+        ItemFormat::JacocoXml,
+        parse_errors,
+        per_archive,
+    );
+    profraw_producer(
+        tmp_dir,
+        &profraws.into_inner(),
+        sender,
+        parse_errors,
+        per_archive,
+    );
+    python_coverage_producer(
+        tmp_dir,
+        &python_coverages.into_inner(),
+        sender,
+        parse_errors,
+        per_archive,
+    );
+    // Outputs of gcov are only used when there are no notes files, otherwise they are most likely
+    // leftovers of a previous run of gcov on them and their coverage would be counted twice.
+    let gcovs = gcovs.into_inner();
+    if !gcno_stems_archives.borrow().is_empty() {
+        if !gcovs.is_empty() {
+            warn!(
+                "Ignoring {} gcov output file(s) (e.g. '{}'), as the inputs contain notes files.",
+                gcovs.len(),
+                gcovs.keys().min().unwrap()
+            );
+        }
+    } else {
+        gcov_producer(tmp_dir, &gcovs, sender, parse_errors, per_archive);
+    }
     gcno_gcda_producer(
         tmp_dir,
         &gcno_stems_archives.into_inner(),
         &gcda_stems_archives.into_inner(),
         sender,
-        ignore_orphan_gcno,
+        config.ignore_orphan_gcno,
+        parse_errors,
+        per_archive,
     );
 
-    get_mapping(&linked_files_maps.into_inner())
+    Ok(get_mapping(&linked_files_maps.into_inner()))
 }
 
 #[cfg(test)]
@@ -569,6 +1148,31 @@ mod tests {
     use crossbeam_channel::unbounded;
     use serde_json::{self, Value};
 
+    #[cfg(windows)]
+    #[test]
+    fn test_to_extended_length_path() {
+        assert_eq!(
+            to_extended_length_path(Path::new(r"C:\tmp\grcov/a/b.gcda")),
+            PathBuf::from(r"\\?\C:\tmp\grcov\a\b.gcda")
+        );
+        assert_eq!(
+            to_extended_length_path(Path::new(r"\\server\share\b.gcda")),
+            PathBuf::from(r"\\?\UNC\server\share\b.gcda")
+        );
+        assert_eq!(
+            to_extended_length_path(Path::new(r"C:\tmp\grcov\..\b.gcda")),
+            PathBuf::from(r"\\?\C:\tmp\b.gcda")
+        );
+        assert_eq!(
+            to_extended_length_path(Path::new(r"\\?\C:\b.gcda")),
+            PathBuf::from(r"\\?\C:\b.gcda")
+        );
+        assert_eq!(
+            to_extended_length_path(Path::new("a/b.gcda")),
+            PathBuf::from("a/b.gcda")
+        );
+    }
+
     fn check_produced(
         directory: PathBuf,
         receiver: &JobReceiver,
@@ -654,7 +1258,16 @@ mod tests {
 
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
         let tmp_path = tmp_dir.path().to_owned();
-        let mapping = producer(&tmp_path, &["test".to_string()], &sender, false, false);
+        let mapping = producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -746,6 +1359,7 @@ mod tests {
                 "jacoco/full-junit4-report-multiple-top-level-classes.xml",
                 false,
             ),
+            (ItemFormat::JacocoXml, false, "jacoco/kotlin.xml", false),
             (ItemFormat::Profraw, true, "default_1.profraw", false),
             (
                 ItemFormat::Gcno,
@@ -776,11 +1390,14 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &["test/sub".to_string(), "test/sub2".to_string()],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/sub".to_string(), "test/sub2".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "RootAccessibleWrap_1.gcno", true),
@@ -799,11 +1416,14 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &["test/gcno_symlink/gcda".to_string()],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/gcno_symlink/gcda".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -819,11 +1439,14 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &["test/only_one_gcda".to_string()],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/only_one_gcda".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "main_1.gcno", true),
@@ -842,11 +1465,15 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &["test/only_one_gcda".to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/only_one_gcda".to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -862,14 +1489,17 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &[
-                "test/zip_dir/gcno.zip".to_string(),
-                "test/zip_dir".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/zip_dir/gcno.zip".to_string(),
+                    "test/zip_dir".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -912,15 +1542,18 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &[
-                "test/gcno.zip".to_string(),
-                "test/gcda1.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcno.zip".to_string(),
+                    "test/gcda1.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -972,14 +1605,17 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &[
-                "test/gcno_no_path_mapping.zip".to_string(),
-                "test/gcda1.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcno_no_path_mapping.zip".to_string(),
+                    "test/gcda1.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1013,15 +1649,18 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/gcda1.zip".to_string(),
-                "test/gcno.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcda1.zip".to_string(),
+                    "test/gcno.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1063,14 +1702,17 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/profraw1.zip".to_string(),
-                "test/profraw2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/profraw1.zip".to_string(),
+                    "test/profraw2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Profraw, true, "default_1.profraw", false),
@@ -1089,11 +1731,14 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["test/info1.zip".to_string(), "test/info2.zip".to_string()],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/info1.zip".to_string(), "test/info2.zip".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Info, false, "1494603967-2977-2_0.info", true),
@@ -1113,6 +1758,233 @@ mod tests {
         check_produced(tmp_path, &receiver, expected);
     }
 
+    // Test tagging the items with their archive.
+    #[test]
+    fn test_zip_producer_per_archive() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/info1.zip".to_string(),
+                    "test/info2.zip".to_string(),
+                    "test/gcno.zip".to_string(),
+                    "test/gcda1.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                ],
+                per_archive: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut archives: BTreeMap<(String, Option<String>), usize> = BTreeMap::new();
+        while let Ok(Some(item)) = receiver.try_recv() {
+            *archives
+                .entry((item.format.to_string(), item.archive))
+                .or_default() += 1;
+        }
+        let count = |format: &str, archive: &str| {
+            archives
+                .get(&(format.to_string(), Some(archive.to_string())))
+                .copied()
+        };
+        assert_eq!(count("info", "test/info1.zip"), Some(6));
+        assert_eq!(count("info", "test/info2.zip"), Some(6));
+        // The gcno files are parsed with the gcda files of each archive.
+        assert!(count("gcno", "test/gcda1.zip").is_some());
+        assert!(count("gcno", "test/gcda2.zip").is_some());
+        assert!(!archives.keys().any(|(_, archive)| archive.is_none()));
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        use std::io::Write;
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let zip_path = tmp_dir.path().join("sources.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.add_directory("src/", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file("src/main.rs", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"fn main() {}\n").unwrap();
+        zip.finish().unwrap();
+
+        let dest = tmp_dir.path().join("sources");
+        extract_zip(&zip_path, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("src").join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+
+        assert!(extract_zip(&tmp_dir.path().join("missing.zip"), &dest).is_err());
+    }
+
+    // Test opening the ZIP files contained in a TAR file, along with its other files.
+    #[test]
+    fn test_tar_producer() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        let tar_path = tmp_path.join("shards.tar");
+        let mut builder = tar::Builder::new(File::create(&tar_path).unwrap());
+        builder
+            .append_path_with_name("test/jacoco1.zip", "shard1/jacoco.zip")
+            .unwrap();
+        builder
+            .append_path_with_name("test/jacoco2.zip", "shard2/jacoco.zip")
+            .unwrap();
+        builder
+            .append_path_with_name("test/prova.info", "prova.info")
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![tar_path.to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected = vec![
+            (
+                ItemFormat::JacocoXml,
+                false,
+                "jacoco/basic-jacoco.xml",
+                true,
+            ),
+            (ItemFormat::JacocoXml, false, "inner-classes.xml", true),
+            (ItemFormat::Info, false, "prova.info", true),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo_producer() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        let fifo_path = tmp_path.join("prova.info");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo_path)
+            .status()
+            .expect("Failed to run mkfifo");
+        assert!(status.success());
+
+        let expected_content = fs::read("test/prova.info").unwrap();
+        let writer = {
+            let fifo_path = fifo_path.clone();
+            let content = expected_content.clone();
+            std::thread::spawn(move || fs::write(fifo_path, content).unwrap())
+        };
+
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![fifo_path.to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        writer.join().unwrap();
+
+        let item = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(item.format, ItemFormat::Info);
+        match item.item {
+            ItemType::Content(content) => assert_eq!(content, expected_content),
+            _ => panic!("Unexpected item"),
+        }
+        assert!(receiver.try_recv().is_err());
+
+        // In a directory, FIFOs are routed by extension without being opened.
+        let (sender, receiver) = unbounded();
+        let gcov_path = tmp_path.join("prova.gcov");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&gcov_path)
+            .status()
+            .expect("Failed to run mkfifo");
+        assert!(status.success());
+        fs::remove_file(&fifo_path).unwrap();
+
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![tmp_path.to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        check_produced(
+            tmp_path,
+            &receiver,
+            vec![(ItemFormat::Gcov, true, "prova_1.gcov", false)],
+        );
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_python_coverage_producer() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        let data_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::copy("test/python_coverage.db", data_dir.path().join(".coverage")).unwrap();
+        fs::copy(
+            "test/python_coverage.db",
+            data_dir.path().join(".coverage.host.1234.567890"),
+        )
+        .unwrap();
+        fs::write(
+            data_dir.path().join(".coveragerc"),
+            "[run]\nbranch = True\n",
+        )
+        .unwrap();
+
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![data_dir.path().to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::PythonCoverage, true, ".coverage_1", false),
+            (
+                ItemFormat::PythonCoverage,
+                true,
+                ".coverage.host.1234.567890_1",
+                false,
+            ),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
     // Test extracting jacoco report XML files.
     #[test]
     fn test_zip_producer_jacoco_xml_files() {
@@ -1122,14 +1994,17 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/jacoco1.zip".to_string(),
-                "test/jacoco2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/jacoco1.zip".to_string(),
+                    "test/jacoco2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (
@@ -1153,16 +2028,19 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/jacoco1.zip".to_string(),
-                "test/jacoco2.zip".to_string(),
-                "test/info1.zip".to_string(),
-                "test/info2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/jacoco1.zip".to_string(),
+                    "test/jacoco2.zip".to_string(),
+                    "test/info1.zip".to_string(),
+                    "test/info2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (
@@ -1198,16 +2076,19 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/gcno.zip".to_string(),
-                "test/gcda1.zip".to_string(),
-                "test/info1.zip".to_string(),
-                "test/info2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcno.zip".to_string(),
+                    "test/gcda1.zip".to_string(),
+                    "test/info1.zip".to_string(),
+                    "test/info2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1252,14 +2133,17 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &[
-                "test/no_gcda/main.gcno.zip".to_string(),
-                "test/no_gcda/empty.gcda.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/no_gcda/main.gcno.zip".to_string(),
+                    "test/no_gcda/empty.gcda.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", false)];
 
@@ -1276,15 +2160,18 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         let mapping = producer(
             &tmp_path,
-            &[
-                "test/no_gcda/main.gcno.zip".to_string(),
-                "test/no_gcda/empty.gcda.zip".to_string(),
-                "test/no_gcda/main.gcda.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/no_gcda/main.gcno.zip".to_string(),
+                    "test/no_gcda/empty.gcda.zip".to_string(),
+                    "test/no_gcda/main.gcda.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -1294,19 +2181,22 @@ mod tests {
 
     // Test passing a gcda archive with no gcno archive makes zip_producer fail.
     #[test]
-    #[should_panic]
     fn test_zip_producer_with_gcda_archive_and_no_gcno_archive() {
-        let (sender, _) = unbounded();
+        let (sender, receiver) = unbounded();
 
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
         let tmp_path = tmp_dir.path().to_owned();
-        producer(
+        let res = producer(
             &tmp_path,
-            &["test/no_gcda/main.gcda.zip".to_string()],
             &sender,
-            false,
-            false,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/no_gcda/main.gcda.zip".to_string()],
+                ..Default::default()
+            },
         );
+        assert_eq!(res, Err(NO_INPUT_FILES.to_string()));
+        assert!(receiver.try_recv().is_err());
     }
 
     // Test extracting gcno/gcda archives, where a gcno file exist with no matching gcda file.
@@ -1318,11 +2208,14 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["test/gcno.zip".to_string(), "test/gcda2.zip".to_string()],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/gcno.zip".to_string(), "test/gcda2.zip".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", false),
@@ -1356,15 +2249,18 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/gcno.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-            ],
             &sender,
-            false,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcno.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", false),
@@ -1406,11 +2302,15 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["test/gcno.zip".to_string(), "test/gcda2.zip".to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/gcno.zip".to_string(), "test/gcda2.zip".to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "nsMaiInterfaceValue_1.gcno", true),
@@ -1436,15 +2336,19 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/gcno.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-                "test/gcda2.zip".to_string(),
-            ],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/gcno.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                    "test/gcda2.zip".to_string(),
+                ],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "nsMaiInterfaceValue_1.gcno", true),
@@ -1478,15 +2382,20 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &[
-                "test/llvm/gcno.zip".to_string(),
-                "test/llvm/gcda1.zip".to_string(),
-                "test/llvm/gcda2.zip".to_string(),
-            ],
             &sender,
-            true,
-            true,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/llvm/gcno.zip".to_string(),
+                    "test/llvm/gcda1.zip".to_string(),
+                    "test/llvm/gcda2.zip".to_string(),
+                ],
+                ignore_orphan_gcno: true,
+                is_llvm: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
         let gcno_buf: Vec<u8> = vec![
             111, 110, 99, 103, 42, 50, 48, 52, 74, 200, 254, 66, 0, 0, 0, 1, 9, 0, 0, 0, 0, 0, 0,
             0, 236, 217, 93, 255, 2, 0, 0, 0, 109, 97, 105, 110, 0, 0, 0, 0, 2, 0, 0, 0, 102, 105,
@@ -1536,11 +2445,15 @@ mod tests {
         let json_path = "test/linked-files-map.json";
         let mapping = producer(
             &tmp_path,
-            &["test/prova.info".to_string(), json_path.to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/prova.info".to_string(), json_path.to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert!(mapping.is_some());
         let mapping = mapping.unwrap();
@@ -1558,6 +2471,38 @@ mod tests {
         check_produced(tmp_path, &receiver, expected);
     }
 
+    #[test]
+    fn test_plain_producer_input_format() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        let txt_path = tmp_path.join("coverage.txt");
+        fs::copy("test/prova.info", &txt_path).unwrap();
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![txt_path.to_str().unwrap().to_string()],
+                input_format: Some(ItemFormat::Info),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let item = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(item.format, ItemFormat::Info);
+        assert!(item.name.ends_with("coverage.txt"));
+        match item.item {
+            ItemType::Content(content) => {
+                assert_eq!(content, fs::read("test/prova.info").unwrap())
+            }
+            _ => panic!("Content expected"),
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
     #[test]
     fn test_plain_profraw_producer() {
         let (sender, receiver) = unbounded();
@@ -1566,17 +2511,96 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["test/default.profraw".to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["test/default.profraw".to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Profraw, true, "default.profraw", false)];
 
         check_produced(PathBuf::from("test"), &receiver, expected);
     }
 
+    #[test]
+    fn test_plain_gcov_producer() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![
+                    "test/prova.gcov".to_string(),
+                    "test/mozillavpn_serverconnection.gcno.gcov.json.gz".to_string(),
+                ],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::Gcov, true, "prova.gcov", false),
+            (
+                ItemFormat::Gcov,
+                true,
+                "mozillavpn_serverconnection.gcno.gcov.json.gz",
+                false,
+            ),
+        ];
+
+        check_produced(PathBuf::from("test"), &receiver, expected);
+    }
+
+    #[test]
+    fn test_dir_gcov_producer() {
+        let (sender, receiver) = unbounded();
+
+        let gcov_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir(gcov_dir.path().join("sub")).unwrap();
+        fs::copy("test/prova.gcov", gcov_dir.path().join("prova.gcov")).unwrap();
+        fs::copy(
+            "test/mozillavpn_serverconnection.gcno.gcov.json.gz",
+            gcov_dir
+                .path()
+                .join("sub/mozillavpn_serverconnection.gcno.gcov.json.gz"),
+        )
+        .unwrap();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &sender,
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec![gcov_dir.path().to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::Gcov, true, "prova_1.gcov", false),
+            (
+                ItemFormat::Gcov,
+                true,
+                "sub/mozillavpn_serverconnection.gcno_1.gcov.json.gz",
+                false,
+            ),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
     #[test]
     #[should_panic]
     fn test_plain_producer_with_gcno() {
@@ -1586,11 +2610,15 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["sub2/RootAccessibleWrap_1.gcno".to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["sub2/RootAccessibleWrap_1.gcno".to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
     }
 
     #[test]
@@ -1602,11 +2630,15 @@ mod tests {
         let tmp_path = tmp_dir.path().to_owned();
         producer(
             &tmp_path,
-            &["./test/llvm/file.gcda".to_string()],
             &sender,
-            true,
-            false,
-        );
+            &SyncParseErrors::default(),
+            &GrcovConfig {
+                paths: vec!["./test/llvm/file.gcda".to_string()],
+                ignore_orphan_gcno: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
     }
 
     #[test]
@@ -1662,4 +2694,105 @@ mod tests {
             "Not an info file expected"
         );
     }
+
+    #[test]
+    fn test_manifest() {
+        let content = "# The inputs of the test.
+invalid_DA_record.info:8416B32BA1655123013D062CED23CF26246183D8AF3C7C2E73C346370AC30CA8
+
+./relative_path/relative_path.info:0000000000000000000000000000000000000000000000000000000000000000
+C:\\coverage\\a.info:fc2bc222eb2d861e893434a2c4079cf98d1bd1235bb1bdb304742b835ef4619a
+";
+        let manifest = Manifest::parse(content, false).unwrap();
+        assert_eq!(manifest.checksums.len(), 3);
+        assert_eq!(
+            manifest.checksums["invalid_DA_record.info"],
+            "8416b32ba1655123013d062ced23cf26246183d8af3c7c2e73c346370ac30ca8"
+        );
+        assert!(manifest
+            .checksums
+            .contains_key("relative_path/relative_path.info"));
+        assert!(manifest.checksums.contains_key("C:/coverage/a.info"));
+        assert!(Manifest::parse("a.info", false).is_err());
+        assert!(Manifest::parse("a.info:1234", false).is_err());
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut archive = Archive {
+            name: "test".to_string(),
+            item: RefCell::new(ArchiveType::Dir(env::current_dir().unwrap().join("test"))),
+            manifest: Some(Rc::new(manifest.clone())),
+            copy_extract: false,
+        };
+        assert!(archive.read("invalid_DA_record.info").is_some());
+        assert!(archive.extract("invalid_DA_record.info", &tmp_dir.path().join("a.info")));
+        // The checksum doesn't match.
+        assert!(archive.read("relative_path/relative_path.info").is_none());
+        assert!(!archive.extract(
+            "relative_path/relative_path.info",
+            &tmp_dir.path().join("b.info")
+        ));
+        // The file isn't in the manifest.
+        assert!(archive.read("empty_line.info").is_none());
+
+        archive.manifest = Some(Rc::new(Manifest {
+            keep_going: true,
+            ..manifest
+        }));
+        assert!(archive.read("relative_path/relative_path.info").is_some());
+        assert!(archive.read("empty_line.info").is_some());
+
+        // The plain files, used in place, are checked too.
+        let plain = Archive {
+            name: "plain".to_string(),
+            item: RefCell::new(ArchiveType::Plain(Vec::new())),
+            manifest: Some(Rc::new(
+                Manifest::parse(
+                    "test/invalid_DA_record.info:8416b32ba1655123013d062ced23cf26246183d8af3c7c2e73c346370ac30ca8",
+                    false,
+                )
+                .unwrap(),
+            )),
+            copy_extract: false,
+        };
+        let path = |name: &str| {
+            env::current_dir()
+                .unwrap()
+                .join(name)
+                .to_string_lossy()
+                .into_owned()
+        };
+        assert!(plain.check_plain_file(&path("test/invalid_DA_record.info")));
+        assert!(!plain.check_plain_file(&path("test/empty_line.info")));
+    }
+
+    #[test]
+    fn test_dir_extract() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut archive = Archive {
+            name: "test".to_string(),
+            item: RefCell::new(ArchiveType::Dir(env::current_dir().unwrap().join("test"))),
+            manifest: None,
+            copy_extract: false,
+        };
+        let symlink_path = tmp_dir.path().join("symlink.info");
+        assert!(archive.extract("empty_line.info", &symlink_path));
+        // On Windows, the symlink falls back to a copy without the privilege.
+        #[cfg(unix)]
+        assert!(fs::symlink_metadata(&symlink_path)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        archive.copy_extract = true;
+        let copy_path = tmp_dir.path().join("copy.info");
+        assert!(archive.extract("empty_line.info", &copy_path));
+        assert!(fs::symlink_metadata(&copy_path)
+            .unwrap()
+            .file_type()
+            .is_file());
+        assert_eq!(
+            fs::read(&copy_path).unwrap(),
+            fs::read("test/empty_line.info").unwrap()
+        );
+    }
 }
@@ -0,0 +1,143 @@
+use rusqlite::{Connection, OpenFlags};
+use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::defs::*;
+use crate::parser::ParserError;
+
+/// The versions of the schema of the coverage.py data files which can be read, i.e. the one of
+/// coverage.py 5.0 and later.
+const SUPPORTED_SCHEMA_VERSIONS: &[i64] = &[7];
+
+impl From<rusqlite::Error> for ParserError {
+    fn from(err: rusqlite::Error) -> ParserError {
+        ParserError::Parse(format!("SQLite error: {}", err))
+    }
+}
+
+/// Decodes a "numbits" blob of coverage.py, in which the bit `n % 8` of the byte `n / 8` is set
+/// when the line `n` was executed.
+fn decode_numbits(numbits: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    numbits.iter().enumerate().flat_map(|(byte_no, byte)| {
+        (0..8)
+            .filter(move |bit| byte & (1 << bit) != 0)
+            .map(move |bit| byte_no as u32 * 8 + bit)
+    })
+}
+
+/// Parses a `.coverage` SQLite data file of coverage.py (schema version 7, used since
+/// coverage.py 5.0), merging the lines executed in all the contexts.
+/// coverage.py only records the executed lines (or arcs, with branch coverage), as it finds the
+/// executable ones by analyzing the sources: the lines which were never executed, the functions
+/// and the branches are therefore missing from the results.
+pub fn parse_python_coverage(path: &Path) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let version: i64 =
+        connection.query_row("SELECT version FROM coverage_schema", [], |row| row.get(0))?;
+    if !SUPPORTED_SCHEMA_VERSIONS.contains(&version) {
+        return Err(ParserError::InvalidData(format!(
+            "Unsupported coverage.py schema version {} (supported: {:?})",
+            version, SUPPORTED_SCHEMA_VERSIONS
+        )));
+    }
+
+    let mut files: FxHashMap<i64, (String, BTreeSet<u32>)> = FxHashMap::default();
+    let mut statement = connection.prepare("SELECT id, path FROM file")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        files.insert(row.get(0)?, (row.get(1)?, BTreeSet::new()));
+    }
+
+    let mut statement = connection.prepare("SELECT file_id, numbits FROM line_bits")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let file_id: i64 = row.get(0)?;
+        let numbits: Vec<u8> = row.get(1)?;
+        if let Some((_, lines)) = files.get_mut(&file_id) {
+            lines.extend(decode_numbits(&numbits));
+        }
+    }
+
+    // The negative line numbers of the arcs stand for the entries and exits of code objects.
+    let mut statement = connection.prepare("SELECT file_id, fromno, tono FROM arc")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let file_id: i64 = row.get(0)?;
+        let from: i64 = row.get(1)?;
+        let to: i64 = row.get(2)?;
+        if let Some((_, lines)) = files.get_mut(&file_id) {
+            lines.extend(
+                [from, to]
+                    .iter()
+                    .filter(|&&line| line > 0)
+                    .map(|&line| line as u32),
+            );
+        }
+    }
+
+    let mut results: Vec<(String, CovResult)> = files
+        .into_values()
+        .map(|(path, lines)| {
+            (
+                path,
+                CovResult {
+                    lines: lines.into_iter().map(|line| (line, 1)).collect(),
+                    branches: BTreeMap::new(),
+                    functions: FxHashMap::default(),
+                    line_functions: BTreeMap::new(),
+                    branch_ids: BTreeMap::new(),
+                    line_tests: BTreeMap::new(),
+                    line_archives: BTreeMap::new(),
+                    informational: false,
+                },
+            )
+        })
+        .collect();
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_numbits() {
+        assert_eq!(
+            decode_numbits(&[0x2E, 0x04]).collect::<Vec<_>>(),
+            vec![1, 2, 3, 5, 10]
+        );
+        assert_eq!(decode_numbits(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_parse_python_coverage() {
+        let lines = |results: &[(String, CovResult)], path: &str| -> Vec<(u32, u64)> {
+            let (_, result) = results.iter().find(|(p, _)| p == path).unwrap();
+            result
+                .lines
+                .iter()
+                .map(|(&line, &count)| (line, count))
+                .collect()
+        };
+
+        let results = parse_python_coverage(Path::new("test/python_coverage.db")).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            lines(&results, "/src/app.py"),
+            vec![(1, 1), (2, 1), (3, 1), (5, 1), (10, 1)]
+        );
+        // The lines of all the contexts are merged.
+        assert_eq!(
+            lines(&results, "/src/util.py"),
+            vec![(1, 1), (2, 1), (8, 1)]
+        );
+        assert!(lines(&results, "/src/empty.py").is_empty());
+
+        let results = parse_python_coverage(Path::new("test/python_coverage_arcs.db")).unwrap();
+        assert_eq!(lines(&results, "/src/app.py"), vec![(1, 1), (2, 1), (4, 1)]);
+    }
+}
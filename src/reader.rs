@@ -23,6 +23,7 @@ const GCOV_TAG_LINES: u32 = 0x0145_0000;
 const GCOV_TAG_COUNTER_ARCS: u32 = 0x01a1_0000;
 const GCOV_TAG_OBJECT_SUMMARY: u32 = 0xa100_0000;
 const GCOV_TAG_PROGRAM_SUMMARY: u32 = 0xa300_0000;
+const GCOV_DATA_MAGIC: u32 = 0x6763_6461;
 
 #[derive(Debug)]
 pub enum GcovReaderError {
@@ -366,6 +367,84 @@ impl Debug for Gcno {
     }
 }
 
+/// Returns the version stamp (e.g. `A74*`) of the compiler which produced a gcno or gcda buffer,
+/// as printed by gcov.
+pub fn get_version_stamp(buf: &[u8]) -> Option<String> {
+    let (magic, version) = buf.get(..8)?.split_at(4);
+    let mut version = version.to_vec();
+    match magic {
+        b"oncg" | b"adcg" => version.reverse(),
+        b"gcno" | b"gcda" => {}
+        _ => return None,
+    }
+    Some(String::from_utf8_lossy(&version).into_owned())
+}
+
+/// Checks that the gcno and gcda buffers of `stem` were produced by the same compiler version,
+/// as gcov can't make sense of them otherwise.
+pub fn check_version_stamps(
+    stem: &str,
+    gcno_buf: &[u8],
+    gcda_buf: &[u8],
+) -> Result<(), GcovReaderError> {
+    match (get_version_stamp(gcno_buf), get_version_stamp(gcda_buf)) {
+        (Some(gcno_version), Some(gcda_version)) if gcno_version != gcda_version => {
+            Err(GcovReaderError::Str(format!(
+                "The gcno and gcda files of {} were produced by different compiler versions (gcno version '{}', gcda version '{}')",
+                stem, gcno_version, gcda_version
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx", "h++", "inl", "inc", "tcc"];
+
+fn is_header(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| HEADER_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Moves the functions defined in headers (e.g. inline functions) to the file including them, in
+/// the results of a single gcno file.
+/// The gcno files don't record which file included which header, so the includer is guessed as
+/// the only file of the results which isn't a header: when there are several (e.g. with unified
+/// builds) or none, the results are left untouched. The lines of the headers are kept as they
+/// are, since their numbers are only meaningful in the headers, and for the same reason the start
+/// lines of the moved functions are reset to 0 (i.e. unknown).
+pub fn attribute_headers_to_includer(results: &mut [(String, CovResult)]) {
+    let mut includers = results.iter().filter(|(path, _)| !is_header(path));
+    let includer = match (includers.next(), includers.next()) {
+        (Some((includer, _)), None) => includer.clone(),
+        _ => return,
+    };
+
+    let mut header_functions = Vec::new();
+    for (path, result) in results.iter_mut() {
+        if is_header(path) {
+            header_functions.extend(result.functions.drain());
+        }
+    }
+
+    let (_, includer_result) = results
+        .iter_mut()
+        .find(|(path, _)| *path == includer)
+        .unwrap();
+    for (name, function) in header_functions {
+        match includer_result.functions.entry(name) {
+            hash_map::Entry::Occupied(f) => f.into_mut().executed |= function.executed,
+            hash_map::Entry::Vacant(v) => {
+                v.insert(Function {
+                    start: 0,
+                    executed: function.executed,
+                });
+            }
+        }
+    }
+}
+
 impl Gcno {
     pub fn new() -> Self {
         Gcno {
@@ -438,6 +517,10 @@ impl Gcno {
         gcda_bufs: Vec<Vec<u8>>,
         branch_enabled: bool,
     ) -> Result<Vec<(String, CovResult)>, GcovReaderError> {
+        for gcda_buf in &gcda_bufs {
+            check_version_stamps(stem, &gcno_buf, gcda_buf)?;
+        }
+
         let mut gcno = Self::new();
         gcno.read(FileType::Gcno, gcno_buf, stem)?;
         for gcda_buf in gcda_bufs.into_iter() {
@@ -663,117 +746,131 @@ impl Gcno {
         Ok(())
     }
 
-    pub fn read_gcda<E: Endian, T: GcovReader<E>>(
-        &mut self,
-        mut reader: T,
-    ) -> Result<(), GcovReaderError> {
+    fn read_gcda_header<E: Endian, T: GcovReader<E>>(
+        &self,
+        reader: &mut T,
+    ) -> Result<u32, GcovReaderError> {
         let version = reader.read_version()?;
         if version != self.version {
-            Err(GcovReaderError::Str(format!(
+            return Err(GcovReaderError::Str(format!(
                 "GCOV versions do not match in {}",
                 reader.get_stem()
-            )))
-        } else {
-            let checksum = reader.read_u32()?;
-            if checksum != self.checksum {
-                Err(GcovReaderError::Str(format!(
-                    "File checksums do not match: {} != {} in {}",
-                    self.checksum,
-                    checksum,
-                    reader.get_stem()
-                )))
-            } else {
-                let mut current_fun_id: Option<usize> = None;
-                while let Ok(tag) = reader.read_u32() {
-                    if tag == 0 {
-                        break;
-                    }
-                    let length = reader.read_u32()?;
-                    let mut pos = reader.get_pos();
+            )));
+        }
+        let checksum = reader.read_u32()?;
+        if checksum != self.checksum {
+            return Err(GcovReaderError::Str(format!(
+                "File checksums do not match: {} != {} in {}",
+                self.checksum,
+                checksum,
+                reader.get_stem()
+            )));
+        }
+        Ok(version)
+    }
 
-                    if tag == GCOV_TAG_FUNCTION {
-                        if length == 0 {
-                            continue;
-                        }
+    /// Reads a gcda buffer, summing the counters of all the runs it contains: several gcda files
+    /// (e.g. of several runs of the same binary) can be concatenated in one buffer.
+    pub fn read_gcda<E: Endian, T: GcovReader<E>>(
+        &mut self,
+        mut reader: T,
+    ) -> Result<(), GcovReaderError> {
+        let version = self.read_gcda_header(&mut reader)?;
+        let mut current_fun_id: Option<usize> = None;
+        while let Ok(tag) = reader.read_u32() {
+            // The end of a run, which may be followed by another one.
+            if tag == 0 {
+                continue;
+            }
+            if tag == GCOV_DATA_MAGIC {
+                self.read_gcda_header(&mut reader)?;
+                current_fun_id = None;
+                continue;
+            }
+            let length = reader.read_u32()?;
+            let mut pos = reader.get_pos();
 
-                        if length == 1 {
-                            return Err(GcovReaderError::Str(format!(
-                                "Invalid header length in {}",
-                                reader.get_stem()
-                            )));
-                        }
+            if tag == GCOV_TAG_FUNCTION {
+                if length == 0 {
+                    continue;
+                }
 
-                        let id = reader.read_u32()?;
-                        let line_sum = reader.read_u32()?;
-                        let cfg_sum = if version >= 47 { reader.read_u32()? } else { 0 };
-                        if let Some(fun_id) = self.ident_to_fun.get(&id) {
-                            let fun = &self.functions[*fun_id];
-                            if line_sum != fun.line_checksum || cfg_sum != fun.cfg_checksum {
-                                return Err(GcovReaderError::Str(format!(
-                                    "Checksum mismatch ({}, {}) != ({}, {}) in {}",
-                                    line_sum,
-                                    fun.line_checksum,
-                                    cfg_sum,
-                                    fun.cfg_checksum,
-                                    reader.get_stem()
-                                )));
-                            }
-                            current_fun_id = Some(*fun_id);
-                        } else {
-                            return Err(GcovReaderError::Str(format!(
-                                "Invalid function identifier {} in {}",
-                                id,
-                                reader.get_stem()
-                            )));
-                        }
-                    } else if tag == GCOV_TAG_COUNTER_ARCS {
-                        let fun = if let Some(fun_id) = &current_fun_id {
-                            &mut self.functions[*fun_id]
-                        } else {
-                            continue;
-                        };
-
-                        let count = length;
-                        let edges = &mut fun.edges;
-                        if fun.real_edge_count as u32 != count / 2 {
-                            return Err(GcovReaderError::Str(format!(
-                                "Unexpected number of edges (in {}) in {}",
-                                fun.name,
-                                reader.get_stem()
-                            )));
-                        }
+                if length == 1 {
+                    return Err(GcovReaderError::Str(format!(
+                        "Invalid header length in {}",
+                        reader.get_stem()
+                    )));
+                }
 
-                        for edge in edges.iter_mut() {
-                            if edge.is_on_tree() {
-                                continue;
-                            }
-                            let counter = reader.read_counter()?;
-                            edge.counter += counter;
-                            fun.blocks[edge.source].counter += counter;
-                        }
-                    } else if tag == GCOV_TAG_OBJECT_SUMMARY {
-                        let runcounts = reader.read_u32()?;
-                        reader.skip_u32()?;
-                        self.runcounts += if length == 9 {
-                            reader.read_u32()?
-                        } else {
-                            runcounts
-                        };
-                    } else if tag == GCOV_TAG_PROGRAM_SUMMARY {
-                        if length > 0 {
-                            reader.skip_u32()?;
-                            reader.skip_u32()?;
-                            self.runcounts += reader.read_u32()?;
-                        }
-                        self.programcounts += 1;
+                let id = reader.read_u32()?;
+                let line_sum = reader.read_u32()?;
+                let cfg_sum = if version >= 47 { reader.read_u32()? } else { 0 };
+                if let Some(fun_id) = self.ident_to_fun.get(&id) {
+                    let fun = &self.functions[*fun_id];
+                    if line_sum != fun.line_checksum || cfg_sum != fun.cfg_checksum {
+                        return Err(GcovReaderError::Str(format!(
+                            "Checksum mismatch ({}, {}) != ({}, {}) in {}",
+                            line_sum,
+                            fun.line_checksum,
+                            cfg_sum,
+                            fun.cfg_checksum,
+                            reader.get_stem()
+                        )));
                     }
-                    pos += 4 * (length as usize);
-                    reader.skip(pos - reader.get_pos())?;
+                    current_fun_id = Some(*fun_id);
+                } else {
+                    return Err(GcovReaderError::Str(format!(
+                        "Invalid function identifier {} in {}",
+                        id,
+                        reader.get_stem()
+                    )));
                 }
+            } else if tag == GCOV_TAG_COUNTER_ARCS {
+                let fun = if let Some(fun_id) = &current_fun_id {
+                    &mut self.functions[*fun_id]
+                } else {
+                    continue;
+                };
 
-                Ok(())
+                let count = length;
+                let edges = &mut fun.edges;
+                if fun.real_edge_count as u32 != count / 2 {
+                    return Err(GcovReaderError::Str(format!(
+                        "Unexpected number of edges (in {}) in {}",
+                        fun.name,
+                        reader.get_stem()
+                    )));
+                }
+
+                for edge in edges.iter_mut() {
+                    if edge.is_on_tree() {
+                        continue;
+                    }
+                    let counter = reader.read_counter()?;
+                    edge.counter += counter;
+                    fun.blocks[edge.source].counter += counter;
+                }
+            } else if tag == GCOV_TAG_OBJECT_SUMMARY {
+                let runcounts = reader.read_u32()?;
+                reader.skip_u32()?;
+                self.runcounts += if length == 9 {
+                    reader.read_u32()?
+                } else {
+                    runcounts
+                };
+            } else if tag == GCOV_TAG_PROGRAM_SUMMARY {
+                if length > 0 {
+                    reader.skip_u32()?;
+                    reader.skip_u32()?;
+                    self.runcounts += reader.read_u32()?;
+                }
+                self.programcounts += 1;
             }
+            pos += 4 * (length as usize);
+            reader.skip(pos - reader.get_pos())?;
         }
+
+        Ok(())
     }
 
     fn collect_lines(&self) -> FxHashMap<&str, FxHashMap<u32, u64>> {
@@ -866,6 +963,11 @@ impl Gcno {
                     lines: BTreeMap::new(),
                     branches: BTreeMap::new(),
                     functions: FxHashMap::default(),
+                    line_functions: BTreeMap::new(),
+                    branch_ids: BTreeMap::new(),
+                    line_tests: BTreeMap::new(),
+                    line_archives: BTreeMap::new(),
+                    informational: false,
                 }),
             };
             res.functions.insert(
@@ -985,7 +1087,7 @@ impl GcovFunction {
 
         for e in dsts {
             let w = fun_edges[*e].destination;
-            if w >= start && blocks.iter().any(|x| *x == w) {
+            if w >= start && blocks.contains(&w) {
                 path.push(*e);
                 if w == start {
                     count += GcovFunction::get_cycle_count(fun_edges, path);
@@ -1015,7 +1117,7 @@ impl GcovFunction {
         } else {
             for e in dsts {
                 let w = fun_edges[*e].destination;
-                if w >= start || blocks.iter().any(|x| *x == w) {
+                if w >= start || blocks.contains(&w) {
                     if let Some(i) = blocked.iter().position(|x| *x == w) {
                         let list = &mut block_lists[i];
                         if list.iter().all(|x| *x != v) {
@@ -1074,7 +1176,7 @@ impl GcovFunction {
                 for e in &block.source {
                     let e = &fun_edges[*e];
                     let w = e.source;
-                    if !blocks.iter().any(|x| *x == w) {
+                    if !blocks.contains(&w) {
                         count += e.counter;
                     }
                 }
@@ -1182,7 +1284,7 @@ impl GcovFunction {
         let mut negative_excess = 0;
         let block = &blocks[block_no];
         for edge_id in block.source.iter() {
-            if pred_arc.map_or(true, |x| *edge_id != x) {
+            if pred_arc.is_none_or(|x| *edge_id != x) {
                 let edge = &edges[*edge_id];
                 positive_excess += if edge.is_on_tree() {
                     let source = edge.source;
@@ -1193,7 +1295,7 @@ impl GcovFunction {
             }
         }
         for edge_id in block.destination.iter() {
-            if pred_arc.map_or(true, |x| *edge_id != x) {
+            if pred_arc.is_none_or(|x| *edge_id != x) {
                 let edge = &edges[*edge_id];
                 negative_excess += if edge.is_on_tree() {
                     let destination = edge.destination;
@@ -1203,11 +1305,7 @@ impl GcovFunction {
                 };
             }
         }
-        let excess = if positive_excess >= negative_excess {
-            positive_excess - negative_excess
-        } else {
-            negative_excess - positive_excess
-        };
+        let excess = positive_excess.abs_diff(negative_excess);
         if let Some(id) = pred_arc {
             let edge = &mut edges[id];
             edge.counter = excess;
@@ -1269,6 +1367,25 @@ mod tests {
         assert_eq!(output, input);
     }
 
+    #[test]
+    fn test_version_stamps_mismatch() {
+        let gcno_buf = get_input_vec("test/reader_gcc-6.gcno");
+        let gcda_buf = get_input_vec("test/reader_gcc-7.gcda");
+
+        assert_eq!(get_version_stamp(&gcno_buf).unwrap(), "603*");
+        assert_eq!(get_version_stamp(&gcda_buf).unwrap(), "A74*");
+        assert_eq!(get_version_stamp(b"not a gcda"), None);
+
+        match Gcno::compute("reader", gcno_buf, vec![gcda_buf], false) {
+            Err(GcovReaderError::Str(e)) => {
+                assert!(e.contains("reader"));
+                assert!(e.contains("'603*'"));
+                assert!(e.contains("'A74*'"));
+            }
+            _ => panic!("Version mismatch not detected"),
+        }
+    }
+
     #[test]
     fn test_reader_gcno_gcda_gcc6() {
         let mut gcno = Gcno::new();
@@ -1398,6 +1515,44 @@ mod tests {
         assert_eq!(output, input);
     }
 
+    #[test]
+    fn test_attribute_headers_to_includer() {
+        let result = |functions: &[(&str, bool)]| CovResult {
+            lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+            branches: BTreeMap::new(),
+            functions: functions
+                .iter()
+                .map(|&(name, executed)| (name.to_string(), Function { start: 1, executed }))
+                .collect(),
+            ..Default::default()
+        };
+
+        let mut results = vec![
+            ("foo.h".to_string(), result(&[("inlined", true)])),
+            ("foo.cpp".to_string(), result(&[("main", true)])),
+            ("bar.HPP".to_string(), result(&[("unused", false)])),
+        ];
+        attribute_headers_to_includer(&mut results);
+        assert!(results[0].1.functions.is_empty());
+        assert_eq!(results[0].1.lines, result(&[]).lines);
+        assert!(results[2].1.functions.is_empty());
+        let mut names: Vec<_> = results[1].1.functions.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["inlined", "main", "unused"]);
+        assert!(!results[1].1.functions["unused"].executed);
+        assert_eq!(results[1].1.functions["inlined"].start, 0);
+        assert_eq!(results[1].1.functions["main"].start, 1);
+
+        // With several includers, there's no way to tell which one included the header.
+        let mut results = vec![
+            ("foo.h".to_string(), result(&[("inlined", true)])),
+            ("foo.cpp".to_string(), result(&[("main", true)])),
+            ("bar.cpp".to_string(), result(&[("bar", true)])),
+        ];
+        attribute_headers_to_includer(&mut results);
+        assert_eq!(results[0].1.functions.len(), 1);
+    }
+
     #[test]
     fn test_reader_finalize_file() {
         let mut gcno = Gcno::new();
@@ -1423,12 +1578,29 @@ mod tests {
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_reader_gcda_with_two_runs() {
+        let mut gcno = Gcno::new();
+        from_path(&mut gcno, FileType::Gcno, "test/llvm/file.gcno");
+        from_path(&mut gcno, FileType::Gcda, "test/llvm/file_two_runs.gcda");
+        gcno.stop();
+        let result = gcno.finalize(true);
+
+        assert_eq!(result.len(), 1);
+        let (name, result) = &result[0];
+        assert_eq!(name, "file.c");
+        assert_eq!(result.lines, [(2, 2)].iter().cloned().collect());
+        assert!(result.functions["main"].executed);
+        assert_eq!(gcno.programcounts, 2);
+    }
+
     #[test]
     fn test_reader_finalize_file_branch() {
         let mut gcno = Gcno::new();
@@ -1510,6 +1682,7 @@ mod tests {
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 